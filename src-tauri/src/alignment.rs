@@ -0,0 +1,518 @@
+// ページ画像の位置合わせ。
+// 写植から戻ってきた新しい版のページは、前の版に対してずれたり、わずかに拡大・回転していることがある。
+// まず平行移動を画像全体で探し、両方の画像から取り出した角 (文字や枠線の角) を予想される位置の近くの
+// 似た点と対応付けて、RANSAC で誤った対応を除いて相似変換 (拡大・回転・平行移動) を求める。
+// 求めた変換で予想を絞って対応付けし直すので、同じ形の文字が並ぶページでも取り違えにくい。
+// 特徴点が足りない (白いページなど) ときは平行移動だけで合わせる。
+
+use ::image::{imageops, imageops::FilterType, DynamicImage, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// 解析する画像の長辺 (px)。これより大きいページは縮小して調べる
+const ANALYSIS_MAX_SIDE: u32 = 1600;
+/// 縮小後にかけるぼかし (JPEG のブロックノイズを消す程度)
+const BLUR_SIGMA: f32 = 0.8;
+/// 平行移動で探すずれの最大 (解析画像の長辺に対する割合)
+const MAX_SHIFT_RATIO: f64 = 0.06;
+/// 特徴点の対応を探す範囲 (予想される位置からの距離、解析画像の px)。
+/// 1 回目は平行移動だけの予想から回転・拡大のぶんを見込み、2 回目は求めた変換の予想の近くだけを探す
+const MATCH_WINDOWS: [f64; 2] = [24.0, 4.0];
+/// 平行移動の粗い探索の縮小率
+const COARSE_FACTOR: u32 = 4;
+/// 特徴点を 1 つずつ選ぶ格子の大きさ (解析画像の px)。ページ全体に散らばるようにする
+const FEATURE_CELL: usize = 24;
+/// 角らしさ (Harris の応答) の下限 (ページ内の最大値に対する割合)
+const MIN_CORNER_RESPONSE: f32 = 0.01;
+/// 特徴量にするパッチの半径と標本の間隔 (解析画像の px)
+const PATCH_RADIUS: i32 = 8;
+const PATCH_STEP: i32 = 2;
+/// 対応とみなす正規化相関の下限と、2 番目の候補との差の下限 (繰り返し模様の取り違えを避ける)
+const MIN_MATCH_SCORE: f32 = 0.8;
+const MIN_MATCH_MARGIN: f32 = 0.05;
+const RANSAC_ITERATIONS: usize = 1000;
+/// 変換に合っているとみなす誤差 (解析画像の px)
+const INLIER_DISTANCE: f64 = 2.5;
+/// 特徴点で合わせるのに必要な、変換に合った対応の数
+const MIN_INLIERS: usize = 10;
+/// ありうる拡大率の変化と回転 (これを超える推定は誤った対応とみなす)
+const MAX_SCALE_CHANGE: f64 = 0.25;
+const MAX_ROTATION: f64 = 10.0 * std::f64::consts::PI / 180.0;
+
+/// 相似変換。前の版 (基準) の点 p を新しい版の scale・R(rotation)・p + (tx, ty) に写す。
+/// rotation はラジアンで、画像の座標 (y が下向き) で時計回りが正。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Similarity {
+    pub scale: f64,
+    pub rotation: f64,
+    pub tx: f64,
+    pub ty: f64,
+}
+
+impl Similarity {
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (c, s) = self.linear();
+        (c * x - s * y + self.tx, s * x + c * y + self.ty)
+    }
+
+    /// 基準の画像を reference_scale 倍、新しい版を moving_scale 倍したときの、その画像どうしの変換。
+    pub fn rescaled(&self, reference_scale: f64, moving_scale: f64) -> Similarity {
+        Similarity {
+            scale: self.scale * moving_scale / reference_scale,
+            rotation: self.rotation,
+            tx: self.tx * moving_scale,
+            ty: self.ty * moving_scale,
+        }
+    }
+
+    /// (scale・cos, scale・sin)。複素数で見たときの a (p' = a・p + t)。
+    fn linear(&self) -> (f64, f64) {
+        (self.scale * self.rotation.cos(), self.scale * self.rotation.sin())
+    }
+
+    fn from_linear((c, s): (f64, f64), (tx, ty): (f64, f64)) -> Similarity {
+        Similarity { scale: c.hypot(s), rotation: s.atan2(c), tx, ty }
+    }
+
+    fn is_plausible(&self) -> bool {
+        self.scale.is_finite() && (self.scale - 1.0).abs() <= MAX_SCALE_CHANGE && self.rotation.abs() <= MAX_ROTATION
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlignMethod {
+    /// 特徴点の対応から相似変換を求めた
+    Features,
+    /// 特徴点が足りず、平行移動 (と解像度の違い) だけを合わせた
+    Translation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alignment {
+    /// 前の版の画像の px から新しい版の画像の px への変換
+    pub transform: Similarity,
+    pub method: AlignMethod,
+    /// 対応付けた特徴点の数と、そのうち変換に合ったものの数
+    pub matches: usize,
+    pub inliers: usize,
+    /// 変換に合った対応の誤差の二乗平均平方根 (前の版の画像の px、平行移動だけのときは 0)
+    pub residual: f64,
+}
+
+/// 解析用に縮小してぼかしたグレースケール画像。
+pub struct AnalysisImage {
+    pub gray: GrayImage,
+    /// 元の画像からの縮小率
+    pub scale: f64,
+}
+
+pub fn analysis_image(image: &DynamicImage) -> AnalysisImage {
+    let (width, height) = image.dimensions();
+    let scale = (ANALYSIS_MAX_SIDE as f64 / width.max(height) as f64).min(1.0);
+    let (w, h) = (((width as f64 * scale).round() as u32).max(1), ((height as f64 * scale).round() as u32).max(1));
+    let gray = imageops::blur(&imageops::resize(&image.to_luma8(), w, h, FilterType::Triangle), BLUR_SIGMA);
+    AnalysisImage { gray, scale }
+}
+
+/// 前の版 (基準) に新しい版を合わせる変換を求める。
+pub fn align(reference: &DynamicImage, moving: &DynamicImage) -> Alignment {
+    align_analysis(&analysis_image(reference), &analysis_image(moving))
+}
+
+/// 解析用の画像どうしで位置合わせする。変換は元の画像の px で返す。
+pub fn align_analysis(reference: &AnalysisImage, moving: &AnalysisImage) -> Alignment {
+    let (w, h) = reference.gray.dimensions();
+    let max_shift = w.max(h) as f64 * MAX_SHIFT_RATIO;
+    let to_original = |transform: Similarity| transform.rescaled(1.0 / reference.scale, 1.0 / moving.scale);
+
+    let translation = align_translation(&reference.gray, &moving.gray, max_shift.round() as i32);
+
+    let reference_features = detect_features(&reference.gray);
+    let moving_features = detect_features(&moving.gray);
+    let mut predict = translation;
+    let mut estimated: Option<Alignment> = None;
+    let mut match_count = 0;
+    for window in MATCH_WINDOWS {
+        let matches = match_features(&reference_features, &moving_features, &predict, window);
+        match_count = matches.len();
+        let Some((transform, inliers)) = estimate_similarity(&matches) else {
+            // 対応が足りなければそれまでの結果 (なければ平行移動) を使う
+            break;
+        };
+        let squared_error: f64 = inliers
+            .iter()
+            .map(|&i| {
+                let ((ox, oy), (nx, ny)) = matches[i];
+                let (px, py) = transform.apply(ox, oy);
+                (px - nx).powi(2) + (py - ny).powi(2)
+            })
+            .sum();
+        predict = transform;
+        estimated = Some(Alignment {
+            transform: to_original(transform),
+            method: AlignMethod::Features,
+            matches: matches.len(),
+            inliers: inliers.len(),
+            residual: (squared_error / inliers.len() as f64).sqrt() / reference.scale,
+        });
+    }
+    if let Some(alignment) = estimated {
+        return alignment;
+    }
+
+    eprintln!("[MojiQ] 特徴点が足りないため平行移動だけで位置合わせします ({} 組の対応)", match_count);
+    Alignment {
+        transform: to_original(translation),
+        method: AlignMethod::Translation,
+        matches: match_count,
+        inliers: 0,
+        residual: 0.0,
+    }
+}
+
+/// 新しい版を前の版の位置に合わせた画像 (width × height、前の版の画像の px)。はみ出した所は紙の白にする。
+pub fn warp(moving: &DynamicImage, transform: &Similarity, width: u32, height: u32) -> RgbaImage {
+    let source = moving.to_rgba8();
+    let mut warped = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    for (x, y, pixel) in warped.enumerate_pixels_mut() {
+        let (sx, sy) = transform.apply(x as f64, y as f64);
+        if let Some(sample) = sample_bilinear(&source, sx, sy) {
+            *pixel = sample;
+        }
+    }
+    warped
+}
+
+/// グレースケールの `warp`。前の版の各画素が新しい版の範囲内か (比べられるか) も返す。
+pub fn warp_gray(moving: &GrayImage, transform: &Similarity, width: u32, height: u32) -> (GrayImage, Vec<bool>) {
+    let mut warped = GrayImage::from_pixel(width, height, Luma([255]));
+    let mut valid = vec![false; (width * height) as usize];
+    for (x, y, pixel) in warped.enumerate_pixels_mut() {
+        let (sx, sy) = transform.apply(x as f64, y as f64);
+        if let Some(sample) = sample_bilinear(moving, sx, sy) {
+            *pixel = sample;
+            valid[(y * width + x) as usize] = true;
+        }
+    }
+    (warped, valid)
+}
+
+/// (x, y) の画素を周りの 4 画素から補間する。画像の外なら None。
+fn sample_bilinear<P>(image: &::image::ImageBuffer<P, Vec<u8>>, x: f64, y: f64) -> Option<P>
+where
+    P: ::image::Pixel<Subpixel = u8>,
+{
+    let (w, h) = image.dimensions();
+    if x < 0.0 || y < 0.0 || x > (w - 1) as f64 || y > (h - 1) as f64 {
+        return None;
+    }
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let corners = [
+        (image.get_pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (image.get_pixel(x1, y0), fx * (1.0 - fy)),
+        (image.get_pixel(x0, y1), (1.0 - fx) * fy),
+        (image.get_pixel(x1, y1), fx * fy),
+    ];
+    let mut sample = *corners[0].0;
+    for (channel, out) in sample.channels_mut().iter_mut().enumerate() {
+        let value: f64 = corners.iter().map(|(pixel, weight)| pixel.channels()[channel] as f64 * weight).sum();
+        *out = value.round().clamp(0.0, 255.0) as u8;
+    }
+    Some(sample)
+}
+
+type Point = (f64, f64);
+
+struct Feature {
+    x: f64,
+    y: f64,
+    /// 平均を引いて長さ 1 にしたパッチ (内積が正規化相関になる)
+    descriptor: Vec<f32>,
+}
+
+/// Harris の角を格子ごとに 1 つずつ選び、周りのパッチを特徴量にする。
+fn detect_features(image: &GrayImage) -> Vec<Feature> {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let margin = (PATCH_RADIUS + 1) as usize;
+    if w <= margin * 2 || h <= margin * 2 {
+        return Vec::new();
+    }
+    let raw = image.as_raw();
+    let at = |x: usize, y: usize| raw[y * w + x] as f32;
+
+    let mut xx = vec![0f32; w * h];
+    let mut yy = vec![0f32; w * h];
+    let mut xy = vec![0f32; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let gx = (at(x + 1, y) - at(x - 1, y)) / 2.0;
+            let gy = (at(x, y + 1) - at(x, y - 1)) / 2.0;
+            let i = y * w + x;
+            xx[i] = gx * gx;
+            yy[i] = gy * gy;
+            xy[i] = gx * gy;
+        }
+    }
+    let (xx, yy, xy) = (box_sum(&xx, w, h, 2), box_sum(&yy, w, h, 2), box_sum(&xy, w, h, 2));
+    let response: Vec<f32> = (0..w * h)
+        .map(|i| xx[i] * yy[i] - xy[i] * xy[i] - 0.04 * (xx[i] + yy[i]).powi(2))
+        .collect();
+    let threshold = response.iter().copied().fold(0.0, f32::max) * MIN_CORNER_RESPONSE;
+    if threshold <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut features = Vec::new();
+    for cell_y in (margin..h - margin).step_by(FEATURE_CELL) {
+        for cell_x in (margin..w - margin).step_by(FEATURE_CELL) {
+            let mut best: Option<(usize, usize, f32)> = None;
+            for y in cell_y..(cell_y + FEATURE_CELL).min(h - margin) {
+                for x in cell_x..(cell_x + FEATURE_CELL).min(w - margin) {
+                    let r = response[y * w + x];
+                    if r > threshold && best.is_none_or(|(_, _, b)| r > b) {
+                        best = Some((x, y, r));
+                    }
+                }
+            }
+            let Some((x, y, _)) = best else {
+                continue;
+            };
+            if let Some(descriptor) = describe(image, x as i32, y as i32) {
+                features.push(Feature { x: x as f64, y: y as f64, descriptor });
+            }
+        }
+    }
+    features
+}
+
+/// 半径 radius の正方形の中の和。
+fn box_sum(values: &[f32], w: usize, h: usize, radius: usize) -> Vec<f32> {
+    let mut horizontal = vec![0f32; w * h];
+    for y in 0..h {
+        let row = &values[y * w..(y + 1) * w];
+        for x in 0..w {
+            horizontal[y * w + x] = row[x.saturating_sub(radius)..(x + radius + 1).min(w)].iter().sum();
+        }
+    }
+    let mut out = vec![0f32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            out[y * w + x] = (y.saturating_sub(radius)..(y + radius + 1).min(h)).map(|yy| horizontal[yy * w + x]).sum();
+        }
+    }
+    out
+}
+
+/// (x, y) の周りのパッチを正規化した特徴量。のっぺりした所 (網点をぼかした面など) は None。
+fn describe(image: &GrayImage, x: i32, y: i32) -> Option<Vec<f32>> {
+    let mut patch = Vec::new();
+    for dy in (-PATCH_RADIUS..=PATCH_RADIUS).step_by(PATCH_STEP as usize) {
+        for dx in (-PATCH_RADIUS..=PATCH_RADIUS).step_by(PATCH_STEP as usize) {
+            patch.push(image.get_pixel((x + dx) as u32, (y + dy) as u32).0[0] as f32);
+        }
+    }
+    let mean = patch.iter().sum::<f32>() / patch.len() as f32;
+    patch.iter_mut().for_each(|v| *v -= mean);
+    let norm = patch.iter().map(|v| v * v).sum::<f32>().sqrt();
+    // 明るさの標準偏差が 4 未満なら特徴にならない
+    if norm < 4.0 * (patch.len() as f32).sqrt() {
+        return None;
+    }
+    patch.iter_mut().for_each(|v| *v /= norm);
+    Some(patch)
+}
+
+/// 前の版の特徴点ごとに、予想される位置から max_distance 以内で一番似た新しい版の特徴点を対応とする。
+fn match_features(reference: &[Feature], moving: &[Feature], predict: &Similarity, max_distance: f64) -> Vec<(Point, Point)> {
+    // 新しい版の特徴点を max_distance の格子に分けて、近くのものだけを調べる
+    let cell = max_distance.max(1.0);
+    let key = |x: f64, y: f64| ((x / cell) as i64, (y / cell) as i64);
+    let mut grid: std::collections::HashMap<(i64, i64), Vec<&Feature>> = std::collections::HashMap::new();
+    for feature in moving {
+        grid.entry(key(feature.x, feature.y)).or_default().push(feature);
+    }
+
+    let mut matches = Vec::new();
+    for feature in reference {
+        let (px, py) = predict.apply(feature.x, feature.y);
+        let (cx, cy) = key(px, py);
+        let mut best: Option<(&Feature, f32)> = None;
+        let mut second = f32::MIN;
+        for gy in cy - 1..=cy + 1 {
+            for gx in cx - 1..=cx + 1 {
+                for candidate in grid.get(&(gx, gy)).into_iter().flatten() {
+                    if (candidate.x - px).hypot(candidate.y - py) > max_distance {
+                        continue;
+                    }
+                    let score: f32 = feature.descriptor.iter().zip(&candidate.descriptor).map(|(a, b)| a * b).sum();
+                    match best {
+                        Some((_, b)) if score <= b => second = second.max(score),
+                        _ => {
+                            second = second.max(best.map_or(f32::MIN, |(_, b)| b));
+                            best = Some((candidate, score));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((candidate, score)) = best {
+            if score >= MIN_MATCH_SCORE && score - second >= MIN_MATCH_MARGIN {
+                matches.push(((feature.x, feature.y), (candidate.x, candidate.y)));
+            }
+        }
+    }
+    matches
+}
+
+/// RANSAC で誤った対応を除き、残った対応に最小二乗で合わせた相似変換と、合った対応の番号。
+fn estimate_similarity(matches: &[(Point, Point)]) -> Option<(Similarity, Vec<usize>)> {
+    if matches.len() < MIN_INLIERS {
+        return None;
+    }
+    let inliers_of = |transform: &Similarity| -> Vec<usize> {
+        (0..matches.len())
+            .filter(|&i| {
+                let ((ox, oy), (nx, ny)) = matches[i];
+                let (px, py) = transform.apply(ox, oy);
+                (px - nx).hypot(py - ny) <= INLIER_DISTANCE
+            })
+            .collect()
+    };
+
+    // 結果が毎回同じになるよう乱数の種は固定する
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = |n: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as usize
+    };
+    let mut best: Vec<usize> = Vec::new();
+    for _ in 0..RANSAC_ITERATIONS {
+        let (i, j) = (random(matches.len()), random(matches.len()));
+        if i == j {
+            continue;
+        }
+        let Some(transform) = fit_similarity(&[matches[i], matches[j]]) else {
+            continue;
+        };
+        if !transform.is_plausible() {
+            continue;
+        }
+        let inliers = inliers_of(&transform);
+        if inliers.len() > best.len() {
+            best = inliers;
+        }
+    }
+    if best.len() < MIN_INLIERS {
+        return None;
+    }
+
+    // 合った対応すべてで合わせ直し、合う対応を数え直す
+    let mut transform = fit_similarity(&best.iter().map(|&i| matches[i]).collect::<Vec<_>>())?;
+    for _ in 0..2 {
+        let inliers = inliers_of(&transform);
+        if inliers.len() < MIN_INLIERS {
+            break;
+        }
+        transform = fit_similarity(&inliers.iter().map(|&i| matches[i]).collect::<Vec<_>>())?;
+        best = inliers;
+    }
+    (transform.is_plausible() && best.len() >= MIN_INLIERS).then_some((transform, best))
+}
+
+/// 対応に最小二乗で合う相似変換 (複素数で p' = a・p + t と見て a と t を求める)。
+fn fit_similarity(pairs: &[(Point, Point)]) -> Option<Similarity> {
+    let n = pairs.len() as f64;
+    let (ox, oy) = pairs.iter().fold((0.0, 0.0), |(x, y), ((px, py), _)| (x + px / n, y + py / n));
+    let (nx, ny) = pairs.iter().fold((0.0, 0.0), |(x, y), (_, (px, py))| (x + px / n, y + py / n));
+    let (mut re, mut im, mut norm) = (0.0, 0.0, 0.0);
+    for ((px, py), (qx, qy)) in pairs {
+        let (ax, ay) = (px - ox, py - oy);
+        let (bx, by) = (qx - nx, qy - ny);
+        // (b) × conj(a)
+        re += bx * ax + by * ay;
+        im += by * ax - bx * ay;
+        norm += ax * ax + ay * ay;
+    }
+    if norm < 1e-9 {
+        return None;
+    }
+    let (c, s) = (re / norm, im / norm);
+    Some(Similarity::from_linear((c, s), (nx - (c * ox - s * oy), ny - (s * ox + c * oy))))
+}
+
+/// 平行移動だけを探す。新しい版の解像度が違えば前の版の大きさに縮めてから比べる。
+fn align_translation(reference: &GrayImage, moving: &GrayImage, max_shift: i32) -> Similarity {
+    let (w, h) = reference.dimensions();
+    let scale = (moving.width() as f64 / w as f64 + moving.height() as f64 / h as f64) / 2.0;
+    let resized;
+    let moving = if moving.dimensions() == (w, h) {
+        moving
+    } else {
+        resized = imageops::resize(moving, w, h, FilterType::Triangle);
+        &resized
+    };
+    let (dx, dy) = estimate_offset(reference, moving, max_shift.max(1));
+    Similarity { scale, rotation: 0.0, tx: dx as f64 * scale, ty: dy as f64 * scale }
+}
+
+/// 新しい版のずれ (dx, dy) を求める。縮小した画像で大まかに探してから元の大きさで詰める。
+fn estimate_offset(a: &GrayImage, b: &GrayImage, max_shift: i32) -> (i32, i32) {
+    let (w, h) = a.dimensions();
+    let coarse = |image: &GrayImage| {
+        imageops::resize(image, (w / COARSE_FACTOR).max(1), (h / COARSE_FACTOR).max(1), FilterType::Triangle)
+    };
+    let range = (max_shift / COARSE_FACTOR as i32).max(1);
+    let (cx, cy) = best_offset(&coarse(a), &coarse(b), -range..=range, -range..=range, 1);
+
+    let factor = COARSE_FACTOR as i32;
+    let (cx, cy) = (cx * factor, cy * factor);
+    best_offset(a, b, cx - factor..=cx + factor, cy - factor..=cy + factor, 2)
+}
+
+/// 範囲の中で差の一番小さいずれ。同じならずれの小さい方 (真っ白なページなどで動かさない)。
+fn best_offset(
+    a: &GrayImage,
+    b: &GrayImage,
+    xs: RangeInclusive<i32>,
+    ys: RangeInclusive<i32>,
+    stride: usize,
+) -> (i32, i32) {
+    let mut best = (0, 0);
+    let mut best_score = (f64::MAX, i32::MAX);
+    for dy in ys {
+        for dx in xs.clone() {
+            let score = (mean_abs_diff(a, b, dx, dy, stride), dx.abs() + dy.abs());
+            if score < best_score {
+                best_score = score;
+                best = (dx, dy);
+            }
+        }
+    }
+    best
+}
+
+/// a(x, y) と b(x + dx, y + dy) の差の平均。重なる範囲を stride おきに比べる。
+pub fn mean_abs_diff(a: &GrayImage, b: &GrayImage, dx: i32, dy: i32, stride: usize) -> f64 {
+    let (w, h) = (a.width() as i32, a.height() as i32);
+    let (x0, x1) = (0.max(-dx), w.min(w - dx));
+    let (y0, y1) = (0.max(-dy), h.min(h - dy));
+    if (x1 - x0) * 2 < w || (y1 - y0) * 2 < h {
+        return f64::MAX;
+    }
+    let (a, b) = (a.as_raw(), b.as_raw());
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    for y in (y0..y1).step_by(stride) {
+        let (row_a, row_b) = ((y * w) as usize, ((y + dy) * w) as usize);
+        for x in (x0..x1).step_by(stride) {
+            sum += a[row_a + x as usize].abs_diff(b[row_b + (x + dx) as usize]) as u64;
+            count += 1;
+        }
+    }
+    sum as f64 / count.max(1) as f64
+}
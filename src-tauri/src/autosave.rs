@@ -0,0 +1,234 @@
+// クラッシュ復旧用の自動保存ジャーナル: フロントエンドから定期的に送られる描画データ (MojiQExportData の JSON) を
+// アプリデータフォルダの `autosave` に元ファイルのパスとハッシュごとに書き出す。
+// 正常に保存・破棄されたジャーナルは削除され、起動時に残っているものを復旧候補として通知する。
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+/// アプリデータフォルダ内のジャーナルの置き場所。
+const AUTOSAVE_DIR_NAME: &str = "autosave";
+
+/// ジャーナルファイルの形式のバージョン。
+const JOURNAL_VERSION: u32 = 1;
+
+/// 起動時に復旧できるセッションを通知するイベント名 (`file-open-request` の後に送る)。
+pub const RECOVERY_EVENT: &str = "autosave-recovery-available";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    version: u32,
+    source_path: String,
+    /// 自動保存した時点の元ファイルのハッシュ (FNV-1a 64bit, 16 進)
+    source_hash: String,
+    /// 自動保存した日時 (UNIX エポックからのミリ秒)
+    saved_at: u64,
+    /// MojiQExportData の JSON
+    data: String,
+}
+
+/// 復旧できる自動保存セッション。
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoverableSession {
+    pub journal_id: String,
+    pub source_path: String,
+    pub saved_at: u64,
+    /// 元ファイルが残っているか
+    pub source_exists: bool,
+    /// 元ファイルが自動保存の後に変更されたか (座標がずれている可能性がある)
+    pub source_changed: bool,
+}
+
+/// 元ファイルごとの (サイズ, 更新日時, ハッシュ)。
+type SourceHashCache = HashMap<PathBuf, (u64, SystemTime, String)>;
+
+/// 元ファイルのハッシュのキャッシュ。自動保存のたびに大きな PDF を読み直さないよう、
+/// サイズと更新日時が変わらない間は前回の値を使う。
+#[derive(Default, Clone)]
+pub struct AutosaveState {
+    source_hashes: Arc<Mutex<SourceHashCache>>,
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a 64bit。ジャーナル名に使うのでバージョン間で値が変わらないハッシュにする。
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut hash = FNV_OFFSET_BASIS;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hash = fnv1a(hash, &buffer[..read]);
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+impl AutosaveState {
+    fn source_hash(&self, path: &Path) -> std::io::Result<String> {
+        let metadata = std::fs::metadata(path)?;
+        let (len, modified) = (metadata.len(), metadata.modified()?);
+        if let Some((cached_len, cached_modified, hash)) = self.source_hashes.lock().unwrap().get(path) {
+            if *cached_len == len && *cached_modified == modified {
+                return Ok(hash.clone());
+            }
+        }
+        let hash = hash_file(path)?;
+        self.source_hashes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (len, modified, hash.clone()));
+        Ok(hash)
+    }
+}
+
+/// ジャーナルのフォルダ (`<アプリデータ>/autosave`)。
+pub fn journal_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(AUTOSAVE_DIR_NAME))
+        .map_err(|e| format!("アプリデータフォルダを取得できません: {}", e))
+}
+
+/// ジャーナル ID は "<パスのハッシュ>-<元ファイルのハッシュ>"。同じファイルの同じ版は 1 つのジャーナルを上書きする。
+fn journal_id(source_path: &str, source_hash: &str) -> String {
+    format!("{:016x}-{}", fnv1a(FNV_OFFSET_BASIS, source_path.as_bytes()), source_hash)
+}
+
+/// フロントエンドから受け取った ID をパスにする。フォルダの外を指さないよう文字を制限する。
+fn journal_path(dir: &Path, journal_id: &str) -> Result<PathBuf, String> {
+    let valid = !journal_id.is_empty() && journal_id.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    if !valid {
+        return Err(format!("Invalid journal id: {}", journal_id));
+    }
+    Ok(dir.join(format!("{}.json", journal_id)))
+}
+
+fn read_journal(path: &Path) -> Result<Journal, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("ジャーナルを読み込めません: {}", e))?;
+    let journal: Journal = serde_json::from_str(&text).map_err(|e| format!("ジャーナルが壊れています: {}", e))?;
+    if journal.version > JOURNAL_VERSION {
+        return Err(format!("未対応のジャーナル形式です (version {})", journal.version));
+    }
+    Ok(journal)
+}
+
+/// 描画データをジャーナルに書き出し、ジャーナル ID を返す。
+/// 一時ファイルに書いて fsync してから置き換えるので、書き込み中に落ちても前回のジャーナルは壊れない。
+pub fn write_journal(dir: &Path, state: &AutosaveState, source_path: &str, data: String) -> Result<String, String> {
+    let source_hash = state
+        .source_hash(Path::new(source_path))
+        .map_err(|e| format!("元ファイルを読み込めません: {}", e))?;
+    let id = journal_id(source_path, &source_hash);
+    let path = journal_path(dir, &id)?;
+
+    let journal = Journal {
+        version: JOURNAL_VERSION,
+        source_path: source_path.to_string(),
+        source_hash,
+        saved_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        data,
+    };
+    let json = serde_json::to_vec(&journal).map_err(|e| e.to_string())?;
+
+    let write = || -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let temp_path = path.with_extension("json.tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, &path)?;
+        crate::backup::sync_dir(dir)
+    };
+    write().map_err(|e| format!("自動保存に失敗しました: {}", e))?;
+    Ok(id)
+}
+
+/// 残っているジャーナルを新しい順に列挙する。
+pub fn list_sessions(dir: &Path) -> Vec<RecoverableSession> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut sessions: Vec<RecoverableSession> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                return None;
+            }
+            let journal_id = path.file_stem()?.to_str()?.to_string();
+            let journal = match read_journal(&path) {
+                Ok(journal) => journal,
+                Err(e) => {
+                    eprintln!("[MojiQ] 自動保存ジャーナルを読み飛ばします: {}: {}", path.display(), e);
+                    return None;
+                }
+            };
+            let source = Path::new(&journal.source_path);
+            let source_exists = source.is_file();
+            let source_changed = source_exists && hash_file(source).ok().as_deref() != Some(journal.source_hash.as_str());
+            Some(RecoverableSession {
+                journal_id,
+                source_path: journal.source_path,
+                saved_at: journal.saved_at,
+                source_exists,
+                source_changed,
+            })
+        })
+        .collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.saved_at));
+    sessions
+}
+
+/// ジャーナルの描画データ (MojiQExportData の JSON) を返す。
+pub fn load_session(dir: &Path, journal_id: &str) -> Result<String, String> {
+    read_journal(&journal_path(dir, journal_id)?).map(|journal| journal.data)
+}
+
+/// ジャーナルを削除する。
+pub fn discard_session(dir: &Path, journal_id: &str) -> Result<(), String> {
+    match std::fs::remove_file(journal_path(dir, journal_id)?) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("ジャーナルを削除できません: {}", e)),
+    }
+}
+
+/// `source_path` のジャーナルを (元ファイルの版に関係なく) すべて削除する。保存や破棄の後に呼ぶ。
+pub fn clear_sessions_for(dir: &Path, source_path: &str) -> Result<(), String> {
+    let prefix = format!("{:016x}-", fnv1a(FNV_OFFSET_BASIS, source_path.as_bytes()));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(&prefix) || !name.ends_with(".json") {
+            continue;
+        }
+        // パスのハッシュが偶然一致した別ファイルのジャーナルは残す
+        if read_journal(&entry.path()).is_ok_and(|journal| journal.source_path != source_path) {
+            continue;
+        }
+        std::fs::remove_file(entry.path()).map_err(|e| format!("ジャーナルを削除できません: {}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+// 保存時の世代バックアップ: 上書きする前の PDF を保存先フォルダの `.mojiq-backups` に
+// タイムスタンプ付きで複製し、ファイルごとに指定した世代数だけ残す。
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// バックアップを置くフォルダ名 (保存先と同じフォルダに作る)。
+pub const BACKUP_DIR_NAME: &str = ".mojiq-backups";
+
+/// 1 ファイルあたりに残すバックアップの世代数のデフォルト。
+pub const DEFAULT_BACKUP_KEEP: usize = 5;
+
+/// タイムスタンプ部分 "YYYYMMDD-HHMMSS-mmm" (UTC) の長さ。
+const TIMESTAMP_LEN: usize = 19;
+
+#[derive(Debug, Serialize)]
+pub struct BackupEntry {
+    pub path: String,
+    pub file_name: String,
+    /// 作成日時 (UNIX エポックからのミリ秒、UTC)
+    pub created_at: u64,
+    pub size: u64,
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(BACKUP_DIR_NAME)
+}
+
+/// バックアップのファイル名の前後 ("<stem>." と ".<ext>")。
+fn backup_name_parts(path: &Path) -> Option<(String, String)> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    Some((format!("{}.", stem), ext))
+}
+
+/// UNIX ミリ秒を UTC の (年, 月, 日, 時, 分, 秒) にする。
+pub fn utc_datetime(millis: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let secs = millis / 1000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // 1970-01-01 からの日数を年月日に変換する (proleptic グレゴリオ暦)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// UNIX ミリ秒を "YYYYMMDD-HHMMSS-mmm" (UTC) にする。ファイル名の辞書順が時刻順になる。
+pub fn format_timestamp(millis: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc_datetime(millis);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        millis % 1000
+    )
+}
+
+/// "YYYYMMDD-HHMMSS-mmm" を UNIX ミリ秒に戻す。形式が違えば None。
+fn parse_timestamp(s: &str) -> Option<u64> {
+    let bytes = s.as_bytes();
+    if bytes.len() != TIMESTAMP_LEN || bytes[8] != b'-' || bytes[15] != b'-' {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = s.get(range)?;
+        if !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse().ok()
+    };
+    let (year, month, day) = (num(0..4)?, num(4..6)?, num(6..8)?);
+    let (hour, minute, second, millis) = (num(9..11)?, num(11..13)?, num(13..15)?, num(16..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // format_timestamp の逆変換
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs * 1000 + millis).ok()
+}
+
+/// `path` のバックアップを新しい順に列挙する。
+pub fn list_backups(path: &Path) -> std::io::Result<Vec<BackupEntry>> {
+    let dir = backup_dir(path);
+    let Some((prefix, suffix)) = backup_name_parts(path) else {
+        return Ok(Vec::new());
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups: Vec<BackupEntry> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let timestamp = file_name.strip_prefix(&prefix)?.strip_suffix(&suffix)?;
+            let created_at = parse_timestamp(timestamp)?;
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some(BackupEntry {
+                path: entry.path().to_string_lossy().into_owned(),
+                file_name,
+                created_at,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// 既存の `path` をバックアップフォルダに複製し、`keep` 世代を超えた古いものを削除する。
+/// `keep` が 0 ならバックアップを作らない。
+pub fn create_backup(path: &Path, keep: usize) -> std::io::Result<Option<PathBuf>> {
+    if keep == 0 || !path.is_file() {
+        return Ok(None);
+    }
+    let Some((prefix, suffix)) = backup_name_parts(path) else {
+        return Ok(None);
+    };
+    let dir = backup_dir(path);
+    std::fs::create_dir_all(&dir)?;
+
+    let mut millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    // 同じミリ秒に 2 回保存した場合は 1ms ずらして上書きを避ける
+    let backup_path = loop {
+        let candidate = dir.join(format!("{}{}{}", prefix, format_timestamp(millis), suffix));
+        if !candidate.exists() {
+            break candidate;
+        }
+        millis += 1;
+    };
+
+    std::fs::copy(path, &backup_path)?;
+    // Windows の FlushFileBuffers は書き込み権限のないハンドルでは失敗するので、書き込み可能で開く
+    let synced = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&backup_path)
+        .and_then(|file| file.sync_all());
+
+    // 同期に失敗しても古い世代の削除は行う (失敗のたびにフォルダが増え続けないように)
+    for old in list_backups(path)?.into_iter().skip(keep) {
+        if let Err(e) = std::fs::remove_file(&old.path) {
+            eprintln!("[MojiQ] 古いバックアップを削除できません: {}: {}", old.path, e);
+        }
+    }
+    synced?;
+    Ok(Some(backup_path))
+}
+
+/// `backup_path` が `path` のバックアップ (同じフォルダの `.mojiq-backups` 内で名前が一致する) か確認する。
+pub fn validate_backup_path(path: &Path, backup_path: &Path) -> Result<(), String> {
+    let is_backup = list_backups(path)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .any(|b| Path::new(&b.path) == backup_path);
+    if is_backup {
+        Ok(())
+    } else {
+        Err(format!("{} は {} のバックアップではありません", backup_path.display(), path.display()))
+    }
+}
+
+/// ディレクトリのエントリ (リネーム結果) をディスクに書き出す。
+/// Windows ではディレクトリを開けないため何もしない (NTFS はメタデータをジャーナルで保護する)。
+pub fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
@@ -0,0 +1,403 @@
+// フォルダ単位の一括書き出し: ルートフォルダ以下から話ごとの原稿 (PDF、または画像をまとめたフォルダ) と
+// その描画データ・コメントデータ (`_描画.json` / `_コメント.json`) を探し、同じ設定で並列に書き出す。
+// 1 件ずつの書き出しは CLI の export と同じ (cli::export)。GUI (batch_export コマンド) と CLI (`mojiq-pro batch`) から使う。
+
+use crate::cli::{default_comment_path, default_drawing_path, export, ExportJob, ExportSettings};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// 1 件書き出すごとにフロントエンドへ送るイベント (BatchProgress)。
+pub const PROGRESS_EVENT: &str = "batch-export-progress";
+
+/// 書き出し先を指定しないときにルートフォルダの中に作るフォルダ名。
+pub const DEFAULT_OUT_DIR_NAME: &str = "書き出し";
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+const DRAWING_SUFFIX: &str = "_描画.json";
+const COMMENT_SUFFIX: &str = "_コメント.json";
+
+/// 書き出す 1 件 (1 話分)。
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItem {
+    /// ルートフォルダからの相対パス (進捗・ログの表示用)
+    pub name: String,
+    /// 元原稿。PDF なら 1 つ、画像フォルダならページ順の画像
+    pub inputs: Vec<PathBuf>,
+    /// 描画データ。見つからなければ None (書き出さずにスキップする)
+    pub drawings: Option<PathBuf>,
+    pub comments: Option<PathBuf>,
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Succeeded,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItemResult {
+    pub name: String,
+    pub out: String,
+    pub status: BatchStatus,
+    pub page_count: usize,
+    /// 失敗・スキップの理由
+    pub message: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub result: BatchItemResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchReport {
+    pub root: String,
+    pub out_dir: String,
+    /// 見つけた順 (フォルダ・ファイル名の自然順) の結果
+    pub items: Vec<BatchItemResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub elapsed_ms: u64,
+    /// 書き出し先に作ったログ。書けなかったときは None
+    pub log_path: Option<String>,
+}
+
+pub fn default_out_dir(root: &Path) -> PathBuf {
+    root.join(DEFAULT_OUT_DIR_NAME)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// 数字の並びを数値として比べる (HeaderBar.tsx の localeCompare の numeric: true と同じ並び)。
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                // 先頭の 0 を除いた桁数、同じ桁数なら文字列で比べれば数値の大小になる
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(&y)) {
+                    Ordering::Equal => {}
+                    order => return order,
+                }
+            }
+            (Some(x), Some(y)) => {
+                match x.to_lowercase().cmp(y.to_lowercase()) {
+                    Ordering::Equal => {}
+                    order => return order,
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// 先頭の数字の並びを読み、先頭の 0 を除いて返す。
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits.trim_start_matches('0').to_string()
+}
+
+/// 画像フォルダの `*<suffix>`。フォルダ名と同じ名前のものを優先し、なければ自然順で最初のもの。
+fn find_sidecar(files: &[PathBuf], dir_name: &str, suffix: &str) -> Option<PathBuf> {
+    let preferred = format!("{}{}", dir_name, suffix);
+    files
+        .iter()
+        .find(|f| file_name(f) == preferred)
+        .or_else(|| files.iter().find(|f| file_name(f).ends_with(suffix)))
+        .cloned()
+}
+
+/// ルートフォルダ以下の書き出す原稿を探す。書き出し先と `.` で始まるフォルダ (.mojiq-backups など) は見ない。
+/// 描画データのない原稿もスキップ対象として含める (ログに残すため)。
+pub fn discover(root: &Path, out_dir: &Path) -> Result<Vec<BatchItem>, String> {
+    if !root.is_dir() {
+        return Err(format!("フォルダが見つかりません: {}", root.display()));
+    }
+    // 書き出し先は相対パスや `..` を含む書き方、シンボリックリンク経由でも指定できるので、実体のパスで比べる。
+    // まだないフォルダなら探す中に出てこない
+    let excluded = out_dir.canonicalize().ok();
+    let mut items = Vec::new();
+    collect_items(root, root, out_dir, excluded.as_deref(), &mut items);
+    Ok(items)
+}
+
+fn collect_items(dir: &Path, root: &Path, out_dir: &Path, excluded: Option<&Path>, items: &mut Vec<BatchItem>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("[MojiQ] フォルダを読めません: {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if file_name(&path).starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if excluded.is_none() || path.canonicalize().ok().as_deref() != excluded {
+                dirs.push(path);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+    dirs.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    let existing = |path: PathBuf| path.is_file().then_some(path);
+
+    let pdfs: Vec<&PathBuf> = files.iter().filter(|f| has_extension(f, &["pdf"])).collect();
+    if !pdfs.is_empty() {
+        for pdf in pdfs {
+            let relative = relative(pdf);
+            items.push(BatchItem {
+                name: relative.to_string_lossy().into_owned(),
+                inputs: vec![pdf.clone()],
+                drawings: existing(default_drawing_path(pdf)),
+                comments: existing(default_comment_path(pdf)),
+                out: out_dir.join(relative),
+            });
+        }
+    } else {
+        // PDF のないフォルダの画像はフォルダ単位で 1 件 (ページ順はファイル名の自然順)
+        let images: Vec<PathBuf> = files.iter().filter(|f| has_extension(f, IMAGE_EXTENSIONS)).cloned().collect();
+        if !images.is_empty() {
+            let dir_name = file_name(dir);
+            let drawings = find_sidecar(&files, &dir_name, DRAWING_SUFFIX);
+            let comments = match &drawings {
+                Some(drawings) => {
+                    let name = file_name(drawings);
+                    let stem = name.strip_suffix(DRAWING_SUFFIX).unwrap_or(&name);
+                    existing(dir.join(format!("{}{}", stem, COMMENT_SUFFIX)))
+                }
+                None => find_sidecar(&files, &dir_name, COMMENT_SUFFIX),
+            };
+            let relative = relative(dir);
+            let name = match relative.as_os_str().is_empty() {
+                true => dir_name.clone(),
+                false => relative.to_string_lossy().into_owned(),
+            };
+            let out_parent = relative.parent().map(|p| out_dir.join(p)).unwrap_or_else(|| out_dir.to_path_buf());
+            items.push(BatchItem {
+                name,
+                inputs: images,
+                drawings,
+                comments,
+                out: out_parent.join(format!("{}.pdf", dir_name)),
+            });
+        }
+    }
+
+    for sub in dirs {
+        collect_items(&sub, root, out_dir, excluded, items);
+    }
+}
+
+fn export_item(item: &BatchItem, settings: &ExportSettings, font: Option<&ab_glyph::FontVec>) -> BatchItemResult {
+    let started = Instant::now();
+    let outcome = (|| {
+        let Some(drawings) = item.drawings.clone() else {
+            return Err((BatchStatus::Skipped, "描画データ (_描画.json) がありません".to_string()));
+        };
+        if item.inputs.contains(&item.out) {
+            return Err((BatchStatus::Failed, "書き出し先が元原稿と同じです".to_string()));
+        }
+        if let Some(parent) = item.out.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| (BatchStatus::Failed, format!("フォルダを作れません: {}: {}", parent.display(), e)))?;
+        }
+        let job = ExportJob {
+            inputs: item.inputs.clone(),
+            drawings: Some(drawings),
+            comments: item.comments.clone(),
+            out: item.out.clone(),
+        };
+        // 1 件の不具合 (壊れた画像など) で一括書き出し全体を止めない
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| export(&job, settings, font)))
+            .map_err(|_| (BatchStatus::Failed, "書き出し中に予期しないエラーが発生しました".to_string()))?
+            .map_err(|e| (BatchStatus::Failed, e))
+    })();
+
+    let (status, page_count, message) = match outcome {
+        Ok(page_count) => (BatchStatus::Succeeded, page_count, None),
+        Err((status, message)) => (status, 0, Some(message)),
+    };
+    BatchItemResult {
+        name: item.name.clone(),
+        out: item.out.to_string_lossy().into_owned(),
+        status,
+        page_count,
+        message,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// `items` を `jobs` 件ずつ並列に書き出す。1 件終わるごとに `on_result(終わった数, 全体の数, 結果)` を呼ぶ。
+/// 書き出し先にログ (mojiq-batch-<日時>.log) を残す。
+pub fn run<F>(
+    root: &Path,
+    out_dir: &Path,
+    items: Vec<BatchItem>,
+    settings: &ExportSettings,
+    font: Option<&ab_glyph::FontVec>,
+    jobs: usize,
+    on_result: F,
+) -> BatchReport
+where
+    F: Fn(usize, usize, &BatchItemResult) + Sync,
+{
+    let started = Instant::now();
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let total = items.len();
+    let next = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<BatchItemResult>>> = Mutex::new(vec![None; total]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, total.max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, atomic::Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = export_item(item, settings, font);
+                let done = completed.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+                on_result(done, total, &result);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let items: Vec<BatchItemResult> = results.into_inner().unwrap().into_iter().flatten().collect();
+    let count = |status: BatchStatus| items.iter().filter(|r| r.status == status).count();
+    let mut report = BatchReport {
+        root: root.to_string_lossy().into_owned(),
+        out_dir: out_dir.to_string_lossy().into_owned(),
+        succeeded: count(BatchStatus::Succeeded),
+        failed: count(BatchStatus::Failed),
+        skipped: count(BatchStatus::Skipped),
+        items,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        log_path: None,
+    };
+
+    let timestamp = crate::backup::format_timestamp(started_at);
+    let log_path = out_dir.join(format!("mojiq-batch-{}.log", timestamp));
+    match std::fs::create_dir_all(out_dir).and_then(|_| std::fs::write(&log_path, format_log(&report, &timestamp))) {
+        Ok(()) => report.log_path = Some(log_path.to_string_lossy().into_owned()),
+        Err(e) => eprintln!("[MojiQ] 一括書き出しのログを書けません: {}: {}", log_path.display(), e),
+    }
+    report
+}
+
+fn format_log(report: &BatchReport, timestamp: &str) -> String {
+    let seconds = |ms: u64| ms as f64 / 1000.0;
+    let mut log = format!(
+        "MojiQ Pro 一括書き出し\n開始: {} (UTC)\nフォルダ: {}\n書き出し先: {}\n成功 {} / 失敗 {} / スキップ {} ({:.1} 秒)\n\n",
+        timestamp,
+        report.root,
+        report.out_dir,
+        report.succeeded,
+        report.failed,
+        report.skipped,
+        seconds(report.elapsed_ms)
+    );
+    for item in &report.items {
+        let message = item.message.as_deref().unwrap_or_default();
+        let line = match item.status {
+            BatchStatus::Succeeded => format!(
+                "[成功] {} -> {} ({} ページ, {:.1} 秒)",
+                item.name,
+                item.out,
+                item.page_count,
+                seconds(item.elapsed_ms)
+            ),
+            BatchStatus::Skipped => format!("[スキップ] {}: {}", item.name, message),
+            BatchStatus::Failed => format!("[失敗] {}: {}", item.name, message),
+        };
+        log.push_str(&line);
+        log.push('\n');
+    }
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order() {
+        let cases = [
+            ("2", "10", Ordering::Less),
+            ("page9.jpg", "page10.jpg", Ordering::Less),
+            ("page010.jpg", "page9.jpg", Ordering::Greater),
+            ("page01.jpg", "page1.jpg", Ordering::Equal),
+            ("第2話", "第10話", Ordering::Less),
+            ("Abc", "abd", Ordering::Less),
+            ("ABC", "abc", Ordering::Equal),
+            ("a", "a1", Ordering::Less),
+            ("a1b", "a1", Ordering::Greater),
+            ("1-2", "1-10", Ordering::Less),
+            ("99999999999999999999", "100000000000000000000", Ordering::Less),
+            ("", "", Ordering::Equal),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(natural_cmp(a, b), expected, "{:?} vs {:?}", a, b);
+            assert_eq!(natural_cmp(b, a), expected.reverse(), "{:?} vs {:?}", b, a);
+        }
+
+        let mut names = vec!["10.jpg", "1.jpg", "02.jpg", "表紙.jpg", "9.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["1.jpg", "02.jpg", "9.jpg", "10.jpg", "表紙.jpg"]);
+    }
+
+    #[test]
+    fn discover_skips_out_dir_written_differently() {
+        let root = std::env::temp_dir().join(format!("mojiq-batch-test-{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        std::fs::create_dir_all(root.join("第1話")).unwrap();
+        std::fs::create_dir_all(root.join(DEFAULT_OUT_DIR_NAME).join("第1話")).unwrap();
+        std::fs::write(root.join("第1話").join("ch01.pdf"), b"").unwrap();
+        std::fs::write(root.join(DEFAULT_OUT_DIR_NAME).join("第1話").join("ch01.pdf"), b"").unwrap();
+
+        let out_dir = root.join("第1話").join("..").join(".").join(DEFAULT_OUT_DIR_NAME);
+        let items = discover(&root, &out_dir).unwrap();
+        std::fs::remove_dir_all(&root).ok();
+
+        let names: Vec<_> = items.iter().map(|item| PathBuf::from(&item.name)).collect();
+        assert_eq!(names, [Path::new("第1話").join("ch01.pdf")]);
+    }
+}
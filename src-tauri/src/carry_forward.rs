@@ -0,0 +1,259 @@
+// 前の版の描画データ (校正の指示) を次の版のページに引き継ぐ。
+// 初校に描いた指示を再校のページに置き直し、直っているかを 1 つずつ確かめられるようにする。
+// ページの対応は縮小画像の差から求め (ページの順番は変わらないとして、差し込まれた・削除されたページを見つける)、
+// 対応したページごとに alignment.rs で位置を合わせて、すべてのオブジェクトの座標を新しい版のページに写す。
+// 削除されたページの描画は引き継げないため、新しい版のページからはみ出すものと合わせて報告する。
+
+use crate::cli::SourcePage;
+use crate::drawing_data::{MojiQExportData, PageSize, CURRENT_VERSION};
+use ::image::{imageops, GrayImage};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// ページの対応を調べる縮小画像の大きさ (縦横比は揃えない)
+const THUMBNAIL_SIZE: (u32, u32) = (48, 64);
+/// 縮小画像の明るさの差の平均 (0-255) がこれを超えるページは同じページとみなさない
+const MAX_PAGE_DISTANCE: f64 = 20.0;
+/// ページを差し込み・削除とみなすコスト。差が MAX_PAGE_DISTANCE までなら対応させるほうが安くなる
+const PAGE_GAP_COST: f64 = MAX_PAGE_DISTANCE / 2.0;
+/// 前の版と新しい版でページ番号がこれ以上ずれる対応は調べない (ページ数の差の分は別に許す)
+const MAX_PAGE_DRIFT: usize = 8;
+/// 縮小画像を比べるときに試すずれ (px)
+const THUMBNAIL_SHIFT: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageStatus {
+    /// 前の版のページと新しい版のページが対応した
+    Matched,
+    /// 新しい版で差し込まれたページ (引き継ぐ描画はない)
+    Inserted,
+    /// 新しい版で削除されたページ (描画は引き継がない)
+    Removed,
+}
+
+/// ページ 1 つ分の引き継ぎ結果。
+#[derive(Debug, Clone, Serialize)]
+pub struct PageCarry {
+    /// 前の版のページ番号 (0 始まり)。差し込まれたページは None
+    pub old_page: Option<usize>,
+    /// 新しい版のページ番号 (0 始まり)。削除されたページは None
+    pub new_page: Option<usize>,
+    pub status: PageStatus,
+    /// 縮小画像の明るさの差の平均 (0-255)。対応したページのみ
+    pub distance: Option<f64>,
+    /// 位置合わせの結果。描画のある対応したページのみ
+    pub alignment: Option<crate::alignment::Alignment>,
+    /// 前の版のページにあったオブジェクトの数
+    pub objects: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagReason {
+    /// ページが新しい版にない (削除された、または前の版のページ数を超えている)。引き継いでいない
+    PageRemoved,
+    /// 写した位置が新しい版のページの外になった。引き継いではいる
+    OutsidePage,
+}
+
+/// 確認が必要なオブジェクト。
+#[derive(Debug, Clone, Serialize)]
+pub struct FlaggedObject {
+    pub old_page: usize,
+    pub new_page: Option<usize>,
+    pub id: String,
+    pub reason: FlagReason,
+}
+
+pub struct CarryForward {
+    /// 新しい版のページに合わせた描画データ
+    pub data: MojiQExportData,
+    pub pages: Vec<PageCarry>,
+    pub flagged: Vec<FlaggedObject>,
+}
+
+/// 縮小画像と、描画座標の基準 (描画データのページサイズに対応する大きさ)。
+struct PageInfo {
+    thumbnail: GrayImage,
+    default_page_size: PageSize,
+}
+
+/// 前の版の描画データを新しい版のページに引き継ぐ。
+/// ページは cli::load_source_pages で読み込んだもの (PDF 1 つ、またはページ順の画像)。
+pub fn carry_forward(data: &MojiQExportData, old: &[SourcePage], new: &[SourcePage]) -> Result<CarryForward, String> {
+    if old.is_empty() || new.is_empty() {
+        return Err("ページがありません".to_string());
+    }
+    let old_pages = old.iter().map(page_info).collect::<Result<Vec<_>, _>>()?;
+    let new_pages = new.iter().map(page_info).collect::<Result<Vec<_>, _>>()?;
+
+    let mut carried = MojiQExportData {
+        version: CURRENT_VERSION.to_string(),
+        exported_at: None,
+        page_count: Some(new.len()),
+        page_sizes: BTreeMap::new(),
+        checked_state: None,
+        data: BTreeMap::new(),
+        extra: data.extra.clone(),
+    };
+    let mut pages = Vec::new();
+    let mut flagged = Vec::new();
+
+    for (old_index, new_index, distance) in match_pages(&old_pages, &new_pages) {
+        let objects = old_index.map(|i| data.page_objects(i)).unwrap_or(&[]);
+        let mut page = PageCarry {
+            old_page: old_index,
+            new_page: new_index,
+            status: PageStatus::Matched,
+            distance,
+            alignment: None,
+            objects: objects.len(),
+        };
+        match (old_index, new_index) {
+            (Some(o), Some(n)) if !objects.is_empty() => {
+                let alignment = crate::alignment::align(&old[o].decode()?, &new[n].decode()?);
+                // 前の版の描画座標 → 前の版の画像の px → 新しい版の画像の px → 新しい版の描画座標
+                let old_size = data.page_size(o).unwrap_or(old_pages[o].default_page_size);
+                let new_size = new_pages[n].default_page_size;
+                let (old_x, old_y) = (old[o].width as f64 / old_size.width, old[o].height as f64 / old_size.height);
+                let (new_x, new_y) = (new_size.width / new[n].width as f64, new_size.height / new[n].height as f64);
+                let transform = alignment.transform;
+                let map = |x: f64, y: f64| {
+                    let (x, y) = transform.apply(x * old_x, y * old_y);
+                    (x * new_x, y * new_y)
+                };
+                let length_scale = transform.scale * (old_x * new_x).min(old_y * new_y);
+
+                let mut objects = objects.to_vec();
+                for object in &mut objects {
+                    object.transform(map, length_scale, transform.rotation);
+                    let outside = object.anchor().is_some_and(|(x, y)| {
+                        !(0.0..=new_size.width).contains(&x) || !(0.0..=new_size.height).contains(&y)
+                    });
+                    if outside {
+                        flagged.push(FlaggedObject {
+                            old_page: o,
+                            new_page: Some(n),
+                            id: object.id.clone(),
+                            reason: FlagReason::OutsidePage,
+                        });
+                    }
+                }
+                carried.page_sizes.insert(n.to_string(), new_size);
+                carried.data.insert(n.to_string(), objects);
+                page.alignment = Some(alignment);
+            }
+            (Some(_), Some(_)) => {}
+            (Some(o), None) => {
+                page.status = PageStatus::Removed;
+                flagged.extend(objects.iter().map(|object| FlaggedObject {
+                    old_page: o,
+                    new_page: None,
+                    id: object.id.clone(),
+                    reason: FlagReason::PageRemoved,
+                }));
+            }
+            (None, _) => page.status = PageStatus::Inserted,
+        }
+        pages.push(page);
+    }
+
+    // 前の版のページ数を超えるページの描画 (ページの違う PDF に描いたものなど)
+    for (key, objects) in &data.data {
+        let Ok(o) = key.parse::<usize>() else {
+            continue;
+        };
+        if o < old.len() {
+            continue;
+        }
+        flagged.extend(objects.iter().map(|object| FlaggedObject {
+            old_page: o,
+            new_page: None,
+            id: object.id.clone(),
+            reason: FlagReason::PageRemoved,
+        }));
+    }
+
+    Ok(CarryForward { data: carried, pages, flagged })
+}
+
+fn page_info(page: &SourcePage) -> Result<PageInfo, String> {
+    let image = page.decode()?;
+    let (width, height) = page.default_page_size;
+    Ok(PageInfo {
+        thumbnail: imageops::thumbnail(&image.to_luma8(), THUMBNAIL_SIZE.0, THUMBNAIL_SIZE.1),
+        default_page_size: PageSize { width: width as f64, height: height as f64 },
+    })
+}
+
+/// 縮小画像の差。少しのずれ (裁ち落としの違いなど) は許す。
+fn page_distance(old: &PageInfo, new: &PageInfo) -> f64 {
+    let mut best = f64::MAX;
+    for dy in -THUMBNAIL_SHIFT..=THUMBNAIL_SHIFT {
+        for dx in -THUMBNAIL_SHIFT..=THUMBNAIL_SHIFT {
+            best = best.min(crate::alignment::mean_abs_diff(&old.thumbnail, &new.thumbnail, dx, dy, 1));
+        }
+    }
+    best
+}
+
+/// 前の版と新しい版のページを順番どおりに対応させる (編集距離と同じ動的計画法)。
+/// (前の版のページ, 新しい版のページ, 差) を新しい版の順に返す。片方が None のものは削除・差し込み。
+fn match_pages(old: &[PageInfo], new: &[PageInfo]) -> Vec<(Option<usize>, Option<usize>, Option<f64>)> {
+    #[derive(Clone, Copy)]
+    enum Step {
+        Match(f64),
+        Remove,
+        Insert,
+    }
+
+    let (n, m) = (old.len(), new.len());
+    let drift = n.abs_diff(m) + MAX_PAGE_DRIFT;
+    let mut cost = vec![vec![0.0; m + 1]; n + 1];
+    let mut steps = vec![vec![Step::Insert; m + 1]; n + 1];
+    for i in 1..=n {
+        cost[i][0] = i as f64 * PAGE_GAP_COST;
+        steps[i][0] = Step::Remove;
+    }
+    for (j, cost) in cost[0].iter_mut().enumerate() {
+        *cost = j as f64 * PAGE_GAP_COST;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let (mut best, mut step) = (cost[i - 1][j] + PAGE_GAP_COST, Step::Remove);
+            if cost[i][j - 1] + PAGE_GAP_COST < best {
+                (best, step) = (cost[i][j - 1] + PAGE_GAP_COST, Step::Insert);
+            }
+            if i.abs_diff(j) <= drift {
+                let distance = page_distance(&old[i - 1], &new[j - 1]);
+                if distance <= MAX_PAGE_DISTANCE && cost[i - 1][j - 1] + distance <= best {
+                    (best, step) = (cost[i - 1][j - 1] + distance, Step::Match(distance));
+                }
+            }
+            cost[i][j] = best;
+            steps[i][j] = step;
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        match steps[i][j] {
+            Step::Match(distance) => {
+                pairs.push((Some(i - 1), Some(j - 1), Some(distance)));
+                (i, j) = (i - 1, j - 1);
+            }
+            Step::Remove => {
+                pairs.push((Some(i - 1), None, None));
+                i -= 1;
+            }
+            Step::Insert => {
+                pairs.push((None, Some(j - 1), None));
+                j -= 1;
+            }
+        }
+    }
+    pairs.reverse();
+    pairs
+}
@@ -0,0 +1,656 @@
+// コマンドラインからのヘッドレス書き出し。ウィンドウを作らずに PDF 保存と同じパイプライン (pdf.rs) で書き出す。
+//   mojiq-pro export --input ch01.pdf --drawings ch01_描画.json --out ch01_marked.pdf --compress 25MB
+// 描画データは Rust 側で描画し (overlay_render.rs)、PDF の背景はページに貼られた画像を使う (pdf_images.rs)。
+//   mojiq-pro batch 入稿フォルダ --out-dir 書き出し先 --jobs 4
+// batch はフォルダ以下の話ごとの原稿をまとめて書き出す (batch.rs)。
+
+use crate::batch::{BatchItemResult, BatchStatus};
+use crate::commands::{PageDrawingsV2, SaveRequestV2};
+use crate::drawing_data::MojiQExportData;
+use crate::overlay_render::{default_line_scale, load_font, render_page, RenderOptions};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::path::{Path, PathBuf};
+use tiny_skia::{Pixmap, PixmapPaint, Transform};
+
+pub const EXIT_OK: i32 = 0;
+/// 書き出しに失敗した (入力が読めない、保存できないなど)
+pub const EXIT_FAILURE: i32 = 1;
+/// 引数の誤り
+pub const EXIT_USAGE: i32 = 2;
+
+/// 一括書き出しの同時実行数の既定値の上限。1 件ごとに全ページの画像をメモリに持つので CPU 数より控えめにする。
+const DEFAULT_MAX_JOBS: usize = 4;
+
+/// pdfRenderer.ts の RENDER_SCALE。PDF のページはこの倍率 (pt → px) で描画した大きさが描画座標の基準になる。
+const PDF_RENDER_SCALE: f32 = 3.0;
+
+/// 全ページの MediaBox がこの差 (pt) に収まれば同じ仕上がりサイズとみなす。
+const PAGE_SIZE_TOLERANCE_PT: f32 = 0.5;
+
+const USAGE: &str = "\
+使い方:
+  mojiq-pro export --input <ファイル> [--input <画像>...] --out <PDF> [オプション]
+  mojiq-pro batch <フォルダ> [--out-dir <フォルダ>] [--jobs <数>] [オプション]
+
+export の入力:
+  -i, --input <パス>          背景の PDF (スキャン画像のページ) か画像 (JPEG / PNG)。画像は複数指定でページ順になる
+                              画像の上に文字や線を描いた PDF のページはエラーになる (アプリから書き出す)
+  -d, --drawings <パス>       描画データ (_描画.json)。省略時は入力と同じ場所の <名前>_描画.json
+  -c, --comments <パス>       コメントデータ (_コメント.json)。省略時は入力と同じ場所の <名前>_コメント.json があれば使う
+  -o, --out <パス>            書き出す PDF
+
+batch の入力:
+  <フォルダ>                  以下の PDF と、その <名前>_描画.json・<名前>_コメント.json を話ごとに書き出す。
+                              PDF のないフォルダの画像はまとめて 1 冊とし、フォルダ内の *_描画.json を使う
+      --out-dir <フォルダ>    書き出し先 (既定 <フォルダ>/書き出し)。元と同じ相対パス・名前の PDF を作り、ログも置く
+      --jobs <数>             同時に書き出す数 (既定は CPU 数、最大 4)
+
+オプション (共通):
+      --compress <サイズ>     圧縮保存してこのサイズ以下にする (例: 25MB, 800KB)
+      --compress-dpi <dpi>    圧縮時に縮小する解像度 (既定 300、0 で縮小しない)
+      --trim-size <サイズ>    仕上がりサイズ (B5 / A5 / 182x257 など)
+      --spread                見開きで書き出す
+      --binding <right|left>  綴じ方向 (既定 right)
+      --separate-overlay      描画を背景と合成せずに重ねる
+      --monochrome            モノクロのページを 2 値 / グレースケールで埋め込む
+      --hide-comments         PDF 注釈由来のコメントを描かない
+      --font <パス>           文字の描画に使うフォント (省略時はシステムの日本語フォント)
+      --line-scale <倍率>     線幅・文字サイズの倍率 (省略時は画面表示の倍率から見積もる)
+      --backups <世代数>      上書き時に残すバックアップ数 (既定 5、0 で残さない)
+
+終了コード: 0 成功 / 1 書き出し失敗 (batch は 1 件でも失敗があれば) / 2 引数の誤り";
+
+/// コマンドライン引数がサブコマンドなら実行して終了コードを返す。GUI を起動する場合は None。
+pub fn run(args: &[String]) -> Option<i32> {
+    let code = match args.get(1).map(String::as_str)? {
+        "export" => {
+            attach_console();
+            export_command(&args[2..])
+        }
+        "batch" => {
+            attach_console();
+            batch_command(&args[2..])
+        }
+        "help" | "--help" | "-h" => {
+            attach_console();
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        "--version" | "-V" => {
+            attach_console();
+            println!("mojiq-pro {}", env!("CARGO_PKG_VERSION"));
+            EXIT_OK
+        }
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// リリースビルドの Windows 版はコンソールを持たない (windows_subsystem = "windows") ので、
+/// 起動元のコンソールにつないで標準出力・標準エラーを表示できるようにする。
+fn attach_console() {
+    #[cfg(windows)]
+    unsafe {
+        use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// 書き出し 1 件分の入出力。
+pub struct ExportJob {
+    pub inputs: Vec<PathBuf>,
+    pub drawings: Option<PathBuf>,
+    /// コメントデータ。None なら入力と同じ場所の `<名前>_コメント.json` があれば使う
+    pub comments: Option<PathBuf>,
+    pub out: PathBuf,
+}
+
+/// 書き出しの設定 (保存ダイアログのオプションに相当)。
+#[derive(Default, Clone)]
+pub struct ExportSettings {
+    pub compress_target_bytes: Option<u64>,
+    pub compress_target_dpi: Option<f32>,
+    pub trim_size: Option<String>,
+    pub spread_mode: bool,
+    pub binding_direction: Option<String>,
+    pub separate_overlay: bool,
+    pub detect_monochrome: bool,
+    pub hide_comments: bool,
+    pub font: Option<PathBuf>,
+    pub line_scale: Option<f32>,
+    pub backup_count: Option<usize>,
+}
+
+fn export_command(args: &[String]) -> i32 {
+    let (job, settings) = match parse_export_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("mojiq-pro: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let font = match load_font(settings.font.as_deref()) {
+        Ok(font) => font,
+        Err(e) => {
+            eprintln!("mojiq-pro: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    match export(&job, &settings, font.as_ref()) {
+        Ok(page_count) => {
+            println!("{} を書き出しました ({} ページ)", job.out.display(), page_count);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("mojiq-pro: 書き出しに失敗しました: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// `--name value` と `--name=value` の両方を受け付ける引数の読み取り。
+struct ArgReader<'a> {
+    args: std::slice::Iter<'a, String>,
+}
+
+impl<'a> ArgReader<'a> {
+    /// 次の引数を (名前, `=` の後ろの値) に分ける。
+    fn next_flag(&mut self) -> Option<(&'a str, Option<&'a str>)> {
+        let arg = self.args.next()?;
+        Some(match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        })
+    }
+
+    fn value(&mut self, name: &str, inline: Option<&'a str>) -> Result<&'a str, String> {
+        inline
+            .or_else(|| self.args.next().map(String::as_str))
+            .ok_or_else(|| format!("{} に値を指定してください", name))
+    }
+
+    fn parsed<T: std::str::FromStr>(&mut self, name: &str, inline: Option<&'a str>) -> Result<T, String> {
+        let value = self.value(name, inline)?;
+        value.parse().map_err(|_| format!("{} の値が不正です: {}", name, value))
+    }
+}
+
+/// export と batch で共通の書き出しオプションを読む。オプションでなければ false。
+fn parse_setting<'a>(
+    reader: &mut ArgReader<'a>,
+    name: &str,
+    inline: Option<&'a str>,
+    settings: &mut ExportSettings,
+) -> Result<bool, String> {
+    match name {
+        "--compress" => {
+            let value = reader.value(name, inline)?;
+            settings.compress_target_bytes =
+                Some(parse_size(value).ok_or_else(|| format!("{} の値が不正です: {}", name, value))?);
+        }
+        "--compress-dpi" => settings.compress_target_dpi = Some(reader.parsed(name, inline)?),
+        "--trim-size" => {
+            let value = reader.value(name, inline)?;
+            crate::pdf::parse_trim_size(value).ok_or_else(|| format!("{} の値が不正です: {}", name, value))?;
+            settings.trim_size = Some(value.to_string());
+        }
+        "--spread" => settings.spread_mode = true,
+        "--binding" => match reader.value(name, inline)? {
+            value @ ("right" | "left") => settings.binding_direction = Some(value.to_string()),
+            value => return Err(format!("{} は right か left を指定してください: {}", name, value)),
+        },
+        "--separate-overlay" => settings.separate_overlay = true,
+        "--monochrome" => settings.detect_monochrome = true,
+        "--hide-comments" => settings.hide_comments = true,
+        "--font" => settings.font = Some(PathBuf::from(reader.value(name, inline)?)),
+        "--line-scale" => {
+            let scale: f32 = reader.parsed(name, inline)?;
+            if !(scale > 0.0 && scale.is_finite()) {
+                return Err(format!("{} は正の数を指定してください", name));
+            }
+            settings.line_scale = Some(scale);
+        }
+        "--backups" => settings.backup_count = Some(reader.parsed(name, inline)?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn parse_export_args(args: &[String]) -> Result<(ExportJob, ExportSettings), String> {
+    let mut reader = ArgReader { args: args.iter() };
+    let mut inputs = Vec::new();
+    let mut drawings = None;
+    let mut comments = None;
+    let mut out = None;
+    let mut settings = ExportSettings::default();
+
+    while let Some((name, inline)) = reader.next_flag() {
+        match name {
+            "-i" | "--input" => inputs.push(PathBuf::from(reader.value(name, inline)?)),
+            "-d" | "--drawings" => drawings = Some(PathBuf::from(reader.value(name, inline)?)),
+            "-c" | "--comments" => comments = Some(PathBuf::from(reader.value(name, inline)?)),
+            "-o" | "--out" => out = Some(PathBuf::from(reader.value(name, inline)?)),
+            _ if parse_setting(&mut reader, name, inline, &mut settings)? => {}
+            other => return Err(format!("不明な引数です: {}", other)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("--input を指定してください".to_string());
+    }
+    let out = out.ok_or("--out を指定してください")?;
+    Ok((ExportJob { inputs, drawings, comments, out }, settings))
+}
+
+/// batch の引数: (ルートフォルダ, 書き出し先, 同時実行数, 設定)。
+fn parse_batch_args(args: &[String]) -> Result<(PathBuf, Option<PathBuf>, Option<usize>, ExportSettings), String> {
+    let mut reader = ArgReader { args: args.iter() };
+    let mut root = None;
+    let mut out_dir = None;
+    let mut jobs = None;
+    let mut settings = ExportSettings::default();
+
+    while let Some((name, inline)) = reader.next_flag() {
+        match name {
+            "--out-dir" => out_dir = Some(PathBuf::from(reader.value(name, inline)?)),
+            "--jobs" => {
+                let value: usize = reader.parsed(name, inline)?;
+                if value == 0 {
+                    return Err(format!("{} は 1 以上を指定してください", name));
+                }
+                jobs = Some(value);
+            }
+            _ if parse_setting(&mut reader, name, inline, &mut settings)? => {}
+            other if other.starts_with('-') => return Err(format!("不明な引数です: {}", other)),
+            other if root.is_none() => root = Some(PathBuf::from(other)),
+            other => return Err(format!("フォルダは 1 つだけ指定してください: {}", other)),
+        }
+    }
+
+    let root = root.ok_or("書き出すフォルダを指定してください")?;
+    Ok((root, out_dir, jobs, settings))
+}
+
+/// 同時実行数の既定値 (CPU 数、最大 DEFAULT_MAX_JOBS)。
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(DEFAULT_MAX_JOBS)
+}
+
+fn batch_command(args: &[String]) -> i32 {
+    let (root, out_dir, jobs, settings) = match parse_batch_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("mojiq-pro: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let font = match load_font(settings.font.as_deref()) {
+        Ok(font) => font,
+        Err(e) => {
+            eprintln!("mojiq-pro: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let out_dir = out_dir.unwrap_or_else(|| crate::batch::default_out_dir(&root));
+    let items = match crate::batch::discover(&root, &out_dir) {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("mojiq-pro: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    if items.is_empty() {
+        eprintln!("mojiq-pro: {} に書き出す原稿がありません", root.display());
+        return EXIT_FAILURE;
+    }
+
+    let jobs = jobs.unwrap_or_else(default_jobs);
+    println!("{} 件を書き出します (同時 {} 件)", items.len(), jobs.min(items.len()));
+    let report = crate::batch::run(&root, &out_dir, items, &settings, font.as_ref(), jobs, print_batch_result);
+
+    println!("成功 {} / 失敗 {} / スキップ {}", report.succeeded, report.failed, report.skipped);
+    if let Some(log_path) = &report.log_path {
+        println!("ログ: {}", log_path);
+    }
+    if report.failed > 0 {
+        EXIT_FAILURE
+    } else {
+        EXIT_OK
+    }
+}
+
+fn print_batch_result(done: usize, total: usize, result: &BatchItemResult) {
+    let message = result.message.as_deref().unwrap_or_default();
+    match result.status {
+        BatchStatus::Succeeded => println!("[{}/{}] 成功: {} ({} ページ)", done, total, result.name, result.page_count),
+        BatchStatus::Skipped => println!("[{}/{}] スキップ: {}: {}", done, total, result.name, message),
+        BatchStatus::Failed => eprintln!("[{}/{}] 失敗: {}: {}", done, total, result.name, message),
+    }
+}
+
+/// "25MB" / "800KB" / "1.5GB" / バイト数を読む (1KB = 1024 バイト)。
+fn parse_size(value: &str) -> Option<u64> {
+    let upper = value.trim().to_ascii_uppercase();
+    let (number, unit) = match upper.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(pos) => upper.split_at(pos),
+        None => (upper.as_str(), ""),
+    };
+    let multiplier = match unit.trim() {
+        "" | "B" => 1u64,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    (number > 0.0 && number.is_finite()).then_some((number * multiplier as f64) as u64)
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+}
+
+/// 入力の各ページの背景画像と、描画座標の基準になるページサイズ。
+pub struct SourcePage {
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+    /// 描画データにページサイズがないときの座標の基準 (GUI で読み込んだときのページの大きさ)
+    pub default_page_size: (f32, f32),
+}
+
+impl SourcePage {
+    /// 背景画像をデコードする (ページ画像の比較などで画素が必要なとき)。
+    pub fn decode(&self) -> Result<::image::DynamicImage, String> {
+        crate::pdf_images::decode_data_url(&self.data_url)
+    }
+}
+
+/// 入力ファイルを読み込む。PDF の場合は仕上がりサイズの候補 (全ページ同じ大きさなら "幅x高さ" mm) も返す。
+pub fn load_source_pages(inputs: &[PathBuf]) -> Result<(Vec<SourcePage>, Option<String>), String> {
+    if let [input] = inputs {
+        if is_pdf(input) {
+            let images = crate::pdf_images::extract_page_images(&input.to_string_lossy()).map_err(|e| e.to_string())?;
+            let first = images.first().map(|p| p.page_size_pt).ok_or("ページがありません")?;
+            let uniform = images.iter().all(|p| {
+                (p.page_size_pt.0 - first.0).abs() <= PAGE_SIZE_TOLERANCE_PT
+                    && (p.page_size_pt.1 - first.1).abs() <= PAGE_SIZE_TOLERANCE_PT
+            });
+            if !uniform {
+                eprintln!("[MojiQ] ページの大きさが揃っていないため、画像の解像度からページサイズを決めます");
+            }
+            let trim_size = uniform.then(|| format!("{:.2}x{:.2}", first.0 * 25.4 / 72.0, first.1 * 25.4 / 72.0));
+            let pages = images
+                .into_iter()
+                .map(|p| SourcePage {
+                    data_url: p.data_url,
+                    width: p.width,
+                    height: p.height,
+                    default_page_size: (
+                        (p.page_size_pt.0 * PDF_RENDER_SCALE).round(),
+                        (p.page_size_pt.1 * PDF_RENDER_SCALE).round(),
+                    ),
+                })
+                .collect();
+            return Ok((pages, trim_size));
+        }
+    }
+
+    let pages = inputs
+        .iter()
+        .map(|input| {
+            if is_pdf(input) {
+                return Err("PDF は 1 つだけ指定してください (画像と混ぜることはできません)".to_string());
+            }
+            let bytes = std::fs::read(input).map_err(|e| format!("{} を読み込めません: {}", input.display(), e))?;
+            let format = ::image::guess_format(&bytes).map_err(|e| format!("{}: {}", input.display(), e))?;
+            let mime = match format {
+                ::image::ImageFormat::Jpeg => "image/jpeg",
+                ::image::ImageFormat::Png => "image/png",
+                _ => return Err(format!("対応していない画像形式です: {}", input.display())),
+            };
+            let (width, height) = ::image::image_dimensions(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+            Ok(SourcePage {
+                data_url: format!("data:{};base64,{}", mime, BASE64.encode(&bytes)),
+                width,
+                height,
+                default_page_size: (width as f32, height as f32),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok((pages, None))
+}
+
+/// 入力と同じ場所の `<名前>_描画.json` (drawingExportImport.ts の getDrawingJsonPath と同じ規則)。
+pub fn default_drawing_path(input: &Path) -> PathBuf {
+    let stem = input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    input.with_file_name(format!("{}_描画.json", stem))
+}
+
+/// 入力と同じ場所の `<名前>_コメント.json` (getCommentJsonPath と同じ規則)。
+pub fn default_comment_path(input: &Path) -> PathBuf {
+    let stem = input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    input.with_file_name(format!("{}_コメント.json", stem))
+}
+
+fn read_drawing_data(path: &Path, label: &str) -> Result<MojiQExportData, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|e| format!("{} {} を読み込めません: {}", label, path.display(), e))?;
+    MojiQExportData::from_json(&json)
+}
+
+/// コメントデータを読む。`--hide-comments` のときは読まない。
+/// 明示されていなければ、入力が 1 つのときだけ既定の場所を見て、なければコメントなしにする。
+fn load_comments(job: &ExportJob, settings: &ExportSettings) -> Result<Option<MojiQExportData>, String> {
+    if settings.hide_comments {
+        return Ok(None);
+    }
+    let path = match (&job.comments, job.inputs.as_slice()) {
+        (Some(path), _) => path.clone(),
+        (None, [input]) => default_comment_path(input),
+        (None, _) => return Ok(None),
+    };
+    if job.comments.is_none() && !path.is_file() {
+        return Ok(None);
+    }
+    read_drawing_data(&path, "コメントデータ").map(Some)
+}
+
+/// ページの描画を描く。描画データとコメントデータは座標の基準サイズが別なので別々に描いて重ねる。
+fn render_overlay(
+    layers: &[&MojiQExportData],
+    index: usize,
+    source: &SourcePage,
+    settings: &ExportSettings,
+    font: Option<&ab_glyph::FontVec>,
+) -> Option<Pixmap> {
+    let mut overlay: Option<Pixmap> = None;
+    for data in layers {
+        let page_size = data
+            .page_size(index)
+            .map(|s| (s.width as f32, s.height as f32))
+            .unwrap_or(source.default_page_size);
+        let options = RenderOptions {
+            line_scale: settings.line_scale.unwrap_or_else(|| default_line_scale(page_size.0, page_size.1)),
+            hide_comments: settings.hide_comments,
+        };
+        let Some(layer) = render_page(
+            data.page_objects(index),
+            page_size.0,
+            page_size.1,
+            source.width,
+            source.height,
+            font,
+            &options,
+        ) else {
+            continue;
+        };
+        match overlay.as_mut() {
+            Some(pixmap) => {
+                pixmap.draw_pixmap(0, 0, layer.as_ref(), &PixmapPaint::default(), Transform::identity(), None)
+            }
+            None => overlay = Some(layer),
+        }
+    }
+    overlay
+}
+
+/// 書き出しを実行し、書き出したページ数を返す。
+pub fn export(job: &ExportJob, settings: &ExportSettings, font: Option<&ab_glyph::FontVec>) -> Result<usize, String> {
+    let drawings_path = match (&job.drawings, job.inputs.as_slice()) {
+        (Some(path), _) => path.clone(),
+        (None, [input]) => default_drawing_path(input),
+        (None, _) => return Err("画像を複数指定したときは --drawings を指定してください".to_string()),
+    };
+    let drawings = read_drawing_data(&drawings_path, "描画データ")?;
+    let comments = load_comments(job, settings)?;
+    let layers: Vec<&MojiQExportData> = std::iter::once(&drawings).chain(comments.as_ref()).collect();
+
+    let (sources, pdf_trim_size) = load_source_pages(&job.inputs)?;
+
+    let mut pages = Vec::with_capacity(sources.len());
+    let mut background_images = Vec::with_capacity(sources.len());
+    for (index, source) in sources.into_iter().enumerate() {
+        let drawing_overlay = match render_overlay(&layers, index, &source, settings, font) {
+            Some(pixmap) => {
+                let png = pixmap.encode_png().map_err(|e| format!("{} ページ目の描画に失敗しました: {}", index + 1, e))?;
+                format!("data:image/png;base64,{}", BASE64.encode(png))
+            }
+            None => String::new(),
+        };
+        pages.push(PageDrawingsV2 {
+            page_number: index,
+            drawing_overlay,
+            width: source.width,
+            height: source.height,
+        });
+        background_images.push(source.data_url);
+    }
+
+    let page_count = pages.len();
+    let request = SaveRequestV2 {
+        pages,
+        background_images,
+        compress_mode: Some(settings.compress_target_bytes.is_some()),
+        compress_target_bytes: settings.compress_target_bytes,
+        compress_target_dpi: settings.compress_target_dpi,
+        separate_overlay: Some(settings.separate_overlay),
+        detect_monochrome: Some(settings.detect_monochrome),
+        trim_size: settings.trim_size.clone().or(pdf_trim_size),
+        spread_mode: Some(settings.spread_mode),
+        binding_direction: settings.binding_direction.clone(),
+        source_pdf_path: job.inputs.first().filter(|p| is_pdf(p)).map(|p| p.to_string_lossy().into_owned()),
+        backup_count: settings.backup_count,
+        ..SaveRequestV2::default()
+    };
+
+    crate::pdf::create_pdf_with_overlays(&job.out.to_string_lossy(), &request).map_err(|e| e.to_string())?;
+    Ok(page_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_sizes() {
+        let cases = [
+            ("25MB", Some(25 * 1024 * 1024)),
+            ("800KB", Some(800 * 1024)),
+            ("1.5GB", Some(1536 * 1024 * 1024)),
+            ("25mb", Some(25 * 1024 * 1024)),
+            (" 10 M ", Some(10 * 1024 * 1024)),
+            ("4096", Some(4096)),
+            ("512B", Some(512)),
+            ("25TB", None),
+            ("MB", None),
+            ("0MB", None),
+            ("-1MB", None),
+            ("1e999MB", None),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_size(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn next_flag_splits_inline_values() {
+        let list = args(&["--out=a=b.pdf", "--out", "c.pdf", "-o=d.pdf", "plain"]);
+        let mut reader = ArgReader { args: list.iter() };
+        assert_eq!(reader.next_flag(), Some(("--out", Some("a=b.pdf"))));
+        assert_eq!(reader.next_flag(), Some(("--out", None)));
+        assert_eq!(reader.value("--out", None), Ok("c.pdf"));
+        // 短い名前は `=` で分けない
+        assert_eq!(reader.next_flag(), Some(("-o=d.pdf", None)));
+        assert_eq!(reader.next_flag(), Some(("plain", None)));
+        assert_eq!(reader.next_flag(), None);
+        assert!(reader.value("--out", None).is_err());
+    }
+
+    #[test]
+    fn export_args_accept_both_value_forms() {
+        for list in [
+            args(&["--input", "a.pdf", "--out", "b.pdf", "--compress", "25MB", "--binding", "left"]),
+            args(&["--input=a.pdf", "--out=b.pdf", "--compress=25MB", "--binding=left"]),
+            args(&["-i", "a.pdf", "-o", "b.pdf", "--compress", "25MB", "--binding=left"]),
+        ] {
+            let (job, settings) = parse_export_args(&list).unwrap();
+            assert_eq!(job.inputs, [PathBuf::from("a.pdf")]);
+            assert_eq!(job.out, PathBuf::from("b.pdf"));
+            assert_eq!(settings.compress_target_bytes, Some(25 * 1024 * 1024));
+            assert_eq!(settings.binding_direction.as_deref(), Some("left"));
+        }
+    }
+
+    #[test]
+    fn export_args_collect_inputs_and_settings() {
+        let list = args(&[
+            "-i", "1.jpg", "-i", "2.jpg", "-d", "d.json", "-c", "c.json", "-o", "out.pdf", "--spread",
+            "--separate-overlay", "--monochrome", "--hide-comments", "--trim-size", "B5", "--compress-dpi", "200",
+            "--line-scale", "1.5", "--backups", "0",
+        ]);
+        let (job, settings) = parse_export_args(&list).unwrap();
+        assert_eq!(job.inputs, [PathBuf::from("1.jpg"), PathBuf::from("2.jpg")]);
+        assert_eq!(job.drawings, Some(PathBuf::from("d.json")));
+        assert_eq!(job.comments, Some(PathBuf::from("c.json")));
+        assert!(settings.spread_mode && settings.separate_overlay && settings.detect_monochrome && settings.hide_comments);
+        assert_eq!(settings.trim_size.as_deref(), Some("B5"));
+        assert_eq!(settings.compress_target_dpi, Some(200.0));
+        assert_eq!(settings.line_scale, Some(1.5));
+        assert_eq!(settings.backup_count, Some(0));
+    }
+
+    #[test]
+    fn export_args_errors() {
+        let cases: &[&[&str]] = &[
+            &["--out", "b.pdf"],
+            &["--input", "a.pdf"],
+            &["--input", "a.pdf", "--out"],
+            &["--input", "a.pdf", "--out", "b.pdf", "--compress", "25TB"],
+            &["--input", "a.pdf", "--out", "b.pdf", "--binding", "top"],
+            &["--input", "a.pdf", "--out", "b.pdf", "--line-scale", "0"],
+            &["--input", "a.pdf", "--out", "b.pdf", "--trim-size", "huge"],
+            &["--input", "a.pdf", "--out", "b.pdf", "--unknown"],
+        ];
+        for list in cases {
+            assert!(parse_export_args(&args(list)).is_err(), "{:?}", list);
+        }
+    }
+
+    #[test]
+    fn missing_out_is_a_usage_error() {
+        assert_eq!(run(&args(&["mojiq-pro", "export", "--input", "a.pdf"])), Some(EXIT_USAGE));
+        assert_eq!(run(&args(&["mojiq-pro", "export", "--input", "a.pdf", "--out"])), Some(EXIT_USAGE));
+        assert_eq!(run(&args(&["mojiq-pro", "batch"])), Some(EXIT_USAGE));
+        assert_eq!(run(&args(&["mojiq-pro", "--some-gui-flag"])), None);
+    }
+}
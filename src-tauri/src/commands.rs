@@ -1,10 +1,24 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::time::{UNIX_EPOCH, SystemTime, Duration};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
 use crate::pdf::create_pdf_with_drawings;
+use crate::autosave::{AutosaveState, RecoverableSession};
+use crate::backup::BackupEntry;
+use crate::batch::{BatchItem, BatchProgress, BatchReport};
+use crate::cli::ExportSettings;
+use crate::drawing_data::{MojiQExportData, PageSize};
+use crate::merge::{MergeConflict, MergeStats};
+use crate::image_diff::{DiffOptions, PageDiff};
+use crate::alignment::Alignment;
+use crate::carry_forward::{FlaggedObject, PageCarry};
+use crate::project::{OpenedProject, ProjectContent, ProjectManifest, ProjectValidation};
+use crate::file_watcher::FileWatcher;
+use crate::printing::{PageSelection, PrintOptions, PrinterInfo};
 
 /// 古い一時ファイルをクリーンアップ（1時間以上前のmojiq-print-*.pdfを削除）
 fn cleanup_old_temp_files(temp_dir: &Path) {
@@ -80,8 +94,62 @@ pub struct SaveRequest {
     pub background_images: Vec<String>,
 }
 
-// 新しいPDF保存用構造体（描画オーバーレイPNG方式）
+/// 印刷用の面付け設定。
 #[derive(Debug, Serialize, Deserialize)]
+pub struct ImpositionSettings {
+    /// "2up" / "4up" / "booklet" (中綴じ)
+    pub layout: String,
+    /// 用紙サイズ。"A4" / "A3" などの規格名か "297x420" (mm) 形式。None なら A4。
+    #[serde(default)]
+    pub paper_size: Option<String>,
+    /// ページの回転 (時計回り、0 / 90 / 180 / 270 度)。None なら 0。
+    #[serde(default)]
+    pub rotation: Option<u16>,
+    /// "fit" (セルに合わせて拡大縮小) / "actual" (原寸)。None なら fit。
+    #[serde(default)]
+    pub scaling: Option<String>,
+    /// 綴じ方向 ("right" / "left")。None なら右綴じ (右上のセルから並べる)。
+    #[serde(default)]
+    pub binding_direction: Option<String>,
+}
+
+impl ImpositionSettings {
+    fn to_options(&self) -> Result<crate::imposition::ImpositionOptions, String> {
+        use crate::imposition::{ImpositionLayout, ImpositionOptions, PageScaling};
+
+        let layout = match self.layout.as_str() {
+            "2up" => ImpositionLayout::TwoUp,
+            "4up" => ImpositionLayout::FourUp,
+            "booklet" => ImpositionLayout::Booklet,
+            other => return Err(format!("Invalid imposition layout: {}", other)),
+        };
+        let paper = self.paper_size.as_deref().unwrap_or("A4");
+        let (short_mm, long_mm) = crate::pdf::parse_trim_size(paper)
+            .ok_or_else(|| format!("Invalid paper size: {}", paper))?;
+        let scaling = match self.scaling.as_deref() {
+            None | Some("fit") => PageScaling::Fit,
+            Some("actual") => PageScaling::ActualSize,
+            Some(other) => return Err(format!("Invalid scaling: {}", other)),
+        };
+        let right_to_left = match self.binding_direction.as_deref() {
+            None | Some("right") => true,
+            Some("left") => false,
+            Some(other) => return Err(format!("Invalid binding direction: {}", other)),
+        };
+
+        let mm_to_pt = |mm: f32| mm * 72.0 / 25.4;
+        Ok(ImpositionOptions {
+            layout,
+            paper_pt: (mm_to_pt(short_mm), mm_to_pt(long_mm)),
+            rotation: self.rotation.unwrap_or(0),
+            scaling,
+            right_to_left,
+        })
+    }
+}
+
+// 新しいPDF保存用構造体（描画オーバーレイPNG方式）
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PageDrawingsV2 {
     pub page_number: usize,
     pub drawing_overlay: String,  // 描画レイヤーのBase64 PNG
@@ -89,7 +157,7 @@ pub struct PageDrawingsV2 {
     pub height: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SaveRequestV2 {
     pub pages: Vec<PageDrawingsV2>,
     pub background_images: Vec<String>,
@@ -104,6 +172,64 @@ pub struct SaveRequestV2 {
     /// 圧縮時の目標ファイルサイズ (バイト)。None なら 25MB。
     #[serde(default)]
     pub compress_target_bytes: Option<u64>,
+    /// 圧縮時、品質を下げる前に縮小する解像度 (dpi)。None なら 300。
+    /// 収まらなければさらに 200 / 150 dpi まで下げる。0 以下なら縮小しない。
+    /// 解像度メタデータのない画像は、仕上がりサイズ (指定がなければ B5) に収めたとみなして解像度を推定する。
+    #[serde(default)]
+    pub compress_target_dpi: Option<f32>,
+    /// レイヤー分離モード。true の場合は背景とオーバーレイを合成せず、
+    /// オーバーレイを SMask 付きの可逆圧縮画像として背景の上に重ねる。
+    #[serde(default)]
+    pub separate_overlay: Option<bool>,
+    /// 可逆圧縮 (FlateDecode) の圧縮レベル (0-9)。None なら 6。
+    /// 通常保存の画像と、レイヤー分離モードのオーバーレイに適用する。
+    #[serde(default)]
+    pub flate_level: Option<u32>,
+    /// モノクロ判定。true の場合は背景を 2 値 / グレースケール / カラーに分類し、
+    /// 2 値ページは 1bit の CCITT G4、グレースケールページは DeviceGray で埋め込む。
+    /// カラーのオーバーレイは合成せず別画像として重ねる (レイヤー分離モードと同じ)。
+    #[serde(default)]
+    pub detect_monochrome: Option<bool>,
+    /// true の場合は画像の解像度メタデータ (JFIF / EXIF / pHYs / TIFF) を無視し、
+    /// 従来どおり 1px = 1/72 インチでページサイズを決める。None なら解像度を使う。
+    #[serde(default)]
+    pub ignore_image_dpi: Option<bool>,
+    /// 仕上がりサイズの強制指定。"B5" / "A5" などの規格名か "182x257" (mm) 形式。
+    /// 指定時は全ページをこのサイズにし (横長のページは横向き)、画像は縦横比を保って
+    /// ページに収まる最大の大きさで中央に配置する。
+    #[serde(default)]
+    pub trim_size: Option<String>,
+    /// 見開き出力。true の場合は 2 ページを横に並べて 1 枚の PDF ページにする。
+    #[serde(default)]
+    pub spread_mode: Option<bool>,
+    /// 綴じ方向 ("right" = 右綴じ / "left" = 左綴じ)。None なら右綴じ。
+    /// 見開き出力では右綴じなら若い番号のページを右側に置く。
+    #[serde(default)]
+    pub binding_direction: Option<String>,
+    /// 見開き出力の左右ページの間隔 (mm)。None なら 0。
+    #[serde(default)]
+    pub spread_gutter_mm: Option<f32>,
+    /// 見開き出力で 1 ページ目 (表紙) を単独にするか。None なら true。
+    #[serde(default)]
+    pub spread_single_cover: Option<bool>,
+    /// 見開き出力で最終ページ (裏表紙) を単独にするか。None なら false。
+    #[serde(default)]
+    pub spread_single_back: Option<bool>,
+    /// 読み方向 (`/ViewerPreferences /Direction`)。"R2L" / "L2R"。
+    /// None なら元 PDF の設定を引き継ぎ、なければ綴じ方向から決める (右綴じなら R2L)。
+    #[serde(default)]
+    pub reading_direction: Option<String>,
+    /// ビューアのページ表示 (`/PageLayout`)。"SinglePage" / "TwoPageRight" など PDF の名前をそのまま指定する。
+    /// None なら元 PDF の設定を引き継ぎ、なければ右綴じは TwoPageRight、左綴じは TwoPageLeft
+    /// (見開き出力では 1 枚が見開きなので SinglePage)。
+    #[serde(default)]
+    pub page_layout: Option<String>,
+    /// 上書き保存の元 PDF。指定時はそのカタログの `/ViewerPreferences` と `/PageLayout` を引き継ぐ。
+    #[serde(default)]
+    pub source_pdf_path: Option<String>,
+    /// 上書き保存時に `.mojiq-backups` に残す元ファイルの世代数。None なら 5、0 ならバックアップしない。
+    #[serde(default)]
+    pub backup_count: Option<usize>,
 }
 
 /// ファイルサイズを取得する（読み込み前のサイズチェック用）
@@ -292,59 +418,49 @@ pub async fn load_files(paths: Vec<String>) -> Result<LoadedDocument, String> {
 /// 旧MojiQ ver_2.11 の electron/main.js より移植
 #[derive(Debug, Serialize)]
 pub struct DiskSpaceResult {
+    /// 保存先のファイルシステムで現在のユーザーが使える空き容量 (クォータを考慮)
     pub free_space: u64,
+    /// ファイルシステム全体の容量
+    pub total_space: u64,
     pub is_enough: bool,
 }
 
+/// 存在する最も近い祖先ディレクトリ。保存先のファイルやフォルダはまだないことがあるため。
+fn nearest_existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .find(|p| p.exists())
+}
+
 #[tauri::command]
 pub async fn check_disk_space(file_path: String, required_bytes: u64) -> Result<DiskSpaceResult, String> {
-    let path = Path::new(&file_path);
-
-    // ドライブのルートを取得
-    // 保存先のパスからルート(例: "C:\")を取り出す
-    let root = {
-        let mut ancestors = path.ancestors();
-        let mut last = path;
-        while let Some(p) = ancestors.next() {
-            if p.parent().is_none() || p == Path::new("") {
-                break;
-            }
-            last = p;
+    // statvfs (Linux / macOS) / GetDiskFreeSpaceExW (Windows) で保存先のボリュームを調べる。
+    // ネットワークマウントや UNC パス (\\server\share) もそのボリュームの値が返る
+    let stats = tokio::task::spawn_blocking(move || {
+        let path = Path::new(&file_path);
+        let dir = nearest_existing_ancestor(path).unwrap_or(path);
+        fs4::statvfs(dir)
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?;
+
+    match stats {
+        Ok(stats) => {
+            let free_space = stats.available_space();
+            // 必要容量の1.5倍のマージンを確保
+            let required_with_margin = required_bytes.saturating_add(required_bytes / 2);
+            Ok(DiskSpaceResult {
+                free_space,
+                total_space: stats.total_space(),
+                is_enough: free_space > required_with_margin,
+            })
         }
-        last.to_path_buf()
-    };
-
-    // Windows: wmicコマンドでディスク空き容量を取得
-    if cfg!(target_os = "windows") {
-        let drive_str = root.to_string_lossy();
-        // ドライブレターを抽出 (例: "C:" → "C")
-        let drive_letter = drive_str.chars().next().unwrap_or('C');
-        if !drive_letter.is_ascii_alphabetic() {
-            // 無効なドライブレター: チェックをスキップ（保存を許可）
-            return Ok(DiskSpaceResult { free_space: u64::MAX, is_enough: true });
-        }
-
-        let output = std::process::Command::new("wmic")
-            .args(["logicaldisk", "where", &format!("DeviceID='{drive_letter}:'"), "get", "FreeSpace"])
-            .output()
-            .map_err(|e| format!("wmicコマンド実行失敗: {}", e))?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = stdout.trim().lines().collect();
-        if lines.len() >= 2 {
-            if let Ok(free_space) = lines[1].trim().parse::<u64>() {
-                // 必要容量の1.5倍のマージンを確保
-                let required_with_margin = required_bytes + required_bytes / 2;
-                return Ok(DiskSpaceResult {
-                    free_space,
-                    is_enough: free_space > required_with_margin,
-                });
-            }
+        Err(e) => {
+            // 容量を取得できないファイルシステムではチェックをスキップ（保存を許可）
+            eprintln!("[MojiQ] ディスク容量を取得できません: {}", e);
+            Ok(DiskSpaceResult { free_space: u64::MAX, total_space: u64::MAX, is_enough: true })
         }
     }
-
-    // 非Windows環境またはコマンド失敗時はチェックをスキップ（保存を許可）
-    Ok(DiskSpaceResult { free_space: u64::MAX, is_enough: true })
 }
 
 #[tauri::command]
@@ -369,6 +485,57 @@ pub async fn save_pdf_v2(
     .map_err(|e| format!("Task join error: {}", e))?
 }
 
+/// 保存先ファイルの世代バックアップ (`.mojiq-backups`) を新しい順に返す。
+#[tauri::command]
+pub async fn list_backups(file_path: String) -> Result<Vec<BackupEntry>, String> {
+    tokio::task::spawn_blocking(move || {
+        crate::backup::list_backups(Path::new(&file_path)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// バックアップで保存先ファイルを置き換える。現在のファイルも世代バックアップに残すので、復元は取り消せる。
+#[tauri::command]
+pub async fn restore_backup(file_path: String, backup_path: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        crate::backup::validate_backup_path(Path::new(&file_path), Path::new(&backup_path))?;
+        crate::pdf::atomic_write_file(&file_path, crate::backup::DEFAULT_BACKUP_KEEP, |writer| {
+            let mut backup = fs::File::open(&backup_path)
+                .map_err(|e| format!("Failed to open backup: {}", e))?;
+            std::io::copy(&mut backup, writer)?;
+            Ok(())
+        })
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// PDF 保存サイズの見積もり結果 (バイト)。
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfSizeEstimate {
+    pub page_count: usize,
+    /// 実際にエンコードして測ったページ数
+    pub sampled_pages: usize,
+    /// 通常保存 (可逆圧縮) の見積もり
+    pub normal_bytes: u64,
+    /// 圧縮保存 (JPEG) の見積もり
+    pub compressed_bytes: u64,
+}
+
+/// 保存前に出力サイズを見積もる。数ページだけエンコードしてページ数で外挿するため、
+/// 全ページの保存よりずっと速い。`check_disk_space` の `required_bytes` にもこの値を使える。
+/// 見積もるのは通常保存と圧縮保存の 2 つ (レイヤー分離などの指定はリクエストに従う)。
+#[tauri::command]
+pub async fn estimate_pdf_size(request: SaveRequestV2) -> Result<PdfSizeEstimate, String> {
+    tokio::task::spawn_blocking(move || {
+        crate::pdf::estimate_pdf_size(&request).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
 #[tauri::command]
 pub async fn read_text_file(path: String) -> Result<String, String> {
     fs::read_to_string(&path).map_err(|e| e.to_string())
@@ -577,6 +744,19 @@ pub async fn load_page_image(path: String) -> Result<String, String> {
     Ok(format!("data:{};base64,{}", mime_type, image_data))
 }
 
+/// リンク方式で読み込んだ画像を監視対象に加える。外部で変更・削除・リネームされると
+/// `linked-file-changed` イベント (LinkedFileChange) が送られる。
+#[tauri::command]
+pub async fn watch_linked_files(watcher: tauri::State<'_, FileWatcher>, paths: Vec<String>) -> Result<(), String> {
+    watcher.watch(paths)
+}
+
+/// 画像を監視対象から外す (ドキュメントを閉じたときなど)。
+#[tauri::command]
+pub async fn unwatch_linked_files(watcher: tauri::State<'_, FileWatcher>, paths: Vec<String>) -> Result<(), String> {
+    watcher.unwatch(paths)
+}
+
 // ===== 校正チェック機能 =====
 
 // 校正チェック項目
@@ -678,8 +858,15 @@ pub async fn read_proofreading_check_file(
 }
 
 // 印刷用PDFを生成してシステム印刷ダイアログを開く
+// 保存と同じ SaveRequestV2 (背景 + 描画オーバーレイ) から生成するので、印刷結果は保存した PDF と一致する
+// imposition を指定すると面付けしてから印刷する (None なら 1 ページずつ)
 #[tauri::command]
-pub async fn print_pdf(request: SaveRequest) -> Result<(), String> {
+pub async fn print_pdf(
+    request: SaveRequestV2,
+    selection: Option<PageSelection>,
+    imposition: Option<ImpositionSettings>,
+    options: Option<PrintOptions>,
+) -> Result<Option<String>, String> {
     use std::env;
     use std::process::Command;
 
@@ -696,12 +883,64 @@ pub async fn print_pdf(request: SaveRequest) -> Result<(), String> {
     // 古い一時ファイルをクリーンアップ（1時間以上前のファイル）
     cleanup_old_temp_files(&temp_dir);
 
-    // PDFを生成
-    create_pdf_with_drawings(&temp_path_str, &request).map_err(|e| e.to_string())?;
+    // 印刷するページだけに絞り込む
+    let mut request = request;
+    let selected = selection
+        .unwrap_or_default()
+        .select(request.pages.len(), |i| !request.pages[i].drawing_overlay.is_empty())?;
+    if selected.len() < request.pages.len() {
+        let mut pages: Vec<Option<PageDrawingsV2>> = request.pages.into_iter().map(Some).collect();
+        let mut backgrounds: Vec<Option<String>> = request.background_images.into_iter().map(Some).collect();
+        request.pages = selected.iter().filter_map(|&i| pages[i].take()).collect();
+        request.background_images = selected
+            .iter()
+            .map(|&i| backgrounds.get_mut(i).and_then(Option::take).unwrap_or_default())
+            .collect();
+    }
+
+    // PDFを生成 (面付けが指定されていれば用紙単位に並べ直す)
+    let imposition = imposition.map(|i| i.to_options()).transpose()?;
+    let pdf_path = temp_path_str.clone();
+    tokio::task::spawn_blocking(move || -> Result<(), String> {
+        crate::pdf::create_pdf_with_overlays(&pdf_path, &request).map_err(|e| e.to_string())?;
+        if let Some(ref imposition) = imposition {
+            // 印刷用の一時ファイルなので面付け前の版はバックアップに残さない
+            crate::imposition::impose_pdf_file(&pdf_path, imposition, 0)
+                .map_err(|e| format!("面付けに失敗しました: {}", e))?;
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))??;
+
+    // Linux / macOS: 印刷オプションが指定されていれば CUPS (lp) に直接投入してジョブ ID を返す
+    #[cfg(not(target_os = "windows"))]
+    if let Some(ref options) = options {
+        let job_id = crate::printing::submit_print_job(&temp_path_str, "MojiQ Pro", options)?;
+        eprintln!("[MojiQ] 印刷ジョブを投入: {}", job_id);
+        return Ok(Some(job_id));
+    }
 
     // Windows: SumatraPDFを探して印刷ダイアログを開く、なければデフォルトビューアで開く
+    // 印刷オプションが指定されていればダイアログを出さずにそのまま印刷する (ジョブ ID は取得できない)
     #[cfg(target_os = "windows")]
     {
+        let mut sumatra_args: Vec<String> = match options {
+            Some(ref options) => {
+                let mut args = match options.printer.as_deref().filter(|p| !p.is_empty()) {
+                    Some(printer) => vec!["-print-to".to_string(), printer.to_string()],
+                    None => vec!["-print-to-default".to_string()],
+                };
+                if let Some(settings) = crate::printing::sumatra_print_settings(options)? {
+                    args.push("-print-settings".to_string());
+                    args.push(settings);
+                }
+                args
+            }
+            None => vec!["-print-dialog".to_string()],
+        };
+        sumatra_args.push(temp_path_str.clone());
+
         // SumatraPDFの可能なパス
         let local_app_data = env::var("LOCALAPPDATA").unwrap_or_default();
         let sumatra_local = format!("{}\\SumatraPDF\\SumatraPDF.exe", local_app_data);
@@ -716,7 +955,7 @@ pub async fn print_pdf(request: SaveRequest) -> Result<(), String> {
             if std::path::Path::new(sumatra_path).exists() {
                 // SumatraPDFで印刷ダイアログを開く
                 let result = Command::new(sumatra_path)
-                    .args(["-print-dialog", &temp_path_str])
+                    .args(&sumatra_args)
                     .spawn();
 
                 if result.is_ok() {
@@ -753,19 +992,451 @@ pub async fn print_pdf(request: SaveRequest) -> Result<(), String> {
             .map_err(|e| format!("Failed to open PDF: {}", e))?;
     }
 
-    Ok(())
+    Ok(None)
 }
 
-// 描画データJSONを保存
+/// 印刷先として選べるプリンターの一覧。
+/// Windows は SumatraPDF の印刷ダイアログで選ぶため空の一覧を返す。
+#[tauri::command]
+pub async fn list_printers() -> Result<Vec<PrinterInfo>, String> {
+    #[cfg(not(target_os = "windows"))]
+    {
+        tokio::task::spawn_blocking(crate::printing::list_printers)
+            .await
+            .map_err(|e| format!("Task join error: {}", e))?
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Vec::new())
+    }
+}
+
+/// 描画データJSONを保存する。壊れたデータで既存のファイルを上書きしないよう検証してから、
+/// PDF と同じく一時ファイル経由で置き換える (上書き前のファイルは世代バックアップに残す)。
 #[tauri::command]
 pub async fn save_drawing_json(path: String, data: String) -> Result<(), String> {
-    fs::write(&path, data).map_err(|e| format!("Failed to save drawing data: {}", e))
+    tokio::task::spawn_blocking(move || {
+        MojiQExportData::from_json(&data)?;
+        crate::pdf::atomic_write_file(&path, crate::backup::DEFAULT_BACKUP_KEEP, |writer| {
+            writer.write_all(data.as_bytes())?;
+            Ok(())
+        })
+        .map_err(|e| format!("Failed to save drawing data: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// 描画データJSONを読み込み、検証して最新のバージョンの形式で返す。
+/// `page_sizes` (読み込むページの大きさ) を渡すと座標をそれに合わせて拡大縮小する。
+#[tauri::command]
+pub async fn load_drawing_json(
+    path: String,
+    page_sizes: Option<BTreeMap<String, PageSize>>,
+) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        let json = fs::read_to_string(&path).map_err(|e| format!("Failed to load drawing data: {}", e))?;
+        let data = MojiQExportData::load(&json, page_sizes.as_ref())
+            .map_err(|e| format!("{}: {}", path, e))?;
+        serde_json::to_string(&data).map_err(|e| format!("Failed to serialize drawing data: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// 編集中の描画データ (MojiQExportData の JSON) をクラッシュ復旧用のジャーナルに書き出し、ジャーナル ID を返す。
+/// フロントエンドが編集のあったときに定期的に呼ぶ。
+#[tauri::command]
+pub async fn autosave_session(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AutosaveState>,
+    source_path: String,
+    data: String,
+) -> Result<String, String> {
+    let dir = crate::autosave::journal_dir(&app)?;
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || crate::autosave::write_journal(&dir, &state, &source_path, data))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// 復旧できる自動保存セッションの一覧 (新しい順)。
+#[tauri::command]
+pub async fn list_autosave_sessions(app: tauri::AppHandle) -> Result<Vec<RecoverableSession>, String> {
+    let dir = crate::autosave::journal_dir(&app)?;
+    tokio::task::spawn_blocking(move || crate::autosave::list_sessions(&dir))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// 自動保存セッションの描画データ (MojiQExportData の JSON) を読み込む。
+#[tauri::command]
+pub async fn load_autosave_session(app: tauri::AppHandle, journal_id: String) -> Result<String, String> {
+    let dir = crate::autosave::journal_dir(&app)?;
+    crate::autosave::load_session(&dir, &journal_id)
 }
 
-// 描画データJSONを読み込み
+/// 自動保存セッションを破棄する。`journal_id` を指定するとそのジャーナルだけ、
+/// `source_path` を指定するとそのファイルのジャーナルをすべて削除する (保存後に呼ぶ)。
 #[tauri::command]
-pub async fn load_drawing_json(path: String) -> Result<String, String> {
-    fs::read_to_string(&path).map_err(|e| format!("Failed to load drawing data: {}", e))
+pub async fn discard_autosave_session(
+    app: tauri::AppHandle,
+    journal_id: Option<String>,
+    source_path: Option<String>,
+) -> Result<(), String> {
+    let dir = crate::autosave::journal_dir(&app)?;
+    if let Some(journal_id) = journal_id {
+        crate::autosave::discard_session(&dir, &journal_id)?;
+    }
+    if let Some(source_path) = source_path {
+        crate::autosave::clear_sessions_for(&dir, &source_path)?;
+    }
+    Ok(())
+}
+
+/// 一括書き出しの指定。書き出し設定は保存ダイアログ (SaveRequestV2) の同名の項目と同じ意味。
+#[derive(Debug, Deserialize)]
+pub struct BatchExportRequest {
+    /// 原稿を探すフォルダ
+    pub root: String,
+    /// 書き出し先。None ならルートフォルダの中の「書き出し」
+    #[serde(default)]
+    pub out_dir: Option<String>,
+    /// 同時に書き出す数。None なら CPU 数 (最大 4)
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    #[serde(default)]
+    pub compress_target_bytes: Option<u64>,
+    #[serde(default)]
+    pub compress_target_dpi: Option<f32>,
+    #[serde(default)]
+    pub trim_size: Option<String>,
+    #[serde(default)]
+    pub spread_mode: Option<bool>,
+    #[serde(default)]
+    pub binding_direction: Option<String>,
+    #[serde(default)]
+    pub separate_overlay: Option<bool>,
+    #[serde(default)]
+    pub detect_monochrome: Option<bool>,
+    /// PDF 注釈由来のコメント (_コメント.json) を書き出さない
+    #[serde(default)]
+    pub hide_comments: Option<bool>,
+    /// 文字の描画に使うフォントファイル。None ならシステムの日本語フォント
+    #[serde(default)]
+    pub font_path: Option<String>,
+    /// 線幅・文字サイズの倍率。None なら画面表示の倍率から見積もる
+    #[serde(default)]
+    pub line_scale: Option<f32>,
+    #[serde(default)]
+    pub backup_count: Option<usize>,
+}
+
+impl BatchExportRequest {
+    fn settings(&self) -> ExportSettings {
+        ExportSettings {
+            compress_target_bytes: self.compress_target_bytes,
+            compress_target_dpi: self.compress_target_dpi,
+            trim_size: self.trim_size.clone(),
+            spread_mode: self.spread_mode.unwrap_or(false),
+            binding_direction: self.binding_direction.clone(),
+            separate_overlay: self.separate_overlay.unwrap_or(false),
+            detect_monochrome: self.detect_monochrome.unwrap_or(false),
+            hide_comments: self.hide_comments.unwrap_or(false),
+            font: self.font_path.as_ref().map(PathBuf::from),
+            line_scale: self.line_scale,
+            backup_count: self.backup_count,
+        }
+    }
+
+    fn out_dir(&self) -> PathBuf {
+        match &self.out_dir {
+            Some(out_dir) => PathBuf::from(out_dir),
+            None => crate::batch::default_out_dir(Path::new(&self.root)),
+        }
+    }
+}
+
+/// 一括書き出しの対象を探す (書き出す前の確認用)。描画データのない原稿も含む。
+#[tauri::command]
+pub async fn scan_batch_folder(root: String, out_dir: Option<String>) -> Result<Vec<BatchItem>, String> {
+    let out_dir = out_dir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| crate::batch::default_out_dir(Path::new(&root)));
+    tokio::task::spawn_blocking(move || crate::batch::discover(Path::new(&root), &out_dir))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// フォルダ以下の原稿をまとめて書き出す。1 件終わるごとに batch-export-progress イベントを送る。
+#[tauri::command]
+pub async fn batch_export(app: tauri::AppHandle, request: BatchExportRequest) -> Result<BatchReport, String> {
+    use tauri::Emitter;
+
+    tokio::task::spawn_blocking(move || {
+        let settings = request.settings();
+        if let Some(trim_size) = &settings.trim_size {
+            crate::pdf::parse_trim_size(trim_size).ok_or_else(|| format!("仕上がりサイズが不正です: {}", trim_size))?;
+        }
+        let font = crate::overlay_render::load_font(settings.font.as_deref())?;
+        let root = PathBuf::from(&request.root);
+        let out_dir = request.out_dir();
+        let items = crate::batch::discover(&root, &out_dir)?;
+        let jobs = request.jobs.unwrap_or_else(crate::cli::default_jobs);
+
+        Ok(crate::batch::run(&root, &out_dir, items, &settings, font.as_ref(), jobs, |completed, total, result| {
+            let progress = BatchProgress { completed, total, result: result.clone() };
+            let _ = app.emit(crate::batch::PROGRESS_EVENT, progress);
+        }))
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// 新しいプロジェクト (.mojiq) を作る。元原稿は埋め込むか参照するかを選べる。
+#[tauri::command]
+pub async fn create_project(path: String, content: ProjectContent) -> Result<ProjectManifest, String> {
+    tokio::task::spawn_blocking(move || crate::project::create(Path::new(&path), content))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// プロジェクトを開く。埋め込みの元原稿はキャッシュフォルダに展開し、そのパスを返す。
+#[tauri::command]
+pub async fn open_project(app: tauri::AppHandle, path: String) -> Result<OpenedProject, String> {
+    let extract_to = crate::project::extract_dir(&app, Path::new(&path))?;
+    tokio::task::spawn_blocking(move || crate::project::open(Path::new(&path), &extract_to))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// プロジェクトを上書き保存する。指定しなかった項目 (元原稿など) は今の内容を残す。
+#[tauri::command]
+pub async fn save_project(path: String, content: ProjectContent) -> Result<ProjectManifest, String> {
+    tokio::task::spawn_blocking(move || crate::project::save(Path::new(&path), content))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// プロジェクトが壊れていないか、参照している元原稿があるかを調べる。
+#[tauri::command]
+pub async fn validate_project(path: String) -> Result<ProjectValidation, String> {
+    tokio::task::spawn_blocking(move || crate::project::validate(Path::new(&path)))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// 描画データの 3-way マージの結果。
+#[derive(Debug, Serialize)]
+pub struct DrawingMergeResult {
+    /// マージした描画データ (MojiQExportData の JSON)
+    pub data: String,
+    /// 競合。競合したオブジェクトは conflict_layer_id のレイヤーに置いてある
+    pub conflicts: Vec<MergeConflict>,
+    pub conflict_layer_id: String,
+    pub stats: MergeStats,
+}
+
+/// 共通の元 (base) と 2 人分の描画データ JSON (ours / theirs) を 3-way マージする。
+/// `out_path` を指定すると結果をそこに保存する (上書き前のファイルは世代バックアップに残す)。
+#[tauri::command]
+pub async fn merge_drawing_json(
+    base_path: String,
+    ours_path: String,
+    theirs_path: String,
+    out_path: Option<String>,
+) -> Result<DrawingMergeResult, String> {
+    tokio::task::spawn_blocking(move || {
+        let read = |path: &str| -> Result<MojiQExportData, String> {
+            let json = fs::read_to_string(path).map_err(|e| format!("Failed to load drawing data: {}: {}", path, e))?;
+            MojiQExportData::from_json(&json).map_err(|e| format!("{}: {}", path, e))
+        };
+        let result = crate::merge::merge(read(&base_path)?, read(&ours_path)?, read(&theirs_path)?);
+        let data = serde_json::to_string_pretty(&result.merged)
+            .map_err(|e| format!("Failed to serialize drawing data: {}", e))?;
+        if let Some(out_path) = out_path {
+            crate::pdf::atomic_write_file(&out_path, crate::backup::DEFAULT_BACKUP_KEEP, |writer| {
+                writer.write_all(data.as_bytes())?;
+                Ok(())
+            })
+            .map_err(|e| format!("Failed to save drawing data: {}", e))?;
+        }
+        Ok(DrawingMergeResult {
+            data,
+            conflicts: result.conflicts,
+            conflict_layer_id: crate::merge::CONFLICT_LAYER_ID.to_string(),
+            stats: result.stats,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// ページ画像の差分のリクエスト。
+#[derive(Debug, Deserialize)]
+pub struct PageDiffRequest {
+    /// 前の版 (画像か、スキャン画像の PDF)。PDF は画像だけのページにのみ対応し、
+    /// 画像の上に文字や線を描いたページ (写植をベクターで重ねたものなど) はエラーになる
+    pub old_path: String,
+    /// PDF のときのページ (0 始まり、既定 0)
+    #[serde(default)]
+    pub old_page: Option<usize>,
+    /// 新しい版 (old_path と同じく、画像か画像だけのページの PDF)
+    pub new_path: String,
+    #[serde(default)]
+    pub new_page: Option<usize>,
+    /// 明るさの差のしきい値 (0-255)
+    #[serde(default)]
+    pub threshold: Option<u8>,
+    /// 位置のずれを許す距離 (解析画像の px)
+    #[serde(default)]
+    pub tolerance: Option<u32>,
+    /// 差分の透過 PNG の保存先 (省略時は data URL で返すだけ)
+    #[serde(default)]
+    pub overlay_path: Option<String>,
+}
+
+/// ページ画像の差分の結果。
+#[derive(Debug, Serialize)]
+pub struct PageDiffResult {
+    #[serde(flatten)]
+    pub diff: PageDiff,
+    /// 変更箇所を色付けした透過 PNG の data URL (前の版のページ全体に重ねる)
+    pub overlay: String,
+}
+
+/// 2 つの版のページ画像を位置合わせして比べ、変わった範囲と差分の透過 PNG を返す。
+#[tauri::command]
+pub async fn diff_page_images(request: PageDiffRequest) -> Result<PageDiffResult, String> {
+    tokio::task::spawn_blocking(move || {
+        let old = crate::image_diff::load_page(&request.old_path, request.old_page.unwrap_or(0))?;
+        let new = crate::image_diff::load_page(&request.new_path, request.new_page.unwrap_or(0))?;
+        let defaults = DiffOptions::default();
+        let options = DiffOptions {
+            threshold: request.threshold.unwrap_or(defaults.threshold),
+            tolerance: request.tolerance.unwrap_or(defaults.tolerance),
+        };
+        let diff = crate::image_diff::diff(&old, &new, &options);
+        let png = crate::image_diff::encode_png(&diff.overlay_image)?;
+        if let Some(overlay_path) = &request.overlay_path {
+            fs::write(overlay_path, &png).map_err(|e| format!("Failed to save diff image: {}", e))?;
+        }
+        Ok(PageDiffResult { diff, overlay: format!("data:image/png;base64,{}", BASE64.encode(&png)) })
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// ページ画像の位置合わせのリクエスト。
+#[derive(Debug, Deserialize)]
+pub struct PageAlignRequest {
+    /// 基準にする前の版 (画像か、スキャン画像の PDF)
+    pub reference_path: String,
+    /// PDF のときのページ (0 始まり、既定 0)
+    #[serde(default)]
+    pub reference_page: Option<usize>,
+    /// 合わせる新しい版
+    pub moving_path: String,
+    #[serde(default)]
+    pub moving_page: Option<usize>,
+    /// 合わせた画像 (PNG) の保存先。省略時は data URL で返す
+    #[serde(default)]
+    pub warped_path: Option<String>,
+}
+
+/// ページ画像の位置合わせの結果。
+#[derive(Debug, Serialize)]
+pub struct PageAlignResult {
+    #[serde(flatten)]
+    pub alignment: Alignment,
+    /// 合わせた画像の大きさ (前の版の画像と同じ, px)
+    pub width: u32,
+    pub height: u32,
+    /// 新しい版を前の版の位置に合わせた画像の data URL (warped_path を指定したときは None)
+    pub warped: Option<String>,
+}
+
+/// 新しい版のページを前の版に合わせる相似変換 (拡大・回転・平行移動) を求め、合わせた画像を返す。
+#[tauri::command]
+pub async fn align_page_images(request: PageAlignRequest) -> Result<PageAlignResult, String> {
+    tokio::task::spawn_blocking(move || {
+        let reference = crate::image_diff::load_page(&request.reference_path, request.reference_page.unwrap_or(0))?;
+        let moving = crate::image_diff::load_page(&request.moving_path, request.moving_page.unwrap_or(0))?;
+        let alignment = crate::alignment::align(&reference, &moving);
+        let (width, height) = (reference.width(), reference.height());
+        let warped = crate::alignment::warp(&moving, &alignment.transform, width, height);
+        let png = crate::image_diff::encode_png(&warped)?;
+        let warped = match &request.warped_path {
+            Some(warped_path) => {
+                fs::write(warped_path, &png).map_err(|e| format!("Failed to save aligned image: {}", e))?;
+                None
+            }
+            None => Some(format!("data:image/png;base64,{}", BASE64.encode(&png))),
+        };
+        Ok(PageAlignResult { alignment, width, height, warped })
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+/// 描画の引き継ぎのリクエスト。
+#[derive(Debug, Deserialize)]
+pub struct CarryForwardRequest {
+    /// 前の版の描画データ JSON
+    pub drawing_path: String,
+    /// 前の版のページ (スキャン画像の PDF 1 つ、またはページ順の画像)
+    pub old_inputs: Vec<String>,
+    /// 新しい版のページ (同上)
+    pub new_inputs: Vec<String>,
+    /// 引き継いだ描画データの保存先 (省略時は返すだけ)
+    #[serde(default)]
+    pub out_path: Option<String>,
+}
+
+/// 描画の引き継ぎの結果。
+#[derive(Debug, Serialize)]
+pub struct CarryForwardResult {
+    /// 新しい版のページに合わせた描画データ (MojiQExportData の JSON)
+    pub data: String,
+    /// 前の版と新しい版のページの対応 (新しい版の順)
+    pub pages: Vec<PageCarry>,
+    /// 削除されたページにあったもの・ページの外に出たもの
+    pub flagged: Vec<FlaggedObject>,
+}
+
+/// 前の版の描画データを新しい版のページに引き継ぐ。
+/// ページごとに位置を合わせて座標を写し、差し込み・削除されたページは pages と flagged で知らせる。
+#[tauri::command]
+pub async fn carry_forward_drawings(request: CarryForwardRequest) -> Result<CarryForwardResult, String> {
+    tokio::task::spawn_blocking(move || {
+        let json = fs::read_to_string(&request.drawing_path)
+            .map_err(|e| format!("Failed to load drawing data: {}: {}", request.drawing_path, e))?;
+        let drawing = MojiQExportData::from_json(&json).map_err(|e| format!("{}: {}", request.drawing_path, e))?;
+        let load = |inputs: &[String]| {
+            let inputs: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            crate::cli::load_source_pages(&inputs).map(|(pages, _)| pages)
+        };
+        let old_pages = load(&request.old_inputs).map_err(|e| format!("前の版: {}", e))?;
+        let new_pages = load(&request.new_inputs).map_err(|e| format!("新しい版: {}", e))?;
+
+        let result = crate::carry_forward::carry_forward(&drawing, &old_pages, &new_pages)?;
+        let data = serde_json::to_string_pretty(&result.data)
+            .map_err(|e| format!("Failed to serialize drawing data: {}", e))?;
+        if let Some(out_path) = request.out_path {
+            crate::pdf::atomic_write_file(&out_path, crate::backup::DEFAULT_BACKUP_KEEP, |writer| {
+                writer.write_all(data.as_bytes())?;
+                Ok(())
+            })
+            .map_err(|e| format!("Failed to save drawing data: {}", e))?;
+        }
+        if !result.flagged.is_empty() {
+            eprintln!("[MojiQ] 引き継げなかった・ページの外に出た描画: {} 件", result.flagged.len());
+        }
+        Ok(CarryForwardResult { data, pages: result.pages, flagged: result.flagged })
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
 }
 
 /// レジストリのフォントキー名からCSSフォントファミリー名を抽出する。
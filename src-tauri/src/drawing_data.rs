@@ -0,0 +1,684 @@
+// 描画データ JSON (フロントエンドの MojiQExportData、`_描画.json`) の型。
+// フロントエンドの drawingExportImport.ts の ExportedObject と対応させる。
+// 読み込み時に検証し (壊れた箇所を JSON のパスで示す)、古いバージョンを最新の形式に移行する。
+//   1.0: ページサイズなし (座標は読み込んだときの表示サイズのまま)
+//   1.1: pageSizes を追加。座標は pageSizes が基準で、読み込むページの大きさに合わせて拡大縮小する
+//   1.2: checkedState (校正チェックの確認済み状態) を追加
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// 最新のバージョン (drawingExportImport.ts の VERSION)。
+pub const CURRENT_VERSION: &str = "1.2";
+
+/// 読み込めるバージョン。これより新しいものはアプリの更新が必要。
+const SUPPORTED_VERSIONS: &[&str] = &["1.0", "1.1", "1.2"];
+
+/// 検証エラーを列挙する上限。
+const MAX_REPORTED_ERRORS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MojiQExportData {
+    /// "1.0" / "1.1" / "1.2"
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exported_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+    /// 描画時のページサイズ (v1.1 以降)。座標はこのサイズが基準
+    #[serde(default)]
+    pub page_sizes: BTreeMap<String, PageSize>,
+    /// 校正チェック済み状態 (v1.2 以降)。書き出しでは使わないのでそのまま保持する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_state: Option<serde_json::Value>,
+    /// ページ番号 (読み込み時の 0 始まり) ごとのオブジェクト
+    pub data: BTreeMap<String, Vec<ExportedObject>>,
+    /// このモデルにない項目。書き戻すときに失わないよう保持する
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PageSize {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LeaderLine {
+    pub start: Point,
+    pub end: Point,
+}
+
+/// 図形に付けるテキスト指示 (引出線 + テキスト)。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    pub font_size: f64,
+    #[serde(default)]
+    pub is_vertical: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+    /// "left" / "right"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub align: Option<String>,
+    pub leader_line: LeaderLine,
+}
+
+/// フォント指定枠線のラベル。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontLabel {
+    pub font_name: String,
+    pub text_x: f64,
+    pub text_y: f64,
+    /// "left" / "right"
+    pub text_align: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectType {
+    Stroke,
+    Shape,
+    Text,
+    Image,
+    /// 新しいバージョンで追加された種類 (描画しない)。書き戻せるよう名前を保持する
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+/// ページ上の 1 つのオブジェクト (ストローク・図形・テキスト・画像をフラットにしたもの)。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedObject {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: ObjectType,
+    #[serde(default)]
+    pub layer_id: String,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+
+    // ストローク
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub points: Option<Vec<Point>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_marker: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+
+    // 図形
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_pos: Option<Point>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_pos: Option<Point>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<Annotation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leader_line: Option<LeaderLine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_label: Option<FontLabel>,
+    /// 回転 (ラジアン)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<f64>,
+    /// "vertical" / "horizontal" (半円・くの字・コの字)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<String>,
+    /// L 字の向き (0: 右下, 1: 左下, 2: 右上, 3: 左上)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flipped: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated: Option<bool>,
+
+    // テキスト
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_vertical: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdf_annotation_source: Option<String>,
+
+    // 画像 (Base64 の data URL)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_data: Option<String>,
+
+    /// このモデルにない項目 (新しいバージョンのフロントエンドが追加したものなど)
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn default_color() -> String {
+    "#000000".to_string()
+}
+
+impl Point {
+    fn mapped(self, map: &impl Fn(f64, f64) -> (f64, f64)) -> Point {
+        let (x, y) = map(self.x, self.y);
+        Point { x, y, pressure: self.pressure }
+    }
+}
+
+impl LeaderLine {
+    fn mapped(self, map: &impl Fn(f64, f64) -> (f64, f64)) -> LeaderLine {
+        LeaderLine { start: self.start.mapped(map), end: self.end.mapped(map) }
+    }
+}
+
+impl ExportedObject {
+    /// 座標を拡大縮小する (drawingExportImport.ts の scaleObjectCoordinates と同じ)。
+    /// 線幅 (ストロークのみ)・文字サイズ・スタンプの大きさは縦横の小さい方の倍率にする。
+    pub fn scale(&mut self, scale_x: f64, scale_y: f64) {
+        self.transform(|x, y| (x * scale_x, y * scale_y), scale_x.min(scale_y), 0.0);
+    }
+
+    /// すべての座標を `map` で写し、線幅 (ストロークのみ)・文字サイズ・スタンプの大きさを `length_scale` 倍する。
+    /// `rotation` は `map` に含まれる回転 (ラジアン、時計回りが正)。中心で回転して描く図形・画像は、
+    /// 中心だけを写して大きさを `length_scale` 倍し、回転は `rotation` フィールドに足す。
+    pub fn transform(&mut self, map: impl Fn(f64, f64) -> (f64, f64), length_scale: f64, rotation: f64) {
+        // スタンプは始点に回転せずに描くので、始点を写すだけにする
+        let rotates_around_center = matches!(self.object_type, ObjectType::Shape | ObjectType::Image)
+            && self.stamp_type.is_none()
+            && rotation != 0.0;
+        match (&mut self.start_pos, &mut self.end_pos) {
+            (Some(start), Some(end)) if rotates_around_center => {
+                let (cx, cy) = map((start.x + end.x) / 2.0, (start.y + end.y) / 2.0);
+                let (hx, hy) = ((end.x - start.x) / 2.0 * length_scale, (end.y - start.y) / 2.0 * length_scale);
+                *start = Point { x: cx - hx, y: cy - hy, pressure: None };
+                *end = Point { x: cx + hx, y: cy + hy, pressure: None };
+                self.rotation = Some(self.rotation.unwrap_or(0.0) + rotation);
+            }
+            (start, end) => {
+                for pos in [start, end].into_iter().flatten() {
+                    // 始点・終点は筆圧を持たない
+                    *pos = Point { pressure: None, ..pos.mapped(&map) };
+                }
+            }
+        }
+        if let Some(points) = &mut self.points {
+            for point in points {
+                *point = point.mapped(&map);
+            }
+        }
+        // 片方しかない座標は写せない (もう片方を 0 とみなすと位置がずれる) ので、そのまま残す
+        if let (Some(x), Some(y)) = (self.x, self.y) {
+            let (x, y) = map(x, y);
+            (self.x, self.y) = (Some(x), Some(y));
+        }
+        if let Some(leader_line) = &mut self.leader_line {
+            *leader_line = leader_line.mapped(&map);
+        }
+        if let Some(font_label) = &mut self.font_label {
+            (font_label.text_x, font_label.text_y) = map(font_label.text_x, font_label.text_y);
+        }
+        if let Some(annotation) = &mut self.annotation {
+            (annotation.x, annotation.y) = map(annotation.x, annotation.y);
+            annotation.font_size *= length_scale;
+            annotation.leader_line = annotation.leader_line.mapped(&map);
+        }
+        if self.object_type == ObjectType::Stroke {
+            if let Some(width) = &mut self.width {
+                *width *= length_scale;
+            }
+        }
+        if let Some(font_size) = &mut self.font_size {
+            *font_size *= length_scale;
+        }
+        if let Some(size) = &mut self.size {
+            *size *= length_scale;
+        }
+    }
+
+    /// 代表点 (位置を調べるための点)。テキストは (x, y)、それ以外は始点か最初の点。
+    pub fn anchor(&self) -> Option<(f64, f64)> {
+        if let (Some(x), Some(y)) = (self.x, self.y) {
+            return Some((x, y));
+        }
+        self.start_pos
+            .or_else(|| self.points.as_ref().and_then(|points| points.first().copied()))
+            .map(|p| (p.x, p.y))
+    }
+}
+
+impl MojiQExportData {
+    /// JSON を読み込んで検証する。構文エラーは行・列、内容の誤りは JSON のパス (`data["3"][5].points[2].x`) で示す。
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(json).map_err(|e| {
+            format!("描画データの JSON が不正です ({} 行 {} 列): {}", e.line(), e.column(), e)
+        })?;
+        let errors = validate(&value);
+        if !errors.is_empty() {
+            return Err(format!("描画データが不正です:\n{}", errors.join("\n")));
+        }
+        serde_json::from_value(value).map_err(|e| format!("描画データを読み込めません: {}", e))
+    }
+
+    /// 読み込んで最新のバージョンに移行する。`page_sizes` (読み込むページの大きさ) を渡すと座標をそれに合わせる。
+    pub fn load(json: &str, page_sizes: Option<&BTreeMap<String, PageSize>>) -> Result<Self, String> {
+        let mut data = Self::from_json(json)?;
+        data.migrate(page_sizes);
+        Ok(data)
+    }
+
+    /// 最新のバージョンの形式にする。
+    /// `page_sizes` があれば座標をそのページの大きさに拡大縮小し (1.1 以降、scaleImportData と同じ)、
+    /// pageSizes をそれに置き換える。1.0 は座標が読み込むページの大きさのままなので、そのサイズを基準として記録する。
+    pub fn migrate(&mut self, page_sizes: Option<&BTreeMap<String, PageSize>>) {
+        if self.version == "1.0" {
+            // 1.0 の pageSizes は基準ではないので使わない
+            self.page_sizes.clear();
+        }
+        if let Some(page_sizes) = page_sizes {
+            for (page, objects) in &mut self.data {
+                let Some(&current) = page_sizes.get(page) else {
+                    continue;
+                };
+                match self.page_sizes.get(page) {
+                    Some(saved) if *saved != current => {
+                        let (scale_x, scale_y) = (current.width / saved.width, current.height / saved.height);
+                        for object in objects.iter_mut() {
+                            object.scale(scale_x, scale_y);
+                        }
+                    }
+                    // サイズ情報がない、または同じ大きさならそのまま
+                    _ => {}
+                }
+                self.page_sizes.insert(page.clone(), current);
+            }
+        }
+        // pageCount がない古いデータはそのまま None にする (data.len() は描画のあるページ数で、文書のページ数ではない)
+        self.version = CURRENT_VERSION.to_string();
+    }
+
+    /// ページ (読み込み時の 0 始まりの番号) のオブジェクト。
+    pub fn page_objects(&self, page_index: usize) -> &[ExportedObject] {
+        self.data.get(&page_index.to_string()).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// 座標の基準になるページサイズ。v1.0 以前やサイズ情報がなければ None (スケーリングしない)。
+    pub fn page_size(&self, page_index: usize) -> Option<PageSize> {
+        if self.version == "1.0" {
+            return None;
+        }
+        self.page_sizes.get(&page_index.to_string()).copied()
+    }
+}
+
+/// "1.2" を (1, 2) にする。
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// `object[key]`。null は省略と同じに扱う (serde の Option と同じ)。
+fn non_null<'a>(object: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    object.get(key).filter(|value| !value.is_null())
+}
+
+/// 値の型の判定 (Value::is_string など)。
+type ValueCheck = fn(&Value) -> bool;
+
+/// JSON の値を検証し、誤りを JSON のパス付きで返す (最大 MAX_REPORTED_ERRORS 件)。
+fn validate(value: &Value) -> Vec<String> {
+    let mut v = Validator::default();
+    let Some(root) = v.object(value, "$") else {
+        return v.errors;
+    };
+
+    match root.get("version") {
+        Some(Value::String(version)) => {
+            let latest = parse_version(CURRENT_VERSION);
+            if parse_version(version).is_some_and(|parsed| Some(parsed) > latest) {
+                v.error("version", &format!(
+                    "新しいバージョン ({}) の描画データです。アプリを更新してください",
+                    version
+                ));
+            } else if !SUPPORTED_VERSIONS.contains(&version.as_str()) {
+                v.error("version", &format!("対応していないバージョンです: {}", version));
+            }
+        }
+        Some(_) => v.error("version", "文字列ではありません"),
+        None => v.error("version", "バージョン情報がありません。MojiQ の描画データではない可能性があります"),
+    }
+    v.optional_string(root, "exportedAt", "exportedAt");
+    if let Some(count) = non_null(root, "pageCount") {
+        if count.as_u64().is_none() {
+            v.error("pageCount", "0 以上の整数ではありません");
+        }
+    }
+
+    if let Some(sizes) = root.get("pageSizes") {
+        if let Some(sizes) = v.object(sizes, "pageSizes") {
+            for (page, size) in sizes {
+                let path = format!("pageSizes[{:?}]", page);
+                v.page_key(page, &path);
+                if let Some(size) = v.object(size, &path) {
+                    for key in ["width", "height"] {
+                        let field = format!("{}.{}", path, key);
+                        match size.get(key).and_then(Value::as_f64) {
+                            Some(n) if n > 0.0 && n.is_finite() => {}
+                            Some(_) => v.error(&field, "正の数ではありません"),
+                            None => v.error(&field, "数値がありません"),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(checked) = non_null(root, "checkedState") {
+        if let Some(checked) = v.object(checked, "checkedState") {
+            let lists: [(&str, ValueCheck); 3] = [
+                ("checkedComments", Value::is_number),
+                ("checkedCorrectnessItems", Value::is_string),
+                ("checkedProposalItems", Value::is_string),
+            ];
+            for (key, is_valid) in lists {
+                let path = format!("checkedState.{}", key);
+                if let Some(items) = checked.get(key).and_then(|items| v.array(items, &path)) {
+                    if let Some(index) = items.iter().position(|item| !is_valid(item)) {
+                        v.error(&format!("{}[{}]", path, index), "値の型が違います");
+                    }
+                }
+            }
+        }
+    }
+
+    match root.get("data") {
+        Some(data) => {
+            if let Some(pages) = v.object(data, "data") {
+                for (page, objects) in pages {
+                    let path = format!("data[{:?}]", page);
+                    v.page_key(page, &path);
+                    if let Some(objects) = v.array(objects, &path) {
+                        for (index, object) in objects.iter().enumerate() {
+                            v.exported_object(object, &format!("{}[{}]", path, index));
+                        }
+                    }
+                }
+            }
+        }
+        None => v.error("data", "描画データが含まれていません"),
+    }
+
+    v.errors
+}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<String>,
+}
+
+impl Validator {
+    fn error(&mut self, path: &str, message: &str) {
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(format!("{}: {}", path, message));
+        } else if self.errors.len() == MAX_REPORTED_ERRORS {
+            self.errors.push("...(以降のエラーは省略)".to_string());
+        }
+    }
+
+    fn object<'a>(&mut self, value: &'a Value, path: &str) -> Option<&'a Map<String, Value>> {
+        let object = value.as_object();
+        if object.is_none() {
+            self.error(path, "オブジェクトではありません");
+        }
+        object
+    }
+
+    fn array<'a>(&mut self, value: &'a Value, path: &str) -> Option<&'a Vec<Value>> {
+        let array = value.as_array();
+        if array.is_none() {
+            self.error(path, "配列ではありません");
+        }
+        array
+    }
+
+    /// ページ番号のキーは 0 以上の整数。
+    fn page_key(&mut self, key: &str, path: &str) {
+        if key.parse::<usize>().is_err() {
+            self.error(path, "ページ番号が整数ではありません");
+        }
+    }
+
+    fn number(&mut self, object: &Map<String, Value>, key: &str, path: &str, required: bool) {
+        match non_null(object, key) {
+            Some(value) if value.as_f64().is_some_and(f64::is_finite) => {}
+            Some(_) => self.error(&format!("{}.{}", path, key), "数値ではありません"),
+            None if required => self.error(&format!("{}.{}", path, key), "数値がありません"),
+            None => {}
+        }
+    }
+
+    fn string(&mut self, object: &Map<String, Value>, key: &str, path: &str, required: bool) {
+        match non_null(object, key) {
+            Some(Value::String(_)) => {}
+            Some(_) => self.error(&format!("{}.{}", path, key), "文字列ではありません"),
+            None if required => self.error(&format!("{}.{}", path, key), "文字列がありません"),
+            None => {}
+        }
+    }
+
+    fn optional_string(&mut self, object: &Map<String, Value>, key: &str, path: &str) {
+        if let Some(value) = non_null(object, key) {
+            if !value.is_string() {
+                self.error(path, "文字列ではありません");
+            }
+        }
+    }
+
+    fn boolean(&mut self, object: &Map<String, Value>, key: &str, path: &str) {
+        if non_null(object, key).is_some_and(|value| !value.is_boolean()) {
+            self.error(&format!("{}.{}", path, key), "真偽値ではありません");
+        }
+    }
+
+    fn point(&mut self, value: &Value, path: &str) {
+        if let Some(point) = self.object(value, path) {
+            self.number(point, "x", path, true);
+            self.number(point, "y", path, true);
+            self.number(point, "pressure", path, false);
+        }
+    }
+
+    /// `object[key]` の点。なければ `required` のときだけエラー。
+    fn point_field(&mut self, object: &Map<String, Value>, key: &str, path: &str, required: bool) {
+        let field = format!("{}.{}", path, key);
+        match non_null(object, key) {
+            Some(value) => self.point(value, &field),
+            None if required => self.error(&field, "座標がありません"),
+            None => {}
+        }
+    }
+
+    fn leader_line(&mut self, value: &Value, path: &str) {
+        if let Some(line) = self.object(value, path) {
+            self.point_field(line, "start", path, true);
+            self.point_field(line, "end", path, true);
+        }
+    }
+
+    fn exported_object(&mut self, value: &Value, path: &str) {
+        let Some(object) = self.object(value, path) else {
+            return;
+        };
+        self.string(object, "id", path, true);
+        self.string(object, "type", path, true);
+        for key in ["layerId", "color", "shapeType", "stampType", "label", "text", "pdfAnnotationSource", "imageData"] {
+            self.string(object, key, path, false);
+        }
+        for key in ["width", "opacity", "size", "rotation", "x", "y", "fontSize"] {
+            self.number(object, key, path, false);
+        }
+        for key in ["isMarker", "isVertical", "flipped", "rotated"] {
+            self.boolean(object, key, path);
+        }
+
+        if let Some(points) = non_null(object, "points") {
+            let points_path = format!("{}.points", path);
+            if let Some(points) = self.array(points, &points_path) {
+                for (index, point) in points.iter().enumerate() {
+                    self.point(point, &format!("{}[{}]", points_path, index));
+                }
+            }
+        }
+        if let Some(line) = non_null(object, "leaderLine") {
+            self.leader_line(line, &format!("{}.leaderLine", path));
+        }
+        if let Some(annotation) = non_null(object, "annotation") {
+            let annotation_path = format!("{}.annotation", path);
+            if let Some(annotation) = self.object(annotation, &annotation_path) {
+                self.string(annotation, "text", &annotation_path, true);
+                self.number(annotation, "x", &annotation_path, true);
+                self.number(annotation, "y", &annotation_path, true);
+                self.number(annotation, "fontSize", &annotation_path, true);
+                match annotation.get("leaderLine") {
+                    Some(line) => self.leader_line(line, &format!("{}.leaderLine", annotation_path)),
+                    None => self.error(&format!("{}.leaderLine", annotation_path), "引出線がありません"),
+                }
+            }
+        }
+        if let Some(label) = non_null(object, "fontLabel") {
+            let label_path = format!("{}.fontLabel", path);
+            if let Some(label) = self.object(label, &label_path) {
+                self.string(label, "fontName", &label_path, true);
+                self.number(label, "textX", &label_path, true);
+                self.number(label, "textY", &label_path, true);
+                self.string(label, "textAlign", &label_path, true);
+            }
+        }
+        if let Some(direction) = non_null(object, "direction") {
+            if direction.as_u64().is_none_or(|d| d > 3) {
+                self.error(&format!("{}.direction", path), "0 から 3 の整数ではありません");
+            }
+        }
+
+        // 欠けた座標などはフロントエンドの exportedObjectTo* が既定値で補うので、あるものの形だけを見る
+        self.point_field(object, "startPos", path, false);
+        self.point_field(object, "endPos", path, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(entries: &[(&str, f64, f64)]) -> BTreeMap<String, PageSize> {
+        entries
+            .iter()
+            .map(|&(page, width, height)| (page.to_string(), PageSize { width, height }))
+            .collect()
+    }
+
+    fn stroke(x: f64, y: f64) -> String {
+        format!(r#"{{"id": "s", "type": "stroke", "width": 4, "points": [{{"x": {}, "y": {}}}]}}"#, x, y)
+    }
+
+    #[test]
+    fn rejects_future_version() {
+        let error = MojiQExportData::from_json(r#"{"version": "1.3", "data": {}}"#).unwrap_err();
+        assert!(error.contains("version: 新しいバージョン (1.3)"), "{}", error);
+        let error = MojiQExportData::from_json(r#"{"version": "2.0", "data": {}}"#).unwrap_err();
+        assert!(error.contains("アプリを更新してください"), "{}", error);
+        let error = MojiQExportData::from_json(r#"{"version": "0.9", "data": {}}"#).unwrap_err();
+        assert!(error.contains("対応していないバージョンです: 0.9"), "{}", error);
+    }
+
+    #[test]
+    fn reports_json_paths() {
+        let objects: Vec<String> = (0..5).map(|i| stroke(i as f64, 0.0)).collect();
+        let json = format!(
+            r#"{{"version": "1.2", "data": {{"3": [{}, {{"id": "s", "type": "stroke", "points": [{{"x": 1, "y": 1}}, {{"x": 2, "y": 2}}, {{"x": "3", "y": 3}}]}}]}}}}"#,
+            objects.join(", ")
+        );
+        let error = MojiQExportData::from_json(&json).unwrap_err();
+        assert_eq!(error, "描画データが不正です:\ndata[\"3\"][5].points[2].x: 数値ではありません");
+
+        let error = MojiQExportData::from_json(r#"{"version": "1.2", "data": {"x": []}, "pageSizes": {"0": {"width": 0, "height": 10}}}"#)
+            .unwrap_err();
+        assert!(error.contains("pageSizes[\"0\"].width: 正の数ではありません"), "{}", error);
+        assert!(error.contains("data[\"x\"]: ページ番号が整数ではありません"), "{}", error);
+
+        let error = MojiQExportData::from_json("{\"version\": \"1.2\",\n \"data\": [}").unwrap_err();
+        assert!(error.contains("(2 行"), "{}", error);
+    }
+
+    #[test]
+    fn scales_each_page_to_page_sizes() {
+        let json = format!(
+            r#"{{"version": "1.1", "pageCount": 3, "pageSizes": {{"0": {{"width": 100, "height": 200}}, "1": {{"width": 100, "height": 200}}}},
+                "data": {{"0": [{}], "1": [{}], "2": [{}]}}}}"#,
+            stroke(10.0, 20.0),
+            stroke(10.0, 20.0),
+            stroke(10.0, 20.0)
+        );
+        let current = sizes(&[("0", 200.0, 400.0), ("1", 100.0, 200.0), ("2", 300.0, 300.0)]);
+        let data = MojiQExportData::load(&json, Some(&current)).unwrap();
+        assert_eq!(data.version, CURRENT_VERSION);
+        assert_eq!(data.page_count, Some(3));
+
+        let point = |page: usize| data.page_objects(page)[0].points.as_ref().unwrap()[0];
+        // 倍のサイズのページは座標と線幅も倍になる
+        assert_eq!((point(0).x, point(0).y), (20.0, 40.0));
+        assert_eq!(data.page_objects(0)[0].width, Some(8.0));
+        // 同じ大きさのページとサイズ情報のないページはそのまま
+        assert_eq!((point(1).x, point(1).y), (10.0, 20.0));
+        assert_eq!((point(2).x, point(2).y), (10.0, 20.0));
+        // 基準のページサイズは読み込んだページの大きさになる
+        assert_eq!(data.page_sizes, current);
+    }
+
+    #[test]
+    fn version_1_0_page_sizes_are_cleared() {
+        let json = format!(
+            r#"{{"version": "1.0", "pageSizes": {{"0": {{"width": 100, "height": 200}}}}, "data": {{"0": [{}]}}}}"#,
+            stroke(10.0, 20.0)
+        );
+        let data = MojiQExportData::load(&json, None).unwrap();
+        assert_eq!(data.version, CURRENT_VERSION);
+        assert!(data.page_sizes.is_empty());
+        // pageCount は補わない (描画のあるページ数は文書のページ数ではない)
+        assert_eq!(data.page_count, None);
+
+        // 1.0 の座標は読み込むページの大きさのままなので、スケーリングせずにそのサイズを基準にする
+        let current = sizes(&[("0", 300.0, 600.0)]);
+        let data = MojiQExportData::load(&json, Some(&current)).unwrap();
+        let point = data.page_objects(0)[0].points.as_ref().unwrap()[0];
+        assert_eq!((point.x, point.y), (10.0, 20.0));
+        assert_eq!(data.page_sizes, current);
+        assert!(!serde_json::to_string(&data).unwrap().contains("pageCount"));
+    }
+}
@@ -0,0 +1,381 @@
+// リンク方式の画像ファイルの監視: 読み込んだ画像のパスを登録しておき、外部で上書き・削除・リネームされたら
+// フロントエンドにイベントで知らせる。OS のファイル変更通知 (notify) に加えて、通知が届かない
+// ネットワークドライブ向けに一定間隔で更新日時を確認し直す。
+// リネームは OS によって旧パス・新パスが別々の通知 (Windows では削除 + 作成のこともある) で届くため、
+// 消えたファイルはしばらく保留し、同じ大きさ・更新日時のファイルが現れたらリネームとして通知する。
+// 上書きもコピー中は通知が何度も届き、途中の状態では画像を読めないため、大きさと更新日時が
+// 一定時間変わらなくなってから通知する。
+
+use notify::event::{CreateKind, ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::Emitter;
+
+/// 変更を通知するイベント名。
+pub const LINKED_FILE_EVENT: &str = "linked-file-changed";
+
+/// OS の通知とは別に、すべての登録ファイルの状態を確認し直す間隔。
+const RESCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 消えたファイルをリネームの旧パスとして保留する時間。これを過ぎたら削除として通知する。
+const RENAME_PAIRING_WINDOW: Duration = Duration::from_secs(1);
+
+/// 変更されたファイルの大きさと更新日時がこの時間変わらなければ、書き込みが終わったとみなして通知する。
+const MODIFY_SETTLE_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkedFileChange {
+    /// "modified" / "deleted" / "renamed"
+    pub kind: &'static str,
+    pub path: String,
+    /// リネーム後のパス (kind = "renamed" のとき)
+    pub new_path: Option<String>,
+    /// 新しい更新日時 (UNIX 秒、FileMetadata.modified_at と同じ単位)
+    pub modified_at: Option<u64>,
+    /// 新しい画像サイズ。書き込み途中などで読めなければ None
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// 前回確認したときのファイルの状態。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified_at: u64,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let modified_at = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Some(FileStamp { modified_at, len: metadata.len() })
+}
+
+/// 通知を保留しているファイル。消えたファイルはリネーム先が現れるのを、
+/// 変更されたファイルは書き込みが落ち着くのを待つ。
+struct PendingChange {
+    path: PathBuf,
+    /// 消えたファイルでは消える前の状態 (リネーム先と比べる)、変更されたファイルでは最後に見た状態
+    stamp: FileStamp,
+    since: Instant,
+}
+
+enum Message {
+    Watch(Vec<PathBuf>),
+    Unwatch(Vec<PathBuf>),
+    Fs(notify::Event),
+}
+
+/// 監視スレッドへの窓口 (Tauri の managed state)。
+pub struct FileWatcher {
+    tx: Sender<Message>,
+}
+
+impl FileWatcher {
+    /// 監視スレッドを起動する。
+    pub fn start(app: tauri::AppHandle) -> Self {
+        let (tx, rx) = channel();
+        let fs_tx = tx.clone();
+
+        std::thread::spawn(move || {
+            let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+                Ok(event) => {
+                    let _ = fs_tx.send(Message::Fs(event));
+                }
+                Err(e) => eprintln!("[MojiQ] ファイル監視エラー: {}", e),
+            });
+            let mut state = WatchState {
+                watcher: watcher
+                    .map_err(|e| eprintln!("[MojiQ] ファイル監視を開始できません (定期確認のみ行います): {}", e))
+                    .ok(),
+                files: HashMap::new(),
+                directories: HashMap::new(),
+                pending_removals: Vec::new(),
+                pending_modifications: Vec::new(),
+            };
+
+            let mut last_rescan = Instant::now();
+            loop {
+                let timeout = RESCAN_INTERVAL
+                    .saturating_sub(last_rescan.elapsed())
+                    .min(state.next_deadline().unwrap_or(Duration::MAX));
+                let mut changes = match rx.recv_timeout(timeout) {
+                    Ok(Message::Watch(paths)) => {
+                        state.watch(paths);
+                        Vec::new()
+                    }
+                    Ok(Message::Unwatch(paths)) => {
+                        state.unwatch(paths);
+                        Vec::new()
+                    }
+                    Ok(Message::Fs(event)) => state.handle_event(&event),
+                    Err(RecvTimeoutError::Timeout) => Vec::new(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                // 同じフォルダで別のファイルが頻繁に変わっても定期確認が止まらないよう、経過時間で判定する
+                if last_rescan.elapsed() >= RESCAN_INTERVAL {
+                    state.rescan();
+                    last_rescan = Instant::now();
+                }
+                changes.extend(state.expire_removals());
+                changes.extend(state.settle_modifications());
+                for change in changes {
+                    let _ = app.emit(LINKED_FILE_EVENT, change);
+                }
+            }
+        });
+
+        Self { tx }
+    }
+
+    pub fn watch(&self, paths: Vec<String>) -> Result<(), String> {
+        self.send(Message::Watch(paths.into_iter().map(PathBuf::from).collect()))
+    }
+
+    pub fn unwatch(&self, paths: Vec<String>) -> Result<(), String> {
+        self.send(Message::Unwatch(paths.into_iter().map(PathBuf::from).collect()))
+    }
+
+    fn send(&self, message: Message) -> Result<(), String> {
+        self.tx.send(message).map_err(|_| "ファイル監視スレッドが停止しています".to_string())
+    }
+}
+
+struct WatchState {
+    /// OS の通知を受け取れない環境では None (定期確認のみ)
+    watcher: Option<RecommendedWatcher>,
+    /// 登録ファイルと前回の状態 (削除済みなら None)
+    files: HashMap<PathBuf, Option<FileStamp>>,
+    /// 通知を受け取っているフォルダと、その中の登録ファイル数
+    directories: HashMap<PathBuf, usize>,
+    /// 消えたファイル (古いものから順)
+    pending_removals: Vec<PendingChange>,
+    /// 変更されて書き込みが落ち着くのを待っているファイル
+    pending_modifications: Vec<PendingChange>,
+}
+
+impl WatchState {
+    fn watch(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if self.files.contains_key(&path) {
+                continue;
+            }
+            self.files.insert(path.clone(), stamp(&path));
+            // ファイル自体ではなくフォルダを監視する (上書き保存でファイルが置き換えられても追える)
+            let Some(dir) = path.parent().map(Path::to_path_buf) else {
+                continue;
+            };
+            let count = self.directories.entry(dir.clone()).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                if let Some(watcher) = self.watcher.as_mut() {
+                    if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                        eprintln!("[MojiQ] フォルダを監視できません: {}: {}", dir.display(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn unwatch(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if self.files.remove(&path).is_none() {
+                continue;
+            }
+            self.pending_removals.retain(|pending| pending.path != path);
+            self.pending_modifications.retain(|pending| pending.path != path);
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            if let Some(count) = self.directories.get_mut(dir) {
+                *count -= 1;
+                if *count == 0 {
+                    self.directories.remove(dir);
+                    if let Some(watcher) = self.watcher.as_mut() {
+                        let _ = watcher.unwatch(dir);
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: &notify::Event) -> Vec<LinkedFileChange> {
+        let mut changes = Vec::new();
+        match event.kind {
+            // 1 つのイベントで旧パスと新パスが分かる場合 (Linux)
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = event.paths.as_slice() {
+                    // 旧パスの通知で保留中か、まだ消えたことに気づいていないときだけ (新パスの通知で通知済みなら何もしない)
+                    let unreported = self.pending_removals.iter().any(|pending| &pending.path == from)
+                        || matches!(self.files.get(from), Some(Some(_)));
+                    if unreported && !from.exists() {
+                        self.pending_removals.retain(|pending| &pending.path != from);
+                        self.pending_modifications.retain(|pending| &pending.path != from);
+                        self.files.insert(from.clone(), None);
+                        return vec![renamed_file(from, to)];
+                    }
+                }
+            }
+            // 新パスだけの通知 (Windows / macOS のリネーム、Windows の削除 + 作成による移動)。
+            // 旧パスは先に届いた通知で保留しているので、同じ大きさ・更新日時のファイルと組にする
+            EventKind::Create(CreateKind::File | CreateKind::Any)
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Any)) => {
+                for path in event.paths.iter().filter(|p| !self.files.contains_key(*p)) {
+                    let Some(current) = stamp(path) else {
+                        continue;
+                    };
+                    let Some(index) = self.pending_removals.iter().position(|pending| pending.stamp == current) else {
+                        continue;
+                    };
+                    let pending = self.pending_removals.remove(index);
+                    changes.push(renamed_file(&pending.path, path));
+                }
+            }
+            _ => {}
+        }
+
+        let paths: Vec<PathBuf> = event
+            .paths
+            .iter()
+            .filter(|p| self.files.contains_key(*p))
+            .cloned()
+            .collect();
+        for path in &paths {
+            self.check(path);
+        }
+        changes
+    }
+
+    /// 保留中のファイルのうち、最も早く期限が来るものまでの時間。
+    fn next_deadline(&self) -> Option<Duration> {
+        let removal = self
+            .pending_removals
+            .first()
+            .map(|pending| RENAME_PAIRING_WINDOW.saturating_sub(pending.since.elapsed()));
+        let modification = self
+            .pending_modifications
+            .iter()
+            .map(|pending| MODIFY_SETTLE_PERIOD.saturating_sub(pending.since.elapsed()))
+            .min();
+        removal.into_iter().chain(modification).min()
+    }
+
+    /// リネーム先が現れないまま保留期間を過ぎたファイルを削除として通知する。
+    fn expire_removals(&mut self) -> Vec<LinkedFileChange> {
+        let expired = self
+            .pending_removals
+            .iter()
+            .take_while(|pending| pending.since.elapsed() >= RENAME_PAIRING_WINDOW)
+            .count();
+        self.pending_removals
+            .drain(..expired)
+            .map(|pending| LinkedFileChange {
+                kind: "deleted",
+                path: pending.path.to_string_lossy().into_owned(),
+                new_path: None,
+                modified_at: None,
+                width: None,
+                height: None,
+            })
+            .collect()
+    }
+
+    /// 保留期間のあいだ状態が変わらなかった変更を通知する。まだ変わり続けていれば保留し直す。
+    fn settle_modifications(&mut self) -> Vec<LinkedFileChange> {
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_modifications)
+            .into_iter()
+            .partition(|pending| pending.since.elapsed() >= MODIFY_SETTLE_PERIOD);
+        self.pending_modifications = waiting;
+
+        let mut changes = Vec::new();
+        for pending in due {
+            if stamp(&pending.path) == Some(pending.stamp) {
+                self.files.insert(pending.path.clone(), Some(pending.stamp));
+                changes.push(changed_file(&pending.path, "modified"));
+            } else {
+                // 書き込みが続いているか、消えた (check が保留し直すか削除として保留する)
+                self.check(&pending.path);
+            }
+        }
+        changes
+    }
+
+    fn rescan(&mut self) {
+        let paths: Vec<PathBuf> = self.files.keys().cloned().collect();
+        for path in &paths {
+            self.check(path);
+        }
+    }
+
+    /// ファイルの状態を前回通知したときと比べる。
+    /// 変更は書き込み途中かもしれず、消えたファイルはリネームかもしれないので、どちらもすぐには通知せず保留する。
+    fn check(&mut self, path: &Path) {
+        let Some(&previous) = self.files.get(path) else {
+            return;
+        };
+        let current = stamp(path);
+        if current == previous {
+            // 書き込み途中に見えた状態から元に戻った
+            self.pending_modifications.retain(|pending| pending.path != path);
+            return;
+        }
+        match (previous, current) {
+            (_, Some(current)) => {
+                // 削除してから同じパスに作り直す保存方法では、保留中の削除は取り消して上書きとして扱う
+                self.pending_removals.retain(|pending| pending.path != path);
+                match self.pending_modifications.iter_mut().find(|pending| pending.path == path) {
+                    // 前回と同じ状態なら保留開始時刻はそのまま (settle_modifications が期限で通知する)
+                    Some(pending) if pending.stamp == current => {}
+                    Some(pending) => {
+                        pending.stamp = current;
+                        pending.since = Instant::now();
+                    }
+                    None => self.pending_modifications.push(PendingChange {
+                        path: path.to_path_buf(),
+                        stamp: current,
+                        since: Instant::now(),
+                    }),
+                }
+            }
+            (Some(stamp), None) => {
+                self.files.insert(path.to_path_buf(), None);
+                self.pending_modifications.retain(|pending| pending.path != path);
+                self.pending_removals.push(PendingChange {
+                    path: path.to_path_buf(),
+                    stamp,
+                    since: Instant::now(),
+                });
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// `from` から `to` へのリネームの通知内容。
+fn renamed_file(from: &Path, to: &Path) -> LinkedFileChange {
+    let mut change = changed_file(to, "renamed");
+    change.path = from.to_string_lossy().into_owned();
+    change.new_path = Some(to.to_string_lossy().into_owned());
+    change
+}
+
+/// 存在するファイルの更新日時と画像サイズを読み取って通知内容にする。
+fn changed_file(path: &Path, kind: &'static str) -> LinkedFileChange {
+    let dimensions = ::image::image_dimensions(path).ok();
+    LinkedFileChange {
+        kind,
+        path: path.to_string_lossy().into_owned(),
+        new_path: None,
+        modified_at: stamp(path).map(|s| s.modified_at),
+        width: dimensions.map(|(w, _)| w),
+        height: dimensions.map(|(_, h)| h),
+    }
+}
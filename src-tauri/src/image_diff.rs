@@ -0,0 +1,327 @@
+// ページ画像の差分 (写植の修正が反映されたかの確認用)。
+// 前の版と新しい版のページ画像 (画像ファイルか、スキャン画像の PDF のページ) を位置合わせ (alignment.rs) して比べ、
+// 変わった箇所を色付けした透過 PNG と、変わった範囲の矩形を返す。
+// PDF は画像だけのページ (スキャン) にのみ対応する。写植を画像の上にベクターの文字で重ねた PDF では
+// 画像だけを比べても文字の修正が差分に出ないため、そのようなページはエラーにする (pdf_images.rs)。
+// JPEG のノイズやスクリーントーンの網点の細かなずれを変更とみなさないよう、縮小してぼかした画像を
+// 相手の画像の近く (tolerance の範囲) の一番暗い画素と比べ、それより暗いインクだけを変更とする。
+
+use crate::alignment::Alignment;
+use ::image::{DynamicImage, GenericImageView, GrayImage, ImageFormat, Rgba, RgbaImage};
+use serde::Serialize;
+use std::io::Cursor;
+
+/// 既定の明るさの差のしきい値 (0-255)
+pub const DEFAULT_THRESHOLD: u8 = 48;
+/// 既定の位置のずれの許容 (解析画像の px)
+pub const DEFAULT_TOLERANCE: u32 = 2;
+/// 変更とみなす領域の最小の画素数 (解析画像の px)。これより小さいものはノイズとして捨てる
+const MIN_REGION_PIXELS: u32 = 12;
+/// この距離 (解析画像の px) より近い変更は 1 つの領域にまとめる
+const MERGE_DISTANCE: usize = 6;
+
+const ADDED_COLOR: Rgba<u8> = Rgba([220, 20, 60, 255]);
+const REMOVED_COLOR: Rgba<u8> = Rgba([30, 110, 255, 255]);
+const REGION_FILL: Rgba<u8> = Rgba([255, 170, 0, 48]);
+const REGION_OUTLINE: Rgba<u8> = Rgba([255, 120, 0, 230]);
+
+pub struct DiffOptions {
+    /// 明るさの差のしきい値 (0-255)。これ以下の差は無視する
+    pub threshold: u8,
+    /// 位置のずれを許す距離 (解析画像の px)。スクリーントーンの網点のずれなどを吸収する
+    pub tolerance: u32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions { threshold: DEFAULT_THRESHOLD, tolerance: DEFAULT_TOLERANCE }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// 新しい版で増えた (赤)
+    Added,
+    /// 新しい版で消えた (青)
+    Removed,
+    /// 増えたものと消えたものが混ざっている (文字の差し替えなど)
+    Changed,
+}
+
+/// 変わった範囲 (前の版の画像の px)。
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub kind: ChangeKind,
+    /// 変わった画素数 (解析画像の px)
+    pub changed_pixels: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageDiff {
+    /// 領域の座標の基準 (前の版の画像の大きさ, px)
+    pub width: u32,
+    pub height: u32,
+    /// 前の版に新しい版を合わせた変換
+    pub alignment: Alignment,
+    pub regions: Vec<DiffRegion>,
+    /// 比べられた範囲のうち変わった画素の割合 (0-1)
+    pub changed_ratio: f32,
+    /// 変更箇所を色付けした透過画像。解析画像の大きさなので、前の版のページ全体に引き伸ばして重ねる
+    #[serde(skip)]
+    pub overlay_image: RgbaImage,
+}
+
+/// ページ画像を読み込む。PDF ならそのページ (0 始まり) に貼られたスキャン画像を使う。
+/// 画像の上に文字や線を描いている PDF のページは、差分が正しく出ないのでエラーにする。
+pub fn load_page(path: &str, page_index: usize) -> Result<DynamicImage, String> {
+    if path.to_lowercase().ends_with(".pdf") {
+        return crate::pdf_images::extract_page_image_at(path, page_index)
+            .map_err(|e| {
+                format!(
+                    "{}: {}\n(PDF で比べられるのは画像だけのページです。文字や線を含むページは画像に書き出してから比べてください)",
+                    path, e
+                )
+            })?
+            .decode();
+    }
+    ::image::open(path).map_err(|e| format!("{} を読み込めません: {}", path, e))
+}
+
+/// 前の版と新しい版のページを比べる。新しい版がずれたり解像度が違っても、前の版に合わせてから比べる。
+pub fn diff(old: &DynamicImage, new: &DynamicImage, options: &DiffOptions) -> PageDiff {
+    let (width, height) = old.dimensions();
+    let reference = crate::alignment::analysis_image(old);
+    let moving = crate::alignment::analysis_image(new);
+    let alignment = crate::alignment::align_analysis(&reference, &moving);
+
+    let old_gray = reference.gray;
+    let (aw, ah) = old_gray.dimensions();
+    let transform = alignment.transform.rescaled(reference.scale, moving.scale);
+    let (new_gray, valid) = crate::alignment::warp_gray(&moving.gray, &transform, aw, ah);
+    let scale = reference.scale as f32;
+
+    let changes = detect_changes(&old_gray, &new_gray, &valid, options);
+    let regions = find_regions(&changes, aw as usize, ah as usize);
+
+    let mut overlay_image = RgbaImage::new(aw, ah);
+    for region in &regions {
+        draw_region(&mut overlay_image, region);
+    }
+    let mut changed = 0u64;
+    for region in &regions {
+        for y in region.y0..=region.y1 {
+            for x in region.x0..=region.x1 {
+                let color = match changes[y * aw as usize + x] {
+                    Change::Added => ADDED_COLOR,
+                    Change::Removed => REMOVED_COLOR,
+                    Change::None => continue,
+                };
+                overlay_image.put_pixel(x as u32, y as u32, color);
+                changed += 1;
+            }
+        }
+    }
+    let compared = valid.iter().filter(|v| **v).count().max(1);
+
+    let to_old = |v: usize| (v as f32 / scale) as u32;
+    let regions = regions
+        .iter()
+        .map(|region| {
+            let (x, y) = (to_old(region.x0), to_old(region.y0));
+            let x1 = (((region.x1 + 1) as f32 / scale).ceil() as u32).min(width);
+            let y1 = (((region.y1 + 1) as f32 / scale).ceil() as u32).min(height);
+            DiffRegion {
+                x,
+                y,
+                width: x1 - x,
+                height: y1 - y,
+                kind: region.kind(),
+                changed_pixels: region.added + region.removed,
+            }
+        })
+        .collect();
+
+    PageDiff {
+        width,
+        height,
+        alignment,
+        regions,
+        changed_ratio: changed as f32 / compared as f32,
+        overlay_image,
+    }
+}
+
+/// 透過 PNG にする。
+pub fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("PNG への変換に失敗しました: {}", e))?;
+    Ok(png)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    None,
+    Added,
+    Removed,
+}
+
+/// 画素ごとの変化。相手の近くのどの画素よりもしきい値以上暗ければ、そのインクが増えた (または消えた) とする。
+fn detect_changes(old: &GrayImage, new: &GrayImage, valid: &[bool], options: &DiffOptions) -> Vec<Change> {
+    let old_min = min_filter(old, options.tolerance);
+    let new_min = min_filter(new, options.tolerance);
+    let threshold = options.threshold as i16;
+    (0..valid.len())
+        .map(|i| {
+            if !valid[i] {
+                Change::None
+            } else if old_min.as_raw()[i] as i16 - new.as_raw()[i] as i16 > threshold {
+                Change::Added
+            } else if new_min.as_raw()[i] as i16 - old.as_raw()[i] as i16 > threshold {
+                Change::Removed
+            } else {
+                Change::None
+            }
+        })
+        .collect()
+}
+
+/// 半径 radius の正方形の中の最小値 (紙より暗いインクを太らせる)。
+fn min_filter(image: &GrayImage, radius: u32) -> GrayImage {
+    if radius == 0 {
+        return image.clone();
+    }
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let r = radius as usize;
+    let src = image.as_raw();
+    let mut horizontal = vec![0u8; w * h];
+    for y in 0..h {
+        let row = &src[y * w..(y + 1) * w];
+        for x in 0..w {
+            horizontal[y * w + x] = row[x.saturating_sub(r)..(x + r + 1).min(w)].iter().copied().min().unwrap_or(255);
+        }
+    }
+    let mut out = vec![0u8; w * h];
+    for y in 0..h {
+        let rows = y.saturating_sub(r)..(y + r + 1).min(h);
+        for x in 0..w {
+            out[y * w + x] = rows.clone().map(|yy| horizontal[yy * w + x]).min().unwrap_or(255);
+        }
+    }
+    GrayImage::from_raw(w as u32, h as u32, out).unwrap_or_else(|| image.clone())
+}
+
+/// 変わった画素のまとまり (解析画像の px、x1 / y1 を含む)。
+struct Region {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    added: u32,
+    removed: u32,
+}
+
+impl Region {
+    fn kind(&self) -> ChangeKind {
+        let total = self.added + self.removed;
+        if self.added * 10 >= total * 9 {
+            ChangeKind::Added
+        } else if self.removed * 10 >= total * 9 {
+            ChangeKind::Removed
+        } else {
+            ChangeKind::Changed
+        }
+    }
+}
+
+/// 近い変更をまとめて領域にする。小さすぎる領域 (ノイズ) は捨てる。
+fn find_regions(changes: &[Change], w: usize, h: usize) -> Vec<Region> {
+    // 変わった画素を MERGE_DISTANCE だけ広げてつながったものを 1 つの領域とする
+    let changed: Vec<bool> = changes.iter().map(|c| *c != Change::None).collect();
+    let grouped = dilate(&changed, w, h, MERGE_DISTANCE);
+
+    let mut labels = vec![usize::MAX; w * h];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..w * h {
+        if !grouped[start] || labels[start] != usize::MAX {
+            continue;
+        }
+        let label = regions.len();
+        let mut region = Region { x0: usize::MAX, y0: usize::MAX, x1: 0, y1: 0, added: 0, removed: 0 };
+        labels[start] = label;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            match changes[i] {
+                Change::None => {}
+                change => {
+                    region.x0 = region.x0.min(x);
+                    region.y0 = region.y0.min(y);
+                    region.x1 = region.x1.max(x);
+                    region.y1 = region.y1.max(y);
+                    if change == Change::Added {
+                        region.added += 1;
+                    } else {
+                        region.removed += 1;
+                    }
+                }
+            }
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ];
+            for j in neighbors.into_iter().flatten() {
+                if grouped[j] && labels[j] == usize::MAX {
+                    labels[j] = label;
+                    stack.push(j);
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions.retain(|r| r.added + r.removed >= MIN_REGION_PIXELS);
+    regions
+}
+
+/// 半径 radius の正方形で広げる。
+fn dilate(mask: &[bool], w: usize, h: usize, radius: usize) -> Vec<bool> {
+    let mut horizontal = vec![false; w * h];
+    for (i, _) in mask.iter().enumerate().filter(|(_, m)| **m) {
+        let (x, y) = (i % w, i / w);
+        horizontal[y * w + x.saturating_sub(radius)..y * w + (x + radius + 1).min(w)].fill(true);
+    }
+    let mut out = vec![false; w * h];
+    for (i, _) in horizontal.iter().enumerate().filter(|(_, m)| **m) {
+        let (x, y) = (i % w, i / w);
+        for row in y.saturating_sub(radius)..(y + radius + 1).min(h) {
+            out[row * w + x] = true;
+        }
+    }
+    out
+}
+
+/// 領域を薄く塗って枠で囲む (少し外側に広げる)。
+fn draw_region(image: &mut RgbaImage, region: &Region) {
+    const MARGIN: usize = 3;
+    const OUTLINE: usize = 2;
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let x0 = region.x0.saturating_sub(MARGIN);
+    let y0 = region.y0.saturating_sub(MARGIN);
+    let x1 = (region.x1 + MARGIN).min(w - 1);
+    let y1 = (region.y1 + MARGIN).min(h - 1);
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let edge = x < x0 + OUTLINE || x + OUTLINE > x1 || y < y0 + OUTLINE || y + OUTLINE > y1;
+            image.put_pixel(x as u32, y as u32, if edge { REGION_OUTLINE } else { REGION_FILL });
+        }
+    }
+}
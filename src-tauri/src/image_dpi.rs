@@ -0,0 +1,316 @@
+// 画像ファイルに埋め込まれた解像度メタデータ (dpi) の読み取り。
+// JPEG (JFIF / EXIF)、PNG (pHYs)、TIFF に対応する。
+// 画素のデコードは行わず、ヘッダ部分だけを走査する。
+
+/// これより小さい / 大きい dpi は壊れたメタデータとみなして無視する
+/// (JFIF の units=1, density=1x1 など、実在しない値を書き込むソフトがある)。
+const MIN_PLAUSIBLE_DPI: f32 = 10.0;
+const MAX_PLAUSIBLE_DPI: f32 = 10000.0;
+
+/// 画像バイト列から (水平 dpi, 垂直 dpi) を読み取る。メタデータがなければ None。
+pub fn read_image_dpi(bytes: &[u8]) -> Option<(f32, f32)> {
+    let dpi = if bytes.starts_with(&[0xFF, 0xD8]) {
+        read_jpeg_dpi(bytes)
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        read_png_dpi(bytes)
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        read_tiff_dpi(bytes)
+    } else {
+        None
+    }?;
+
+    plausible_dpi(dpi)
+}
+
+fn plausible_dpi(dpi: (f32, f32)) -> Option<(f32, f32)> {
+    let plausible = |v: f32| (MIN_PLAUSIBLE_DPI..=MAX_PLAUSIBLE_DPI).contains(&v);
+    if plausible(dpi.0) && plausible(dpi.1) {
+        Some(dpi)
+    } else {
+        None
+    }
+}
+
+/// JPEG: APP0 (JFIF) の密度を優先し、単位がないか不正な値なら APP1 (EXIF) の XResolution / YResolution を使う。
+fn read_jpeg_dpi(bytes: &[u8]) -> Option<(f32, f32)> {
+    let mut exif_dpi = None;
+    let mut pos = 2;
+
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        // 0xFF のフィルバイト
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        // SOS 以降は画像データなのでメタデータはない
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        if length < 2 || pos + 2 + length > bytes.len() {
+            break;
+        }
+        let payload = &bytes[pos + 4..pos + 2 + length];
+
+        match marker {
+            0xE0 if payload.starts_with(b"JFIF\0") && payload.len() >= 12 => {
+                let units = payload[7];
+                let x = u16::from_be_bytes([payload[8], payload[9]]) as f32;
+                let y = u16::from_be_bytes([payload[10], payload[11]]) as f32;
+                let jfif_dpi = match units {
+                    1 => plausible_dpi((x, y)),
+                    2 => plausible_dpi((x * 2.54, y * 2.54)),
+                    // 0 はアスペクト比のみで物理単位なし
+                    _ => None,
+                };
+                if jfif_dpi.is_some() {
+                    return jfif_dpi;
+                }
+            }
+            0xE1 if payload.starts_with(b"Exif\0\0") => {
+                exif_dpi = read_tiff_dpi(&payload[6..]);
+            }
+            _ => {}
+        }
+
+        pos += 2 + length;
+    }
+
+    exif_dpi
+}
+
+/// PNG: IDAT より前にある pHYs チャンク (ピクセル / メートル) を読む。
+fn read_png_dpi(bytes: &[u8]) -> Option<(f32, f32)> {
+    let mut pos = 8;
+
+    while pos + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let chunk_type = &bytes[pos + 4..pos + 8];
+        let data_start = pos + 8;
+        if data_start + length > bytes.len() {
+            return None;
+        }
+
+        match chunk_type {
+            b"pHYs" if length >= 9 => {
+                let data = &bytes[data_start..data_start + length];
+                let x = u32::from_be_bytes(data[0..4].try_into().ok()?) as f32;
+                let y = u32::from_be_bytes(data[4..8].try_into().ok()?) as f32;
+                // unit 1 = メートル。0 はアスペクト比のみ
+                return if data[8] == 1 {
+                    Some((x * 0.0254, y * 0.0254))
+                } else {
+                    None
+                };
+            }
+            b"IDAT" | b"IEND" => return None,
+            _ => {}
+        }
+
+        // データ + CRC (4 bytes)
+        pos = data_start + length + 4;
+    }
+
+    None
+}
+
+/// TIFF (EXIF も同じ構造): IFD0 の XResolution / YResolution / ResolutionUnit を読む。
+fn read_tiff_dpi(bytes: &[u8]) -> Option<(f32, f32)> {
+    let little_endian = match bytes.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read_u16 = |at: usize| -> Option<u16> {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let read_u32 = |at: usize| -> Option<u32> {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+    let read_rational = |at: usize| -> Option<f32> {
+        let num = read_u32(at)? as f32;
+        let den = read_u32(at + 4)? as f32;
+        if den == 0.0 {
+            None
+        } else {
+            Some(num / den)
+        }
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let entry_count = read_u16(ifd)? as usize;
+
+    let mut x_res = None;
+    let mut y_res = None;
+    // TIFF 仕様上の既定値は 2 (インチ)
+    let mut unit = 2u16;
+
+    for i in 0..entry_count {
+        let entry = ifd + 2 + i * 12;
+        let tag = read_u16(entry)?;
+        let value_at = entry + 8;
+        match tag {
+            // RATIONAL は 8 バイトなので値フィールドはオフセット
+            0x011A => x_res = read_rational(read_u32(value_at)? as usize),
+            0x011B => y_res = read_rational(read_u32(value_at)? as usize),
+            0x0128 => unit = read_u16(value_at)?,
+            _ => {}
+        }
+    }
+
+    let (x, y) = (x_res?, y_res.or(x_res)?);
+    match unit {
+        2 => Some((x, y)),
+        3 => Some((x * 2.54, y * 2.54)),
+        // 1 = 単位なし
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 単位と密度を指定した JFIF (APP0) セグメント。
+    fn jfif(units: u8, x: u16, y: u16) -> Vec<u8> {
+        let mut segment = vec![0xFF, 0xE0, 0x00, 0x10];
+        segment.extend_from_slice(b"JFIF\0\x01\x02");
+        segment.push(units);
+        segment.extend_from_slice(&x.to_be_bytes());
+        segment.extend_from_slice(&y.to_be_bytes());
+        segment.extend_from_slice(&[0, 0]);
+        segment
+    }
+
+    /// XResolution / YResolution / ResolutionUnit だけを持つ TIFF ヘッダ + IFD0。
+    fn tiff(little_endian: bool, x: u32, y: u32, unit: u16) -> Vec<u8> {
+        let u16b = |v: u16| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let u32b = |v: u32| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let mut out = Vec::new();
+        out.extend_from_slice(if little_endian { b"II*\0" } else { b"MM\0*" });
+        out.extend_from_slice(&u32b(8));
+        out.extend_from_slice(&u16b(3));
+        // IFD0 は 8 + 2 + 3 * 12 + 4 = 50 バイト目で終わり、RATIONAL はその後ろ
+        let entry = |out: &mut Vec<u8>, tag: u16, kind: u16, value: [u8; 4]| {
+            out.extend_from_slice(&u16b(tag));
+            out.extend_from_slice(&u16b(kind));
+            out.extend_from_slice(&u32b(1));
+            out.extend_from_slice(&value);
+        };
+        entry(&mut out, 0x011A, 5, u32b(50));
+        entry(&mut out, 0x011B, 5, u32b(58));
+        let mut unit_value = [0u8; 4];
+        unit_value[..2].copy_from_slice(&u16b(unit));
+        entry(&mut out, 0x0128, 3, unit_value);
+        out.extend_from_slice(&u32b(0));
+        for value in [x, 1, y, 1] {
+            out.extend_from_slice(&u32b(value));
+        }
+        out
+    }
+
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0xFF, 0xD8];
+        for segment in segments {
+            out.extend_from_slice(segment);
+        }
+        out.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        out
+    }
+
+    fn exif(tiff: Vec<u8>) -> Vec<u8> {
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend(tiff);
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend(payload);
+        segment
+    }
+
+    fn png(phys: Option<(u32, u32, u8)>) -> Vec<u8> {
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut chunk = |kind: &[u8], data: &[u8]| {
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(kind);
+            out.extend_from_slice(data);
+            // CRC は読まないので 0 でよい
+            out.extend_from_slice(&[0; 4]);
+        };
+        chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+        if let Some((x, y, unit)) = phys {
+            let mut data = Vec::new();
+            data.extend_from_slice(&x.to_be_bytes());
+            data.extend_from_slice(&y.to_be_bytes());
+            data.push(unit);
+            chunk(b"pHYs", &data);
+        }
+        chunk(b"IDAT", &[]);
+        out
+    }
+
+    fn assert_dpi(actual: Option<(f32, f32)>, expected: (f32, f32)) {
+        let (x, y) = actual.expect("dpi が読めない");
+        assert!((x - expected.0).abs() < 0.01 && (y - expected.1).abs() < 0.01, "{:?} != {:?}", (x, y), expected);
+    }
+
+    #[test]
+    fn jfif_inches_and_centimeters() {
+        assert_dpi(read_image_dpi(&jpeg(&[jfif(1, 350, 600)])), (350.0, 600.0));
+        assert_dpi(read_image_dpi(&jpeg(&[jfif(2, 118, 118)])), (299.72, 299.72));
+    }
+
+    #[test]
+    fn jfif_without_units_falls_back_to_exif() {
+        let bytes = jpeg(&[jfif(0, 1, 1), exif(tiff(true, 600, 600, 2))]);
+        assert_dpi(read_image_dpi(&bytes), (600.0, 600.0));
+        // 単位付きの JFIF があれば EXIF より優先する
+        let bytes = jpeg(&[jfif(1, 300, 300), exif(tiff(true, 600, 600, 2))]);
+        assert_dpi(read_image_dpi(&bytes), (300.0, 300.0));
+        // units=1, 1x1 のような実在しない値も EXIF に回す
+        let bytes = jpeg(&[jfif(1, 1, 1), exif(tiff(false, 400, 400, 2))]);
+        assert_dpi(read_image_dpi(&bytes), (400.0, 400.0));
+        assert_eq!(read_image_dpi(&jpeg(&[jfif(0, 1, 1)])), None);
+    }
+
+    #[test]
+    fn exif_byte_orders_and_units() {
+        assert_dpi(read_image_dpi(&jpeg(&[exif(tiff(true, 350, 350, 2))])), (350.0, 350.0));
+        assert_dpi(read_image_dpi(&jpeg(&[exif(tiff(false, 72, 144, 2))])), (72.0, 144.0));
+        assert_dpi(read_image_dpi(&tiff(false, 100, 100, 3)), (254.0, 254.0));
+        assert_eq!(read_image_dpi(&tiff(true, 300, 300, 1)), None);
+    }
+
+    #[test]
+    fn png_phys() {
+        // 11811 px/m ≒ 300 dpi
+        assert_dpi(read_image_dpi(&png(Some((11811, 11811, 1)))), (299.99, 299.99));
+        assert_eq!(read_image_dpi(&png(Some((11811, 11811, 0)))), None);
+        assert_eq!(read_image_dpi(&png(None)), None);
+    }
+
+    #[test]
+    fn truncated_headers() {
+        let full = [
+            jpeg(&[jfif(1, 350, 350)]),
+            jpeg(&[exif(tiff(true, 350, 350, 2))]),
+            png(Some((11811, 11811, 1))),
+            tiff(false, 350, 350, 2),
+        ];
+        for bytes in &full {
+            for len in 0..bytes.len() {
+                // 途中で切れていてもパニックせず、読めた場合も値は正しい
+                if let Some(dpi) = read_image_dpi(&bytes[..len]) {
+                    assert!(dpi.0 > 299.0 && dpi.0 < 351.0, "len {}: {:?}", len, dpi);
+                }
+            }
+        }
+        assert_eq!(read_image_dpi(&[0xFF, 0xD8, 0xFF, 0xE0, 0xFF, 0xFF]), None);
+        assert_eq!(read_image_dpi(b"II*\0\xFF\xFF\xFF\xFF"), None);
+    }
+}
@@ -0,0 +1,283 @@
+// 印刷用の面付け: 既存の PDF のページを用紙に 2-up / 4-up、または中綴じの冊子順で並べ直す。
+// 元のページは Form XObject に変換して配置するため、ベクタ・画像ともに再エンコードしない。
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+/// 1 枚の用紙にページをどう並べるか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImpositionLayout {
+    /// 横向きの用紙に 2 ページを左右に並べる
+    TwoUp,
+    /// 縦向きの用紙に 2×2 で 4 ページを並べる
+    FourUp,
+    /// 中綴じ冊子: 横向きの用紙の表裏に、折って重ねると順番どおりになるよう 2 ページずつ並べる
+    Booklet,
+}
+
+/// セルへのページの収め方。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageScaling {
+    /// 縦横比を保ってセルいっぱいに拡大縮小する
+    Fit,
+    /// 原寸のまま中央に置く (はみ出した部分はセルで切り抜く)
+    ActualSize,
+}
+
+pub struct ImpositionOptions {
+    pub layout: ImpositionLayout,
+    /// 用紙サイズ (短辺 pt, 長辺 pt)。向きはレイアウトから決める
+    pub paper_pt: (f32, f32),
+    /// ページを時計回りに回転させる角度 (0 / 90 / 180 / 270)
+    pub rotation: u16,
+    pub scaling: PageScaling,
+    /// 右綴じ (右から左に読む)。セルを右上から並べ、冊子は右開きにする
+    pub right_to_left: bool,
+}
+
+/// 面付け前の 1 ページ分 (Form XObject 化済み)。
+struct SourcePage {
+    form_id: ObjectId,
+    /// MediaBox (x0, y0, x1, y1)
+    bbox: (f32, f32, f32, f32),
+    /// ページ自体の /Rotate
+    rotate: u16,
+}
+
+/// PDF ファイルを読み込んで面付けし、同じパスに書き戻す。
+/// 書き戻しは一時ファイル経由で行い、上書き前のファイルは `keep_backups` 世代まで残す。
+pub fn impose_pdf_file(
+    path: &str,
+    options: &ImpositionOptions,
+    keep_backups: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(path)?;
+    impose(&mut doc, options)?;
+    crate::pdf::atomic_write_file(path, keep_backups, |writer| {
+        doc.save_to(writer)?;
+        Ok(())
+    })
+}
+
+/// ドキュメントのページツリーを面付け後の用紙に置き換える。
+pub fn impose(doc: &mut Document, options: &ImpositionOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if ![0, 90, 180, 270].contains(&options.rotation) {
+        return Err(format!("Invalid rotation: {}", options.rotation).into());
+    }
+
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if page_ids.is_empty() {
+        return Err("No pages to impose".into());
+    }
+    let sources = page_ids
+        .iter()
+        .map(|&page_id| page_to_form(doc, page_id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (short, long) = options.paper_pt;
+    let (sheet_w, sheet_h, columns, rows) = match options.layout {
+        ImpositionLayout::TwoUp | ImpositionLayout::Booklet => (long, short, 2, 1),
+        ImpositionLayout::FourUp => (short, long, 2, 2),
+    };
+    let cell_w = sheet_w / columns as f32;
+    let cell_h = sheet_h / rows as f32;
+
+    let pages_id = doc.new_object_id();
+    let mut kids: Vec<Object> = Vec::new();
+
+    for sheet in sheet_slots(sources.len(), options) {
+        let mut xobjects = Dictionary::new();
+        let mut content = String::new();
+
+        for (slot, page_index) in sheet.iter().enumerate() {
+            let Some(page) = page_index.map(|i| &sources[i]) else {
+                continue;
+            };
+            let (column, row) = (slot % columns, slot / columns);
+            // PDF の座標は左下原点なので、1 行目は上端から
+            let cell = (
+                column as f32 * cell_w,
+                sheet_h - (row + 1) as f32 * cell_h,
+                cell_w,
+                cell_h,
+            );
+            let name = format!("P{}", slot);
+            xobjects.set(name.as_bytes(), page.form_id);
+            content.push_str(&place_page(page, cell, &name, options));
+        }
+
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let sheet_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), sheet_w.into(), sheet_h.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+            "Contents" => content_id,
+        });
+        kids.push(sheet_id.into());
+    }
+
+    let count = kids.len() as i64;
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }),
+    );
+    doc.catalog_mut()?.set("Pages", pages_id);
+    // 古いページツリーとコンテンツストリームはどこからも参照されなくなるので取り除く
+    doc.prune_objects();
+    Ok(())
+}
+
+/// 用紙ごとの各セルに置くページ番号 (0 始まり、None は白紙)。セルは左上から行優先の順。
+fn sheet_slots(page_count: usize, options: &ImpositionOptions) -> Vec<Vec<Option<usize>>> {
+    let cells = match options.layout {
+        ImpositionLayout::TwoUp | ImpositionLayout::Booklet => 2,
+        ImpositionLayout::FourUp => 4,
+    };
+
+    let mut sheets: Vec<Vec<Option<usize>>> = match options.layout {
+        ImpositionLayout::TwoUp | ImpositionLayout::FourUp => (0..page_count)
+            .collect::<Vec<_>>()
+            .chunks(cells)
+            .map(|chunk| {
+                let mut slots: Vec<Option<usize>> = chunk.iter().map(|&i| Some(i)).collect();
+                slots.resize(cells, None);
+                slots
+            })
+            .collect(),
+        ImpositionLayout::Booklet => {
+            // 4 の倍数に白紙を足し、外側の用紙から順に [最後, 最初] / [2 番目, 最後から 2 番目] と組む
+            let padded = page_count.div_ceil(4) * 4;
+            let page = |i: usize| Some(i).filter(|&i| i < page_count);
+            (0..padded / 4)
+                .flat_map(|s| {
+                    let front = vec![page(padded - 1 - 2 * s), page(2 * s)];
+                    let back = vec![page(2 * s + 1), page(padded - 2 - 2 * s)];
+                    [front, back]
+                })
+                .collect()
+        }
+    };
+
+    // 右綴じは各行を右から左へ読む。冊子も左右を入れ替えると右開きの順になる
+    if options.right_to_left {
+        for sheet in &mut sheets {
+            for row in sheet.chunks_mut(2) {
+                row.reverse();
+            }
+        }
+    }
+    sheets
+}
+
+/// セル `(x, y, width, height)` にページを配置するコンテンツ演算子列。
+fn place_page(page: &SourcePage, cell: (f32, f32, f32, f32), name: &str, options: &ImpositionOptions) -> String {
+    let (cell_x, cell_y, cell_w, cell_h) = cell;
+    let (x0, y0, x1, y1) = page.bbox;
+    let (page_w, page_h) = (x1 - x0, y1 - y0);
+
+    let rotation = (options.rotation + page.rotate) % 360;
+    let (rotated_w, rotated_h) = if rotation == 0 || rotation == 180 {
+        (page_w, page_h)
+    } else {
+        (page_h, page_w)
+    };
+    let scale = match options.scaling {
+        PageScaling::Fit => (cell_w / rotated_w).min(cell_h / rotated_h),
+        PageScaling::ActualSize => 1.0,
+    };
+    let offset_x = cell_x + (cell_w - rotated_w * scale) / 2.0;
+    let offset_y = cell_y + (cell_h - rotated_h * scale) / 2.0;
+
+    // 回転後のページが (0, 0)-(rotated_w, rotated_h) に収まるよう時計回りに回す
+    let rotate = match rotation {
+        90 => format!("0 -1 1 0 0 {:.4}", page_w),
+        180 => format!("-1 0 0 -1 {:.4} {:.4}", page_w, page_h),
+        270 => format!("0 1 -1 0 {:.4} 0", page_h),
+        _ => "1 0 0 1 0 0".to_string(),
+    };
+
+    format!(
+        "q {:.4} {:.4} {:.4} {:.4} re W n 1 0 0 1 {:.4} {:.4} cm {:.4} 0 0 {:.4} 0 0 cm {} cm 1 0 0 1 {:.4} {:.4} cm /{} Do Q\n",
+        cell_x, cell_y, cell_w, cell_h, offset_x, offset_y, scale, scale, rotate, -x0, -y0, name
+    )
+}
+
+/// ページを同じ見た目の Form XObject に変換する。
+fn page_to_form(doc: &mut Document, page_id: ObjectId) -> Result<SourcePage, Box<dyn std::error::Error>> {
+    let media_box = inherited_attribute(doc, page_id, b"MediaBox")
+        .and_then(|obj| resolve(doc, obj).as_array().ok().cloned())
+        .and_then(|values| {
+            let numbers: Vec<f32> = values.iter().filter_map(|v| v.as_float().ok()).collect();
+            (numbers.len() == 4).then(|| (numbers[0], numbers[1], numbers[2], numbers[3]))
+        })
+        // MediaBox がないページは A4 とみなす
+        .unwrap_or((0.0, 0.0, 595.0, 842.0));
+    let rotate = inherited_attribute(doc, page_id, b"Rotate")
+        .and_then(|obj| resolve(doc, obj).as_i64().ok())
+        .map_or(0, |r| r.rem_euclid(360) as u16 / 90 * 90);
+    let resources = inherited_attribute(doc, page_id, b"Resources").unwrap_or(Object::Dictionary(Dictionary::new()));
+
+    // 複数のコンテンツストリームは演算子の区切りを保つため改行でつなぐ
+    // Form XObject にはフィルタを付けないので、展開できないストリームがあるページは面付けできない
+    let mut content = Vec::new();
+    for stream_id in doc.get_page_contents(page_id) {
+        if let Ok(stream) = doc.get_object(stream_id).and_then(Object::as_stream) {
+            if stream.dict.has(b"Filter") {
+                let data = stream.decompressed_content().map_err(|e| {
+                    format!(
+                        "Failed to decode content stream {} {} of page {} {}: {}",
+                        stream_id.0, stream_id.1, page_id.0, page_id.1, e
+                    )
+                })?;
+                content.extend_from_slice(&data);
+            } else {
+                content.extend_from_slice(&stream.content);
+            }
+            content.push(b'\n');
+        }
+    }
+
+    let (x0, y0, x1, y1) = media_box;
+    let form_id = doc.add_object(Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![x0.into(), y0.into(), x1.into(), y1.into()],
+            "Resources" => resources,
+        },
+        content,
+    ));
+
+    Ok(SourcePage {
+        form_id,
+        bbox: (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)),
+        rotate,
+    })
+}
+
+/// ページ辞書の属性を、なければ親の Pages から継承して取得する。
+/// 参照は解決しない (Resources を参照のまま Form XObject に渡して共有するため)。
+fn inherited_attribute(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut node_id = page_id;
+    // 壊れた PDF の循環参照で無限ループしないよう深さを制限する
+    for _ in 0..32 {
+        let node = doc.get_dictionary(node_id).ok()?;
+        if let Ok(value) = node.get(key) {
+            return Some(value.clone());
+        }
+        node_id = node.get(b"Parent").ok()?.as_reference().ok()?;
+    }
+    None
+}
+
+/// 参照なら参照先のオブジェクトを返す (解決できなければ元のまま)。
+fn resolve(doc: &Document, object: Object) -> Object {
+    match doc.dereference(&object) {
+        Ok((_, resolved)) => resolved.clone(),
+        Err(_) => object,
+    }
+}
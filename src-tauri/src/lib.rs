@@ -1,11 +1,35 @@
 mod pdf;
+mod pdf_writer;
+mod pdf_catalog;
+mod image_dpi;
+mod spread;
+mod imposition;
+mod printing;
+mod backup;
+mod autosave;
+mod file_watcher;
+mod single_instance;
+mod drawing_data;
+mod overlay_render;
+mod pdf_images;
+mod cli;
+mod batch;
+mod zip_archive;
+mod project;
+mod merge;
+mod alignment;
+mod image_diff;
+mod carry_forward;
 mod commands;
 
 use commands::{
-    get_file_size, check_disk_space, save_pdf, save_pdf_v2, load_file, load_files, read_text_file, list_folder_entries,
-    load_files_metadata, load_page_image, print_pdf,
+    get_file_size, check_disk_space, save_pdf, save_pdf_v2, list_backups, restore_backup, estimate_pdf_size, load_file, load_files, read_text_file, list_folder_entries,
+    load_files_metadata, load_page_image, watch_linked_files, unwatch_linked_files, print_pdf, list_printers,
     get_proofreading_check_base_path, list_proofreading_check_directory, read_proofreading_check_file,
-    save_drawing_json, load_drawing_json, list_system_fonts,
+    save_drawing_json, load_drawing_json, autosave_session, list_autosave_sessions, load_autosave_session,
+    discard_autosave_session, list_system_fonts, scan_batch_folder, batch_export,
+    create_project, open_project, save_project, validate_project, merge_drawing_json, diff_page_images,
+    align_page_images, carry_forward_drawings,
     search_json_files_recursive
 };
 
@@ -32,7 +56,9 @@ fn extract_file_paths_from_args() -> Vec<String> {
             continue;
         }
         if is_supported_file(arg) && std::path::Path::new(arg).exists() {
-            files.push(arg.clone());
+            // 起動中のインスタンスに渡すことがあるので、作業フォルダに依存しない絶対パスにする
+            let path = std::path::absolute(arg).map(|p| p.to_string_lossy().into_owned());
+            files.push(path.unwrap_or_else(|_| arg.clone()));
         }
     }
     files
@@ -89,16 +115,72 @@ async fn close_splash(window: tauri::Window) -> Result<(), String> {
         let _ = app.emit("file-open-request", files);
     }
 
+    // 前回クラッシュなどで残った自動保存があれば復旧候補として通知する
+    if let Ok(dir) = autosave::journal_dir(app) {
+        let sessions = tokio::task::spawn_blocking(move || autosave::list_sessions(&dir))
+            .await
+            .unwrap_or_default();
+        if !sessions.is_empty() {
+            let _ = app.emit(autosave::RECOVERY_EVENT, sessions);
+        }
+    }
+
     Ok(())
 }
 
+/// 後から起動されたインスタンスから渡されたファイルを開き、メインウィンドウを前面に出す。
+/// スプラッシュ表示中は起動時のファイルと同じく close_splash で通知する。
+fn open_forwarded_files(app: &tauri::AppHandle, files: Vec<String>) {
+    let files: Vec<String> = files.into_iter().filter(|f| is_supported_file(f)).collect();
+
+    if app.get_webview_window("splash").is_some() {
+        app.state::<PendingFiles>().0.lock().unwrap().extend(files);
+        return;
+    }
+
+    if let Some(main_window) = app.get_webview_window("main") {
+        let _ = main_window.unminimize();
+        let _ = main_window.show();
+        let _ = main_window.set_focus();
+    }
+    if !files.is_empty() {
+        let _ = app.emit("file-open-request", files);
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // `mojiq-pro export ...` などのサブコマンドはウィンドウを作らずに実行して終了する
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
+    let files = extract_file_paths_from_args();
+
+    // 既に起動していればファイルを渡して終了する
+    let listener = match single_instance::acquire(&files) {
+        single_instance::Instance::Forwarded => return,
+        single_instance::Instance::Primary(listener) => listener,
+    };
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(PendingFiles(Mutex::new(extract_file_paths_from_args())))
+        .manage(PendingFiles(Mutex::new(files)))
+        .manage(autosave::AutosaveState::default())
         .setup(|app| {
+            // リンク方式の画像の変更監視
+            app.manage(file_watcher::FileWatcher::start(app.handle().clone()));
+
+            // 後から起動されたインスタンスからのファイルを受け取る
+            if let Some(listener) = listener {
+                let handle = app.handle().clone();
+                std::thread::spawn(move || {
+                    single_instance::serve(listener, move |files| open_forwarded_files(&handle, files));
+                });
+            }
+
             // スプラッシュウィンドウを作成
             let splash_url = tauri::WebviewUrl::App("splash.html".into());
             tauri::WebviewWindowBuilder::new(app, "splash", splash_url)
@@ -120,18 +202,38 @@ pub fn run() {
             check_disk_space,
             save_pdf,
             save_pdf_v2,
+            list_backups,
+            restore_backup,
+            estimate_pdf_size,
             load_file,
             load_files,
             read_text_file,
             list_folder_entries,
             load_files_metadata,
             load_page_image,
+            watch_linked_files,
+            unwatch_linked_files,
             print_pdf,
+            list_printers,
             get_proofreading_check_base_path,
             list_proofreading_check_directory,
             read_proofreading_check_file,
             save_drawing_json,
             load_drawing_json,
+            autosave_session,
+            list_autosave_sessions,
+            load_autosave_session,
+            discard_autosave_session,
+            scan_batch_folder,
+            batch_export,
+            create_project,
+            open_project,
+            save_project,
+            validate_project,
+            merge_drawing_json,
+            diff_page_images,
+            align_page_images,
+            carry_forward_drawings,
             list_system_fonts,
             search_json_files_recursive
         ])
@@ -0,0 +1,495 @@
+// 描画データ (MojiQExportData) の 3-way マージ。
+// 同じ話を複数の校正者 (編集者と監修者など) が別々に描き込んだとき、共通の元 (base) と
+// 2 人分 (ours / theirs) からページごと・オブジェクトの id ごとに変更をまとめる。
+// 両方が同じオブジェクトを別々に変えたもの (または片方が消してもう片方が変えたもの) は競合として
+// 専用のレイヤー (CONFLICT_LAYER_ID) に置き、どちらを残すか描画画面で選べるようにする。
+
+use crate::drawing_data::{MojiQExportData, ExportedObject, PageSize, CURRENT_VERSION};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// 競合したオブジェクトを置くレイヤーの ID (フロントエンドの unflattenToLayers がこの ID のレイヤーを作る)。
+pub const CONFLICT_LAYER_ID: &str = "merge-conflict";
+
+/// 競合レイヤーに置いたオブジェクトの id に付ける接尾辞 (元のオブジェクトと id が重ならないように)。
+const CONFLICT_ID_SUFFIX: &str = "-conflict";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// 両方が別々に変更した。ours を元のレイヤーに残し、theirs を競合レイヤーに置く
+    BothModified,
+    /// 両方が同じ id で別の内容を追加した。扱いは BothModified と同じ
+    BothAdded,
+    /// ours が削除し、theirs が変更した。theirs の変更を競合レイヤーに置く
+    DeletedByOurs,
+    /// theirs が削除し、ours が変更した。ours の変更を競合レイヤーに置く
+    DeletedByTheirs,
+}
+
+/// 競合 1 件。
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeConflict {
+    /// ページ番号 (描画データのキー、0 始まり)
+    pub page: String,
+    /// 競合したオブジェクトの id
+    pub id: String,
+    pub kind: ConflictKind,
+    /// 競合レイヤーに置いたオブジェクトの id
+    pub conflict_id: String,
+}
+
+/// マージの件数。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MergeStats {
+    pub unchanged: usize,
+    pub added_by_ours: usize,
+    pub added_by_theirs: usize,
+    pub modified_by_ours: usize,
+    pub modified_by_theirs: usize,
+    pub deleted_by_ours: usize,
+    pub deleted_by_theirs: usize,
+    pub conflicts: usize,
+}
+
+pub struct MergeResult {
+    pub merged: MojiQExportData,
+    pub conflicts: Vec<MergeConflict>,
+    pub stats: MergeStats,
+}
+
+/// base から ours / theirs への変更をまとめる。
+/// 座標はページごとに ours のページサイズ (ours にないページは theirs) に合わせてから比べる。
+/// オブジェクトの並び (重なり順) は ours の順で、theirs だけが追加したものはページの最後に足す。
+pub fn merge(mut base: MojiQExportData, mut ours: MojiQExportData, mut theirs: MojiQExportData) -> MergeResult {
+    let mut page_sizes = reference_page_sizes(&theirs);
+    page_sizes.extend(reference_page_sizes(&ours));
+    for data in [&mut base, &mut ours, &mut theirs] {
+        data.migrate(Some(&page_sizes));
+    }
+
+    let pages: Vec<String> = {
+        let mut pages: Vec<&String> = base.data.keys().chain(ours.data.keys()).chain(theirs.data.keys()).collect();
+        pages.sort_by_key(|page| page.parse::<usize>().unwrap_or(usize::MAX));
+        pages.dedup();
+        pages.into_iter().cloned().collect()
+    };
+
+    let mut conflicts = Vec::new();
+    let mut stats = MergeStats::default();
+    let mut data = BTreeMap::new();
+    for page in pages {
+        let objects = merge_page(
+            &page,
+            base.data.remove(&page).unwrap_or_default(),
+            ours.data.remove(&page).unwrap_or_default(),
+            theirs.data.remove(&page).unwrap_or_default(),
+            &mut conflicts,
+            &mut stats,
+        );
+        if !objects.is_empty() {
+            data.insert(page, objects);
+        }
+    }
+    stats.conflicts = conflicts.len();
+
+    let checked_state = merge_checked_state(ours.checked_state.take(), theirs.checked_state.take());
+    let page_count = [ours.page_count, theirs.page_count, base.page_count].into_iter().flatten().max();
+    let mut extra = theirs.extra;
+    extra.extend(ours.extra);
+    let merged = MojiQExportData {
+        version: CURRENT_VERSION.to_string(),
+        exported_at: ours.exported_at,
+        page_count,
+        page_sizes: page_sizes.into_iter().filter(|(page, _)| data.contains_key(page)).collect(),
+        checked_state,
+        data,
+        extra,
+    };
+    MergeResult { merged, conflicts, stats }
+}
+
+/// 座標の基準として使えるページサイズ (1.0 のものは基準ではない)。
+fn reference_page_sizes(data: &MojiQExportData) -> BTreeMap<String, PageSize> {
+    if data.version == "1.0" {
+        return BTreeMap::new();
+    }
+    data.page_sizes.clone()
+}
+
+/// 比較用の値。同じ id のオブジェクトはこれが `same_value` なら変更なしとみなす。
+fn object_value(object: &ExportedObject) -> Value {
+    serde_json::to_value(object).unwrap_or(Value::Null)
+}
+
+/// 値が等しいか。ページサイズの違う描画データを合わせたときの丸め誤差は同じとみなす。
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0),
+            _ => a == b,
+        },
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b)),
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| same_value(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+fn merge_page(
+    page: &str,
+    base: Vec<ExportedObject>,
+    ours: Vec<ExportedObject>,
+    theirs: Vec<ExportedObject>,
+    conflicts: &mut Vec<MergeConflict>,
+    stats: &mut MergeStats,
+) -> Vec<ExportedObject> {
+    let base: HashMap<String, ExportedObject> = base.into_iter().map(|o| (o.id.clone(), o)).collect();
+    let mut theirs_by_id: HashMap<String, ExportedObject> = HashMap::new();
+    let mut theirs_order = Vec::new();
+    for object in theirs {
+        theirs_order.push(object.id.clone());
+        theirs_by_id.insert(object.id.clone(), object);
+    }
+
+    let mut merged = Vec::new();
+    let mut conflicted = Vec::new();
+    let mut add_conflict = |object: ExportedObject, kind: ConflictKind, conflicted: &mut Vec<ExportedObject>| {
+        let conflict_id = format!("{}{}", object.id, CONFLICT_ID_SUFFIX);
+        conflicts.push(MergeConflict { page: page.to_string(), id: object.id.clone(), kind, conflict_id: conflict_id.clone() });
+        conflicted.push(ExportedObject { id: conflict_id, layer_id: CONFLICT_LAYER_ID.to_string(), ..object });
+    };
+
+    for our in ours {
+        let their = theirs_by_id.remove(&our.id);
+        let Some(original) = base.get(&our.id) else {
+            // ours が追加したもの
+            match their {
+                Some(their) if !same_value(&object_value(&their), &object_value(&our)) => {
+                    merged.push(our);
+                    add_conflict(their, ConflictKind::BothAdded, &mut conflicted);
+                }
+                Some(_) => {
+                    stats.unchanged += 1;
+                    merged.push(our);
+                }
+                None => {
+                    stats.added_by_ours += 1;
+                    merged.push(our);
+                }
+            }
+            continue;
+        };
+
+        let original = object_value(original);
+        let ours_changed = !same_value(&object_value(&our), &original);
+        match their {
+            None if ours_changed => {
+                // theirs が消したものを ours が変えた
+                add_conflict(our, ConflictKind::DeletedByTheirs, &mut conflicted);
+            }
+            None => stats.deleted_by_theirs += 1,
+            Some(their) => {
+                let their_value = object_value(&their);
+                let theirs_changed = !same_value(&their_value, &original);
+                if !theirs_changed {
+                    if ours_changed {
+                        stats.modified_by_ours += 1;
+                    } else {
+                        stats.unchanged += 1;
+                    }
+                    merged.push(our);
+                } else if !ours_changed {
+                    stats.modified_by_theirs += 1;
+                    merged.push(their);
+                } else if same_value(&their_value, &object_value(&our)) {
+                    // 両方が同じ変更をした
+                    stats.modified_by_ours += 1;
+                    merged.push(our);
+                } else {
+                    merged.push(our);
+                    add_conflict(their, ConflictKind::BothModified, &mut conflicted);
+                }
+            }
+        }
+    }
+
+    // theirs にだけあるもの (theirs の追加、または ours が消したもの)
+    for id in theirs_order {
+        let Some(their) = theirs_by_id.remove(&id) else {
+            continue;
+        };
+        match base.get(&id) {
+            None => {
+                stats.added_by_theirs += 1;
+                merged.push(their);
+            }
+            Some(original) if same_value(&object_value(original), &object_value(&their)) => {
+                stats.deleted_by_ours += 1
+            }
+            Some(_) => add_conflict(their, ConflictKind::DeletedByOurs, &mut conflicted),
+        }
+    }
+    // base にだけあるもの (両方が消した) は残さない
+    merged.extend(conflicted);
+    merged
+}
+
+/// 校正チェックの確認済み状態は、どちらかで確認済みにしたものを確認済みとする (項目ごとの和集合)。
+fn merge_checked_state(ours: Option<Value>, theirs: Option<Value>) -> Option<Value> {
+    let (mut ours, theirs) = match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (ours, theirs) => return ours.or(theirs),
+    };
+    let (Some(our_lists), Value::Object(their_lists)) = (ours.as_object_mut(), theirs) else {
+        return Some(ours);
+    };
+    for (key, their_items) in their_lists {
+        match (our_lists.get_mut(&key), their_items) {
+            (Some(Value::Array(our_items)), Value::Array(their_items)) => {
+                for item in their_items {
+                    if !our_items.contains(&item) {
+                        our_items.push(item);
+                    }
+                }
+            }
+            (None, their_items) => {
+                our_lists.insert(key, their_items);
+            }
+            _ => {}
+        }
+    }
+    Some(ours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 左上が (x, 0) の矩形。
+    fn rect(id: &str, x: f64) -> ExportedObject {
+        serde_json::from_value(json!({
+            "id": id,
+            "type": "shape",
+            "layerId": "layer-1",
+            "shapeType": "rect",
+            "startPos": { "x": x, "y": 0.0 },
+            "endPos": { "x": x + 10.0, "y": 10.0 },
+        }))
+        .unwrap()
+    }
+
+    fn page(objects: &[(&str, f64)]) -> Vec<ExportedObject> {
+        objects.iter().map(|&(id, x)| rect(id, x)).collect()
+    }
+
+    /// (unchanged, added_by_ours, added_by_theirs, modified_by_ours, modified_by_theirs, deleted_by_ours, deleted_by_theirs)
+    fn counts(stats: &MergeStats) -> [usize; 7] {
+        [
+            stats.unchanged,
+            stats.added_by_ours,
+            stats.added_by_theirs,
+            stats.modified_by_ours,
+            stats.modified_by_theirs,
+            stats.deleted_by_ours,
+            stats.deleted_by_theirs,
+        ]
+    }
+
+    struct Case {
+        name: &'static str,
+        base: &'static [(&'static str, f64)],
+        ours: &'static [(&'static str, f64)],
+        theirs: &'static [(&'static str, f64)],
+        /// マージ後の (id, x, layer_id)
+        merged: &'static [(&'static str, f64, &'static str)],
+        conflicts: &'static [(&'static str, ConflictKind)],
+        counts: [usize; 7],
+    }
+
+    const CASES: &[Case] = &[
+        Case {
+            name: "unchanged",
+            base: &[("a", 1.0)],
+            ours: &[("a", 1.0)],
+            theirs: &[("a", 1.0)],
+            merged: &[("a", 1.0, "layer-1")],
+            conflicts: &[],
+            counts: [1, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "rounding differences are unchanged",
+            base: &[("a", 100.0)],
+            ours: &[("a", 100.000_000_01)],
+            theirs: &[("a", 99.999_999_99)],
+            merged: &[("a", 100.000_000_01, "layer-1")],
+            conflicts: &[],
+            counts: [1, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "added by ours",
+            base: &[],
+            ours: &[("a", 1.0)],
+            theirs: &[],
+            merged: &[("a", 1.0, "layer-1")],
+            conflicts: &[],
+            counts: [0, 1, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "added by theirs goes last",
+            base: &[("a", 1.0)],
+            ours: &[("a", 1.0)],
+            theirs: &[("b", 2.0), ("a", 1.0)],
+            merged: &[("a", 1.0, "layer-1"), ("b", 2.0, "layer-1")],
+            conflicts: &[],
+            counts: [1, 0, 1, 0, 0, 0, 0],
+        },
+        Case {
+            name: "both added the same",
+            base: &[],
+            ours: &[("a", 1.0)],
+            theirs: &[("a", 1.0)],
+            merged: &[("a", 1.0, "layer-1")],
+            conflicts: &[],
+            counts: [1, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "both added differently",
+            base: &[],
+            ours: &[("a", 1.0)],
+            theirs: &[("a", 2.0)],
+            merged: &[("a", 1.0, "layer-1"), ("a-conflict", 2.0, CONFLICT_LAYER_ID)],
+            conflicts: &[("a", ConflictKind::BothAdded)],
+            counts: [0, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "modified by ours",
+            base: &[("a", 1.0)],
+            ours: &[("a", 2.0)],
+            theirs: &[("a", 1.0)],
+            merged: &[("a", 2.0, "layer-1")],
+            conflicts: &[],
+            counts: [0, 0, 0, 1, 0, 0, 0],
+        },
+        Case {
+            name: "modified by theirs",
+            base: &[("a", 1.0)],
+            ours: &[("a", 1.0)],
+            theirs: &[("a", 3.0)],
+            merged: &[("a", 3.0, "layer-1")],
+            conflicts: &[],
+            counts: [0, 0, 0, 0, 1, 0, 0],
+        },
+        Case {
+            name: "both modified the same",
+            base: &[("a", 1.0)],
+            ours: &[("a", 2.0)],
+            theirs: &[("a", 2.0)],
+            merged: &[("a", 2.0, "layer-1")],
+            conflicts: &[],
+            counts: [0, 0, 0, 1, 0, 0, 0],
+        },
+        Case {
+            name: "both modified differently",
+            base: &[("a", 1.0)],
+            ours: &[("a", 2.0)],
+            theirs: &[("a", 3.0)],
+            merged: &[("a", 2.0, "layer-1"), ("a-conflict", 3.0, CONFLICT_LAYER_ID)],
+            conflicts: &[("a", ConflictKind::BothModified)],
+            counts: [0, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "deleted by ours",
+            base: &[("a", 1.0)],
+            ours: &[],
+            theirs: &[("a", 1.0)],
+            merged: &[],
+            conflicts: &[],
+            counts: [0, 0, 0, 0, 0, 1, 0],
+        },
+        Case {
+            name: "deleted by theirs",
+            base: &[("a", 1.0)],
+            ours: &[("a", 1.0)],
+            theirs: &[],
+            merged: &[],
+            conflicts: &[],
+            counts: [0, 0, 0, 0, 0, 0, 1],
+        },
+        Case {
+            name: "deleted by ours, modified by theirs",
+            base: &[("a", 1.0)],
+            ours: &[],
+            theirs: &[("a", 3.0)],
+            merged: &[("a-conflict", 3.0, CONFLICT_LAYER_ID)],
+            conflicts: &[("a", ConflictKind::DeletedByOurs)],
+            counts: [0, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "deleted by theirs, modified by ours",
+            base: &[("a", 1.0)],
+            ours: &[("a", 2.0)],
+            theirs: &[],
+            merged: &[("a-conflict", 2.0, CONFLICT_LAYER_ID)],
+            conflicts: &[("a", ConflictKind::DeletedByTheirs)],
+            counts: [0, 0, 0, 0, 0, 0, 0],
+        },
+        Case {
+            name: "deleted by both",
+            base: &[("a", 1.0)],
+            ours: &[],
+            theirs: &[],
+            merged: &[],
+            conflicts: &[],
+            counts: [0, 0, 0, 0, 0, 0, 0],
+        },
+    ];
+
+    #[test]
+    fn merge_page_cases() {
+        for case in CASES {
+            let mut conflicts = Vec::new();
+            let mut stats = MergeStats::default();
+            let merged = merge_page("0", page(case.base), page(case.ours), page(case.theirs), &mut conflicts, &mut stats);
+
+            let merged: Vec<_> = merged
+                .iter()
+                .map(|o| (o.id.as_str(), o.start_pos.as_ref().unwrap().x, o.layer_id.as_str()))
+                .collect();
+            assert_eq!(merged, case.merged, "{}", case.name);
+            let kinds: Vec<_> = conflicts.iter().map(|c| (c.id.as_str(), c.kind)).collect();
+            assert_eq!(kinds, case.conflicts, "{}", case.name);
+            for conflict in &conflicts {
+                assert_eq!(conflict.page, "0", "{}", case.name);
+                assert_eq!(conflict.conflict_id, format!("{}{}", conflict.id, CONFLICT_ID_SUFFIX), "{}", case.name);
+            }
+            assert_eq!(counts(&stats), case.counts, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn merge_counts_every_page() {
+        let data = |pages: &[(&str, &[(&str, f64)])]| MojiQExportData {
+            version: CURRENT_VERSION.to_string(),
+            exported_at: None,
+            page_count: Some(2),
+            page_sizes: BTreeMap::new(),
+            checked_state: None,
+            data: pages.iter().map(|(key, objects)| (key.to_string(), page(objects))).collect(),
+            extra: Default::default(),
+        };
+        let base = data(&[("0", &[("a", 1.0)]), ("1", &[("b", 1.0)])]);
+        let ours = data(&[("0", &[("a", 2.0)]), ("1", &[("b", 2.0)])]);
+        let theirs = data(&[("0", &[("a", 1.0)]), ("1", &[("b", 3.0), ("c", 1.0)])]);
+
+        let result = merge(base, ours, theirs);
+        assert_eq!(counts(&result.stats), [0, 0, 1, 1, 0, 0, 0]);
+        assert_eq!(result.stats.conflicts, 1);
+        assert_eq!(result.conflicts[0].page, "1");
+        let ids: Vec<_> = result.merged.data["1"].iter().map(|o| o.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c", "b-conflict"]);
+    }
+}
@@ -0,0 +1,811 @@
+// 描画データ (MojiQExportData) のページを透過 PNG のオーバーレイに描画する。
+// フロントエンドの drawingRenderer.ts (Canvas 2D) と同じ描き方を tiny-skia で再現し、
+// ウィンドウを開かずに PDF 保存のパイプライン (SaveRequestV2) へ渡せるようにする。
+
+use crate::drawing_data::{Annotation, ExportedObject, LeaderLine, ObjectType, Point};
+use ab_glyph::{Font, FontVec, OutlineCurve};
+use std::f32::consts::PI;
+use std::path::Path;
+use tiny_skia::{
+    BlendMode, Color, FillRule, FilterQuality, LineCap, LineJoin, Paint, Path as SkPath, PathBuilder, Pixmap,
+    PixmapPaint, Rect, Stroke, Transform,
+};
+
+/// フォントが指定されていないときに探す日本語フォント (上から順に最初に見つかったもの)。
+const FALLBACK_FONT_PATHS: &[&str] = &[
+    "C:\\Windows\\Fonts\\YuGothM.ttc",
+    "C:\\Windows\\Fonts\\meiryo.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/fonts-japanese-gothic.ttf",
+];
+
+/// 画面表示のキャンバス領域の目安 (既定のウィンドウサイズ 1280x800 からツールバー等を除いた大きさ)。
+/// 画面では線幅・文字サイズに表示倍率の逆数を掛けて描いているため、その倍率をこの大きさから見積もる。
+const REFERENCE_VIEW_SIZE: (f32, f32) = (900.0, 640.0);
+
+/// 縦書きで 90 度回転して描く文字。
+const VERTICAL_ROTATED_CHARS: &[char] = &[
+    'ー', '−', '―', '…', '(', ')', '（', '）', '[', ']', '「', '」', '～', '〜', '＝', '=',
+];
+
+/// 縦書きで右上に寄せる句読点。
+const VERTICAL_PUNCTUATION_CHARS: &[char] = &['、', '。', '，', '．', '｡', '､'];
+
+/// フォントファイルを読み込む。`path` がなければシステムの日本語フォントを探す。
+pub fn load_font(path: Option<&Path>) -> Result<Option<FontVec>, String> {
+    if let Some(path) = path {
+        let bytes = std::fs::read(path).map_err(|e| format!("フォントを読み込めません: {}: {}", path.display(), e))?;
+        return FontVec::try_from_vec_and_index(bytes, 0)
+            .map(Some)
+            .map_err(|e| format!("フォントを読み込めません: {}: {}", path.display(), e));
+    }
+    Ok(FALLBACK_FONT_PATHS
+        .iter()
+        .filter_map(|p| std::fs::read(p).ok())
+        .find_map(|bytes| FontVec::try_from_vec_and_index(bytes, 0).ok()))
+}
+
+/// 画面表示と同じ線幅になるよう掛ける倍率 (drawingRenderer.ts の renderScale)。
+/// ページを表示領域に収める縮小率の逆数 (拡大表示はしないので 1 以上)。
+pub fn default_line_scale(page_width: f32, page_height: f32) -> f32 {
+    let fit = (REFERENCE_VIEW_SIZE.0 / page_width).min(REFERENCE_VIEW_SIZE.1 / page_height).min(1.0);
+    if fit > 0.0 {
+        1.0 / fit
+    } else {
+        1.0
+    }
+}
+
+pub struct RenderOptions {
+    /// 線幅・文字サイズに掛ける倍率 (`default_line_scale`)
+    pub line_scale: f32,
+    /// PDF 注釈由来のテキスト (コメント) を描かない
+    pub hide_comments: bool,
+}
+
+/// ページのオブジェクトを `out_width` x `out_height` の透過画像に描画する。
+/// 座標は `page_width` x `page_height` (描画時のページサイズ) が基準で、出力サイズに合わせて拡大縮小する。
+/// 描画するものがなければ None。
+pub fn render_page(
+    objects: &[ExportedObject],
+    page_width: f32,
+    page_height: f32,
+    out_width: u32,
+    out_height: u32,
+    font: Option<&FontVec>,
+    options: &RenderOptions,
+) -> Option<Pixmap> {
+    if objects.is_empty() {
+        return None;
+    }
+    let mut pixmap = Pixmap::new(out_width, out_height)?;
+    let mut canvas = Canvas {
+        pixmap: &mut pixmap,
+        base: Transform::from_scale(out_width as f32 / page_width, out_height as f32 / page_height),
+        font,
+        rs: options.line_scale,
+        missing_font_warned: false,
+    };
+
+    // 画面と同じ順序 (レイヤーごとに 画像 → ストローク → 図形 → テキスト) で描く
+    let mut layer_ids: Vec<&str> = Vec::new();
+    for object in objects {
+        if !layer_ids.contains(&object.layer_id.as_str()) {
+            layer_ids.push(&object.layer_id);
+        }
+    }
+    for layer_id in layer_ids {
+        let in_layer = |object_type: ObjectType| {
+            objects
+                .iter()
+                .filter(move |o| o.layer_id == layer_id && o.object_type == object_type)
+        };
+        for object in in_layer(ObjectType::Image) {
+            canvas.draw_image(object);
+        }
+        for object in in_layer(ObjectType::Stroke) {
+            canvas.draw_stroke(object);
+        }
+        for object in in_layer(ObjectType::Shape) {
+            canvas.draw_shape(object);
+        }
+        for object in in_layer(ObjectType::Text) {
+            if options.hide_comments && object.pdf_annotation_source.is_some() {
+                continue;
+            }
+            canvas.draw_text(object);
+        }
+    }
+
+    Some(pixmap)
+}
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("right") => Align::Right,
+            Some("center") => Align::Center,
+            _ => Align::Left,
+        }
+    }
+}
+
+/// Canvas の textBaseline。
+#[derive(Clone, Copy)]
+enum Baseline {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Canvas の strokeText / fillText に相当する描画内容。
+struct TextStyle {
+    size: f32,
+    color: Color,
+    align: Align,
+    baseline: Baseline,
+    bold: bool,
+    /// 白フチの線幅 (なければフチなし)
+    outline_width: Option<f32>,
+}
+
+struct Canvas<'a> {
+    pixmap: &'a mut Pixmap,
+    /// 描画時のページ座標 → 出力画像の画素
+    base: Transform,
+    font: Option<&'a FontVec>,
+    rs: f32,
+    missing_font_warned: bool,
+}
+
+fn pt(p: &Point) -> (f32, f32) {
+    (p.x as f32, p.y as f32)
+}
+
+impl Canvas<'_> {
+    fn stroke_path(&mut self, path: &SkPath, color: Color, width: f32, round: bool, transform: Transform) {
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        paint.anti_alias = true;
+        let stroke = Stroke {
+            width,
+            miter_limit: 10.0,
+            line_cap: if round { LineCap::Round } else { LineCap::Butt },
+            line_join: if round { LineJoin::Round } else { LineJoin::Miter },
+            ..Stroke::default()
+        };
+        self.pixmap.stroke_path(path, &paint, &stroke, transform, None);
+    }
+
+    fn fill_path(&mut self, path: &SkPath, color: Color, transform: Transform) {
+        let mut paint = Paint::default();
+        paint.set_color(color);
+        paint.anti_alias = true;
+        self.pixmap.fill_path(path, &paint, FillRule::Winding, transform, None);
+    }
+
+    fn stroke_polyline(&mut self, points: &[(f32, f32)], color: Color, width: f32, round: bool, transform: Transform) {
+        if let Some(path) = polyline(points) {
+            self.stroke_path(&path, color, width, round, transform);
+        }
+    }
+
+    fn fill_circle(&mut self, (x, y): (f32, f32), radius: f32, color: Color) {
+        if let Some(path) = PathBuilder::from_circle(x, y, radius) {
+            self.fill_path(&path, color, self.base);
+        }
+    }
+
+    /// 引出線と、その起点の●。
+    fn draw_leader_line(&mut self, line: &LeaderLine, color: Color, width: f32, dot_radius: f32) {
+        let (start, end) = (pt(&line.start), pt(&line.end));
+        self.stroke_polyline(&[start, end], color, width, false, self.base);
+        self.fill_circle(start, dot_radius, color);
+    }
+
+    fn draw_stroke(&mut self, object: &ExportedObject) {
+        let points = object.points.as_deref().unwrap_or(&[]);
+        if points.len() < 2 {
+            return;
+        }
+        let is_marker = object.is_marker.unwrap_or(false);
+        let scaled_width = object.width.unwrap_or(2.0) as f32 * self.rs;
+        // Canvas では最後に設定した lineWidth でパス全体が描かれる
+        let width = if is_marker {
+            scaled_width
+        } else {
+            let pressure = points.last().and_then(|p| p.pressure).filter(|p| *p != 0.0).unwrap_or(0.5);
+            scaled_width * (0.5 + pressure as f32)
+        };
+        let Some(path) = polyline(&points.iter().map(pt).collect::<Vec<_>>()) else {
+            return;
+        };
+
+        let mut paint = Paint::default();
+        let mut color = parse_css_color(&object.color);
+        if is_marker {
+            // マーカーは半透明の乗算
+            let opacity = object.opacity.filter(|o| *o != 0.0).unwrap_or(0.3) as f32;
+            color.apply_opacity(opacity);
+            paint.blend_mode = BlendMode::Multiply;
+        }
+        paint.set_color(color);
+        paint.anti_alias = true;
+        let stroke = Stroke {
+            width,
+            line_cap: LineCap::Round,
+            line_join: LineJoin::Round,
+            ..Stroke::default()
+        };
+        self.pixmap.stroke_path(&path, &paint, &stroke, self.base, None);
+    }
+
+    fn draw_shape(&mut self, object: &ExportedObject) {
+        let origin = Point { x: 0.0, y: 0.0, pressure: None };
+        let (sx, sy) = pt(object.start_pos.as_ref().unwrap_or(&origin));
+        let (ex, ey) = pt(object.end_pos.as_ref().unwrap_or(&origin));
+        let shape_type = object.shape_type.as_deref().unwrap_or("rect");
+        let color = parse_css_color(&object.color);
+
+        if shape_type == "stamp" {
+            if let Some(stamp_type) = object.stamp_type.as_deref() {
+                let size = object.size.unwrap_or(20.0) as f32 * self.rs;
+                if let Some(line) = &object.leader_line {
+                    self.draw_leader_line(line, color, 2.0 * self.rs, 3.0);
+                }
+                self.draw_stamp(sx, sy, stamp_type, size, color);
+                return;
+            }
+        }
+
+        let transform = match object.rotation {
+            Some(rotation) if rotation != 0.0 => self.base.pre_concat(Transform::from_rotate_at(
+                (rotation as f32).to_degrees(),
+                (sx + ex) / 2.0,
+                (sy + ey) / 2.0,
+            )),
+            _ => self.base,
+        };
+        let width = object.width.unwrap_or(2.0) as f32 * self.rs;
+        let base_type = shape_type.replace("Annotated", "");
+
+        let (left, right) = (sx.min(ex), sx.max(ex));
+        let (top, bottom) = (sy.min(ey), sy.max(ey));
+        let (w, h) = (right - left, bottom - top);
+
+        match base_type.as_str() {
+            "rect" => {
+                if let Some(rect) = Rect::from_ltrb(left, top, right, bottom) {
+                    self.stroke_path(&PathBuilder::from_rect(rect), color, width, true, transform);
+                }
+                if let Some(label) = &object.font_label {
+                    let style = TextStyle {
+                        size: 16.0 * self.rs,
+                        color,
+                        align: Align::parse(Some(&label.text_align)),
+                        baseline: if ey > sy { Baseline::Top } else { Baseline::Bottom },
+                        bold: true,
+                        outline_width: Some(4.0 * self.rs),
+                    };
+                    self.fill_text(&label.font_name, label.text_x as f32, label.text_y as f32, &style, transform);
+                }
+            }
+            "ellipse" => {
+                let mut pb = PathBuilder::new();
+                push_ellipse_arc(&mut pb, (sx + ex) / 2.0, (sy + ey) / 2.0, w / 2.0, h / 2.0, 0.0, 2.0 * PI);
+                if let Some(path) = pb.finish() {
+                    self.stroke_path(&path, color, width, true, transform);
+                }
+            }
+            "line" => self.stroke_polyline(&[(sx, sy), (ex, ey)], color, width, true, transform),
+            "arrow" | "doubleArrow" => {
+                self.stroke_polyline(&[(sx, sy), (ex, ey)], color, width, true, transform);
+                let head_len = (8.0 * self.rs).max(width * 3.0);
+                let angle = (ey - sy).atan2(ex - sx);
+                self.draw_arrow_head(ex, ey, angle, head_len, color, width, transform);
+                if base_type == "doubleArrow" {
+                    self.draw_arrow_head(sx, sy, angle + PI, head_len, color, width, transform);
+                }
+            }
+            "polyline" => {
+                let points: Vec<(f32, f32)> = object.points.as_deref().unwrap_or(&[]).iter().map(pt).collect();
+                if points.len() >= 2 {
+                    self.stroke_polyline(&points, color, width, true, transform);
+                }
+            }
+            "labeledRect" => {
+                if let Some(line) = &object.leader_line {
+                    let (start, end) = (pt(&line.start), pt(&line.end));
+                    self.stroke_polyline(&[start, end], color, width, true, transform);
+                    if let Some(dot) = PathBuilder::from_circle(start.0, start.1, width.max(2.0 * self.rs)) {
+                        self.fill_path(&dot, color, transform);
+                    }
+                }
+                let size = w.min(h);
+                if let Some(rect) = Rect::from_xywh(left, top, size, size) {
+                    self.stroke_path(&PathBuilder::from_rect(rect), color, width, true, transform);
+                }
+                let label = object.label.as_deref().filter(|l| !l.is_empty()).unwrap_or("小");
+                let padding = 3.0 * self.rs;
+                let style = TextStyle {
+                    size: (10.0 * self.rs).max((16.0 * self.rs).min(size * 0.4)),
+                    color,
+                    align: Align::Right,
+                    baseline: Baseline::Bottom,
+                    bold: true,
+                    outline_width: Some(3.0 * self.rs),
+                };
+                self.fill_text(label, left + size - padding, top + size - padding, &style, transform);
+            }
+            "semicircle" => {
+                let vertical = match object.orientation.as_deref() {
+                    Some(orientation) => orientation == "vertical",
+                    None => h > w,
+                };
+                let (start, end) = if vertical { (-0.5 * PI, 0.5 * PI) } else { (PI, 2.0 * PI) };
+                let mut pb = PathBuilder::new();
+                push_ellipse_arc(&mut pb, (sx + ex) / 2.0, (sy + ey) / 2.0, w / 2.0, h / 2.0, start, end);
+                if let Some(path) = pb.finish() {
+                    self.stroke_path(&path, color, width, true, transform);
+                }
+            }
+            "chevron" => {
+                let points = if object.orientation.as_deref().unwrap_or("vertical") == "vertical" {
+                    [(right, top), (left, (top + bottom) / 2.0), (right, bottom)]
+                } else {
+                    [(left, top), ((left + right) / 2.0, bottom), (right, top)]
+                };
+                self.stroke_polyline(&points, color, width, true, transform);
+            }
+            "lshape" => {
+                let points = match object.direction.unwrap_or(0) {
+                    0 => [(left, bottom), (left, top), (right, top)],
+                    1 => [(right, bottom), (right, top), (left, top)],
+                    2 => [(left, top), (left, bottom), (right, bottom)],
+                    _ => [(right, top), (right, bottom), (left, bottom)],
+                };
+                self.stroke_polyline(&points, color, width, true, transform);
+            }
+            "zshape" => {
+                let points = if object.rotated == Some(true) {
+                    let mid_x = sx + (ex - sx) / 2.0;
+                    [(sx, sy), (mid_x, sy), (mid_x, ey), (ex, ey)]
+                } else {
+                    let mid_y = sy + (ey - sy) / 2.0;
+                    [(sx, sy), (sx, mid_y), (ex, mid_y), (ex, ey)]
+                };
+                self.stroke_polyline(&points, color, width, true, transform);
+            }
+            "bracket" => {
+                let serif = w.min(h) * 0.15;
+                let vertical = match object.orientation.as_deref() {
+                    Some(orientation) => orientation == "vertical",
+                    None => h > w,
+                };
+                let flipped = object.flipped == Some(true);
+                // 本体と、両端のひげ 2 本
+                let (body, serifs) = match (vertical, flipped) {
+                    (true, false) => (
+                        [(left, top), (right, top), (right, bottom), (left, bottom)],
+                        [[(left, top), (left, top - serif)], [(left, bottom), (left, bottom + serif)]],
+                    ),
+                    (true, true) => (
+                        [(right, top), (left, top), (left, bottom), (right, bottom)],
+                        [[(right, top), (right, top - serif)], [(right, bottom), (right, bottom + serif)]],
+                    ),
+                    (false, true) => (
+                        [(left, top), (left, bottom), (right, bottom), (right, top)],
+                        [[(left, top), (left - serif, top)], [(right, top), (right + serif, top)]],
+                    ),
+                    (false, false) => (
+                        [(left, bottom), (left, top), (right, top), (right, bottom)],
+                        [[(left, bottom), (left - serif, bottom)], [(right, bottom), (right + serif, bottom)]],
+                    ),
+                };
+                self.stroke_polyline(&body, color, width, true, transform);
+                for segment in serifs {
+                    self.stroke_polyline(&segment, color, width, true, transform);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(annotation) = &object.annotation {
+            self.draw_annotation(annotation, color);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_arrow_head(
+        &mut self,
+        x: f32,
+        y: f32,
+        angle: f32,
+        head_len: f32,
+        color: Color,
+        width: f32,
+        transform: Transform,
+    ) {
+        for side in [-PI / 6.0, PI / 6.0] {
+            let tip = (x - head_len * (angle + side).cos(), y - head_len * (angle + side).sin());
+            self.stroke_polyline(&[(x, y), tip], color, width, true, transform);
+        }
+    }
+
+    fn draw_stamp(&mut self, x: f32, y: f32, stamp_type: &str, size: f32, color: Color) {
+        let transform = self.base;
+        let centered = |size: f32, outline: f32| TextStyle {
+            size,
+            color,
+            align: Align::Center,
+            baseline: Baseline::Middle,
+            bold: true,
+            outline_width: Some(outline),
+        };
+
+        match stamp_type {
+            "doneStamp" | "komojiStamp" => {
+                // 済 / 小 (円形)
+                let (ring_outline, ring_width, text_outline) = if stamp_type == "doneStamp" {
+                    (5.0, 2.0, 3.0)
+                } else {
+                    (2.5, 1.0, 1.5)
+                };
+                if let Some(circle) = PathBuilder::from_circle(x, y, size / 2.0) {
+                    self.stroke_path(&circle, Color::WHITE, ring_outline, false, transform);
+                    self.stroke_path(&circle, color, ring_width, false, transform);
+                }
+                let text = if stamp_type == "doneStamp" { "済" } else { "小" };
+                self.fill_text(text, x, y, &centered(size * 0.6, text_outline), transform);
+            }
+            "rubyStamp" => {
+                // ルビ (角丸長方形)
+                let (width, height) = (size * 1.8, size * 0.9);
+                if let Some(rect) = rounded_rect(x - width / 2.0, y - height / 2.0, width, height, size * 0.15) {
+                    self.stroke_path(&rect, Color::WHITE, 2.5, false, transform);
+                    self.stroke_path(&rect, color, 1.0, false, transform);
+                }
+                self.fill_text("ルビ", x, y, &centered(size * 0.45, 3.0), transform);
+            }
+            _ => {
+                let text = match stamp_type {
+                    "toruStamp" => "トル",
+                    "torutsumeStamp" => "トルツメ",
+                    "torumamaStamp" => "トルママ",
+                    "zenkakuakiStamp" => "全角アキ",
+                    "hankakuakiStamp" => "半角アキ",
+                    "yonbunakiStamp" => "四分アキ",
+                    "kaigyouStamp" => "改行",
+                    "tojiruStamp" => "とじる",
+                    "hirakuStamp" => "ひらく",
+                    _ => return,
+                };
+                self.fill_text(text, x, y, &centered(size * 0.9, 4.0), transform);
+            }
+        }
+    }
+
+    fn draw_annotation(&mut self, annotation: &Annotation, shape_color: Color) {
+        let color = annotation.color.as_deref().map(parse_css_color).unwrap_or(shape_color);
+        self.draw_leader_line(&annotation.leader_line, color, 2.0 * self.rs, 3.0 * self.rs);
+        self.draw_text_block(
+            &annotation.text,
+            annotation.x as f32,
+            annotation.y as f32,
+            annotation.font_size as f32 * self.rs,
+            annotation.is_vertical,
+            Align::parse(annotation.align.as_deref()),
+            color,
+        );
+    }
+
+    fn draw_text(&mut self, object: &ExportedObject) {
+        let Some(text) = object.text.as_deref() else {
+            return;
+        };
+        self.draw_text_block(
+            text,
+            object.x.unwrap_or(0.0) as f32,
+            object.y.unwrap_or(0.0) as f32,
+            object.font_size.unwrap_or(14.0) as f32 * self.rs,
+            object.is_vertical.unwrap_or(false),
+            Align::Left,
+            parse_css_color(&object.color),
+        );
+    }
+
+    /// 白フチ付きの複数行テキスト (縦書き・横書き)。
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text_block(&mut self, text: &str, x: f32, y: f32, size: f32, vertical: bool, align: Align, color: Color) {
+        if text.is_empty() {
+            return;
+        }
+        let transform = self.base;
+        let outline_width = Some((size * 0.22).max(2.0));
+
+        if !vertical {
+            let style = TextStyle { size, color, align, baseline: Baseline::Top, bold: false, outline_width };
+            for (index, line) in text.split('\n').enumerate() {
+                self.fill_text(line, x, y + index as f32 * size * 1.2, &style, transform);
+            }
+            return;
+        }
+
+        // 縦書き: 1 文字ずつ上から下へ、行は右から左へ
+        let style = TextStyle {
+            size,
+            color,
+            align: Align::Center,
+            baseline: Baseline::Middle,
+            bold: false,
+            outline_width,
+        };
+        for (column, line) in text.split('\n').enumerate() {
+            let current_x = x - column as f32 * size * 1.1;
+            let mut cursor_y = 0.0;
+            for ch in line.chars() {
+                let current_y = y + cursor_y + size / 2.0;
+                if ch == ' ' {
+                    cursor_y += size * 0.3;
+                    continue;
+                }
+                let mut buffer = [0u8; 4];
+                let glyph = ch.encode_utf8(&mut buffer);
+                if VERTICAL_ROTATED_CHARS.contains(&ch) {
+                    let rotated = transform.pre_concat(Transform::from_rotate_at(90.0, current_x, current_y));
+                    self.fill_text(glyph, current_x, current_y, &style, rotated);
+                } else if VERTICAL_PUNCTUATION_CHARS.contains(&ch) {
+                    self.fill_text(glyph, current_x + size * 0.7, current_y - size * 0.55, &style, transform);
+                } else {
+                    self.fill_text(glyph, current_x, current_y, &style, transform);
+                }
+                cursor_y += size;
+            }
+        }
+    }
+
+    /// Canvas の strokeText (白フチ) + fillText。
+    fn fill_text(&mut self, text: &str, x: f32, y: f32, style: &TextStyle, transform: Transform) {
+        let Some(font) = self.font else {
+            if !self.missing_font_warned {
+                eprintln!("[MojiQ] フォントが見つからないため文字を描画できません (--font で指定してください)");
+                self.missing_font_warned = true;
+            }
+            return;
+        };
+        let Some((path, advance)) = text_path(font, text, style.size) else {
+            return;
+        };
+
+        let dx = match style.align {
+            Align::Left => 0.0,
+            Align::Center => -advance / 2.0,
+            Align::Right => -advance,
+        };
+        // em ボックスの上端・中央・下端をベースラインからの位置に直す
+        let ascent = font.ascent_unscaled();
+        let descent = font.descent_unscaled();
+        let ascent_ratio = if ascent - descent > 0.0 { ascent / (ascent - descent) } else { 0.8 };
+        let baseline = match style.baseline {
+            Baseline::Top => y + ascent_ratio * style.size,
+            Baseline::Middle => y + (ascent_ratio - 0.5) * style.size,
+            Baseline::Bottom => y - (1.0 - ascent_ratio) * style.size,
+        };
+        let transform = transform.pre_translate(x + dx, baseline);
+
+        if let Some(outline) = style.outline_width {
+            self.stroke_path(&path, Color::WHITE, outline, false, transform);
+        }
+        self.fill_path(&path, style.color, transform);
+        if style.bold {
+            // 太字の書体がないので輪郭を同じ色でなぞって太らせる
+            self.stroke_path(&path, style.color, style.size / 24.0, false, transform);
+        }
+    }
+
+    fn draw_image(&mut self, object: &ExportedObject) {
+        let (Some(start), Some(end), Some(data)) = (&object.start_pos, &object.end_pos, object.image_data.as_deref())
+        else {
+            return;
+        };
+        let Some(image) = decode_image(data) else {
+            eprintln!("[MojiQ] 画像オブジェクトを読み込めません: {}", object.id);
+            return;
+        };
+        let ((sx, sy), (ex, ey)) = (pt(start), pt(end));
+        let mut transform = self.base;
+        if let Some(rotation) = object.rotation.filter(|r| *r != 0.0) {
+            transform =
+                transform.pre_concat(Transform::from_rotate_at((rotation as f32).to_degrees(), (sx + ex) / 2.0, (sy + ey) / 2.0));
+        }
+        // 終点が始点より左上にあれば Canvas の drawImage と同じく反転する
+        let transform = transform
+            .pre_translate(sx, sy)
+            .pre_scale((ex - sx) / image.width() as f32, (ey - sy) / image.height() as f32);
+        let paint = PixmapPaint { quality: FilterQuality::Bicubic, ..PixmapPaint::default() };
+        self.pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    }
+}
+
+/// 文字列のアウトライン (ベースライン左端が原点、y 下向き) と送り幅。
+fn text_path(font: &FontVec, text: &str, size: f32) -> Option<(SkPath, f32)> {
+    let scale = size / font.units_per_em().unwrap_or(1000.0);
+    let mut pb = PathBuilder::new();
+    let mut pen_x = 0.0;
+    let mut previous = None;
+
+    for ch in text.chars() {
+        let id = font.glyph_id(ch);
+        if let Some(previous) = previous {
+            pen_x += font.kern_unscaled(previous, id) * scale;
+        }
+        if let Some(outline) = font.outline(id) {
+            let map = |p: ab_glyph::Point| (pen_x + p.x * scale, -p.y * scale);
+            let mut last: Option<(f32, f32)> = None;
+            for curve in &outline.curves {
+                let (from, to) = match *curve {
+                    OutlineCurve::Line(a, b) => (map(a), map(b)),
+                    OutlineCurve::Quad(a, _, c) => (map(a), map(c)),
+                    OutlineCurve::Cubic(a, _, _, d) => (map(a), map(d)),
+                };
+                if last != Some(from) {
+                    if last.is_some() {
+                        pb.close();
+                    }
+                    pb.move_to(from.0, from.1);
+                }
+                match *curve {
+                    OutlineCurve::Line(_, _) => pb.line_to(to.0, to.1),
+                    OutlineCurve::Quad(_, b, _) => {
+                        let b = map(b);
+                        pb.quad_to(b.0, b.1, to.0, to.1);
+                    }
+                    OutlineCurve::Cubic(_, b, c, _) => {
+                        let (b, c) = (map(b), map(c));
+                        pb.cubic_to(b.0, b.1, c.0, c.1, to.0, to.1);
+                    }
+                }
+                last = Some(to);
+            }
+            if last.is_some() {
+                pb.close();
+            }
+        }
+        pen_x += font.h_advance_unscaled(id) * scale;
+        previous = Some(id);
+    }
+
+    pb.finish().map(|path| (path, pen_x))
+}
+
+fn polyline(points: &[(f32, f32)]) -> Option<SkPath> {
+    let (first, rest) = points.split_first()?;
+    let mut pb = PathBuilder::new();
+    pb.move_to(first.0, first.1);
+    for (x, y) in rest {
+        pb.line_to(*x, *y);
+    }
+    pb.finish()
+}
+
+/// 楕円弧を 90 度以下のベジェ曲線に分けて追加する (Canvas の ellipse)。
+fn push_ellipse_arc(pb: &mut PathBuilder, cx: f32, cy: f32, rx: f32, ry: f32, start: f32, end: f32) {
+    let point = |angle: f32| (cx + rx * angle.cos(), cy + ry * angle.sin());
+    let (x0, y0) = point(start);
+    if pb.is_empty() {
+        pb.move_to(x0, y0);
+    } else {
+        pb.line_to(x0, y0);
+    }
+
+    let segments = ((end - start).abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+    let step = (end - start) / segments as f32;
+    let k = 4.0 / 3.0 * (step / 4.0).tan();
+    for i in 0..segments {
+        let a0 = start + step * i as f32;
+        let a1 = a0 + step;
+        let (p0x, p0y) = point(a0);
+        let (p3x, p3y) = point(a1);
+        pb.cubic_to(
+            p0x - k * rx * a0.sin(),
+            p0y + k * ry * a0.cos(),
+            p3x + k * rx * a1.sin(),
+            p3y - k * ry * a1.cos(),
+            p3x,
+            p3y,
+        );
+    }
+}
+
+/// 角丸長方形 (ルビスタンプの枠)。
+fn rounded_rect(x: f32, y: f32, w: f32, h: f32, r: f32) -> Option<SkPath> {
+    let mut pb = PathBuilder::new();
+    pb.move_to(x + r, y);
+    pb.line_to(x + w - r, y);
+    push_ellipse_arc(&mut pb, x + w - r, y + r, r, r, -PI / 2.0, 0.0);
+    pb.line_to(x + w, y + h - r);
+    push_ellipse_arc(&mut pb, x + w - r, y + h - r, r, r, 0.0, PI / 2.0);
+    pb.line_to(x + r, y + h);
+    push_ellipse_arc(&mut pb, x + r, y + h - r, r, r, PI / 2.0, PI);
+    pb.line_to(x, y + r);
+    push_ellipse_arc(&mut pb, x + r, y + r, r, r, PI, PI * 1.5);
+    pb.close();
+    pb.finish()
+}
+
+/// 画像オブジェクトの data URL を tiny-skia の (乗算済みアルファの) 画像にする。
+fn decode_image(data_url: &str) -> Option<Pixmap> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+
+    let encoded = data_url.split_once(',').map(|(_, data)| data).unwrap_or(data_url);
+    let bytes = BASE64.decode(encoded).ok()?;
+    let rgba = ::image::load_from_memory(&bytes).ok()?.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut pixmap = Pixmap::new(width, height)?;
+    for (dst, src) in pixmap.pixels_mut().iter_mut().zip(rgba.pixels()) {
+        let [r, g, b, a] = src.0;
+        *dst = tiny_skia::ColorU8::from_rgba(r, g, b, a).premultiply();
+    }
+    Some(pixmap)
+}
+
+/// CSS の色指定 (#rgb / #rrggbb / #rrggbbaa / rgb() / rgba() / 主な色名) を読む。読めなければ黒。
+fn parse_css_color(value: &str) -> Color {
+    let value = value.trim();
+    let hex = |s: &str| u8::from_str_radix(s, 16).ok();
+
+    if let Some(digits) = value.strip_prefix('#') {
+        let rgba = match digits.len() {
+            3 => digits
+                .chars()
+                .map(|c| hex(&c.to_string()).map(|v| v * 17))
+                .collect::<Option<Vec<u8>>>(),
+            6 | 8 => (0..digits.len())
+                .step_by(2)
+                .map(|i| digits.get(i..i + 2).and_then(hex))
+                .collect::<Option<Vec<u8>>>(),
+            _ => None,
+        };
+        return match rgba.as_deref() {
+            Some([r, g, b]) => Color::from_rgba8(*r, *g, *b, 255),
+            Some([r, g, b, a]) => Color::from_rgba8(*r, *g, *b, *a),
+            _ => Color::BLACK,
+        };
+    }
+
+    if let Some(inner) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let parts: Vec<f32> = inner.split(',').filter_map(|p| p.trim().parse().ok()).collect();
+        let channel = |v: f32| v.clamp(0.0, 255.0) as u8;
+        return match parts.as_slice() {
+            [r, g, b] => Color::from_rgba8(channel(*r), channel(*g), channel(*b), 255),
+            [r, g, b, a] => Color::from_rgba8(channel(*r), channel(*g), channel(*b), (a.clamp(0.0, 1.0) * 255.0) as u8),
+            _ => Color::BLACK,
+        };
+    }
+
+    match value.to_ascii_lowercase().as_str() {
+        "white" => Color::WHITE,
+        "red" => Color::from_rgba8(255, 0, 0, 255),
+        "blue" => Color::from_rgba8(0, 0, 255, 255),
+        "green" => Color::from_rgba8(0, 128, 0, 255),
+        "transparent" => Color::TRANSPARENT,
+        _ => Color::BLACK,
+    }
+}
@@ -1,9 +1,13 @@
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 use std::path::Path;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use ::image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
 use ::image::codecs::jpeg::JpegEncoder;
 use printpdf::*;
+use crate::backup::{create_backup, sync_dir, DEFAULT_BACKUP_KEEP};
+use crate::image_dpi::read_image_dpi;
+use crate::pdf_writer::{ImageRect, PdfImage, PdfWriter};
+use crate::spread::{PageLayout, SpreadOptions};
 
 /// 圧縮保存のデフォルト目標サイズ (25MB)。
 /// 旧 MojiQ の pdf-lib-saver.js の compressMode と同等。
@@ -12,37 +16,108 @@ const DEFAULT_COMPRESS_TARGET_BYTES: u64 = 25 * 1024 * 1024;
 /// 圧縮品質探索の候補 (画質優先で上から順に試す)。
 const COMPRESS_QUALITY_STEPS: &[u8] = &[85, 75, 65, 55, 45, 35, 25];
 
+/// 保存サイズの見積もりで実際にエンコードするページ数。
+const ESTIMATE_SAMPLE_PAGES: usize = 3;
+
+/// 圧縮保存で縮小する解像度のデフォルト (dpi)。
+const DEFAULT_COMPRESS_TARGET_DPI: f32 = 300.0;
+
+/// 目標解像度でも収まらない場合にさらに試す解像度 (dpi)。目標より低いものだけを使う。
+const COMPRESS_DPI_STEPS: &[f32] = &[200.0, 150.0];
+
+/// 最後の解像度段階以外では、品質をこれより下げる前に解像度を下げる。
+const COMPRESS_MIN_QUALITY_BEFORE_DOWNSAMPLE: u8 = 65;
+
 /// PDF 構造の固定オーバーヘッドの安全マージン。JPEG byte sum が
 /// (target - margin) 以下になる品質を選ぶ。
 const COMPRESS_OVERHEAD_MARGIN_BYTES: u64 = 512 * 1024; // 512KB
 
+/// 解像度メタデータのない画像の解像度 (1px = 1/72 インチ = 1pt)。
+const DEFAULT_IMAGE_DPI: f32 = 72.0;
+
+/// 解像度メタデータも仕上がりサイズの指定もない画像の実際の解像度を推定するときの大きさ
+/// (B5、短辺 mm, 長辺 mm)。ページサイズは 72 dpi のままで、圧縮時の縮小にだけ使う。
+const DPI_INFERENCE_TRIM_MM: (f32, f32) = (182.0, 257.0);
+
+/// 可逆圧縮 (FlateDecode) のデフォルト圧縮レベル。zlib の標準値と同じ。
+const DEFAULT_FLATE_LEVEL: u32 = 6;
+
+/// モノクロ判定: 彩度 (RGB の max - min) がこれを超える画素を有彩色とみなす。
+/// JPEG の色ノイズで無彩色の画素がわずかに色づく分を吸収する幅。
+const MONO_CHROMA_TOLERANCE: u8 = 24;
+
+/// モノクロ判定: 有彩色の画素がこの割合を超えるページはカラー。
+const MONO_COLOR_PIXEL_RATIO: f64 = 0.001;
+
+/// モノクロ判定: 中間調とみなす輝度の範囲。
+const MONO_MIDTONE_RANGE: std::ops::RangeInclusive<u8> = 48..=207;
+
+/// モノクロ判定: 中間調の画素がこの割合以下なら 2 値 (線画 + トーン) とみなす。
+/// スキャンのアンチエイリアスや JPEG ノイズで線の縁に出る中間調を許容する。
+const MONO_BILEVEL_MIDTONE_RATIO: f64 = 0.02;
+
+/// 2 値化の閾値 (輝度がこれ未満を黒にする)。
+const MONO_BILEVEL_THRESHOLD: u8 = 128;
+
 /// アトミックな書き込みを行う（一時ファイル→リネーム方式）
 /// ディスク容量不足等で書き込み失敗時も元ファイルを保護
 fn atomic_save_pdf(doc: PdfDocumentReference, save_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    atomic_write_file(save_path, DEFAULT_BACKUP_KEEP, |writer| {
+        doc.save(writer)
+            .map_err(|e| format!("Failed to write PDF: {}", e).into())
+    })
+}
+
+/// `atomic_save_pdf` の本体。書き込み処理をクロージャで受け取り、
+/// PDF 以外 (描画データ JSON や .mojiq プロジェクト) の出力にも同じ保護を適用する。
+/// 上書き前のファイルは `.mojiq-backups` に `keep_backups` 世代まで残し、
+/// 書き込んだファイルとフォルダは fsync して電源断でも空のファイルが残らないようにする。
+/// 途中で失敗した場合は一時ファイルを削除する。
+pub(crate) fn atomic_write_file<F>(save_path: &str, keep_backups: usize, write: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut std::io::BufWriter<std::fs::File>) -> Result<(), Box<dyn std::error::Error>>,
+{
     let path = Path::new(save_path);
 
     // 一時ファイルパスを生成（同じディレクトリに作成）
-    let temp_path = path.with_extension("pdf.tmp");
+    // PDF 以外 (.mojiq プロジェクト) にも使うので元の拡張子の後ろに付ける
+    let extension = path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp_path = path.with_extension(format!("{}.tmp", extension));
 
     // 一時ファイルに書き込み
-    {
-        let file = std::fs::File::create(&temp_path)
-            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+    let file = std::fs::File::create(&temp_path)
+        .map_err(|e| format!("Failed to create temp file: {}", e))?;
+    let written = (|| -> Result<(), Box<dyn std::error::Error>> {
         let mut writer = std::io::BufWriter::new(file);
-        doc.save(&mut writer)
-            .map_err(|e| format!("Failed to write PDF: {}", e))?;
+        write(&mut writer)?;
+        let file = writer.into_inner()
+            .map_err(|e| format!("Failed to write temp file: {}", e.error()))?;
+        file.sync_all()
+            .map_err(|e| format!("Failed to sync temp file: {}", e))?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        std::fs::remove_file(&temp_path).ok();
+        return Err(e);
+    }
+
+    // 上書き前のファイルを世代バックアップに残す (失敗しても保存は続ける)
+    if let Err(e) = create_backup(path, keep_backups) {
+        eprintln!("[MojiQ] バックアップを作成できません: {}: {}", path.display(), e);
     }
 
     // 書き込み成功後、元のファイルにリネーム
     // Windowsでは上書きリネームができないため、既存ファイルを先に削除
     if path.exists() {
         // バックアップファイルを作成（リネーム失敗時の保険）
-        let backup_path = path.with_extension("pdf.bak");
+        let backup_path = path.with_extension(format!("{}.bak", extension));
         if backup_path.exists() {
             std::fs::remove_file(&backup_path).ok();
         }
-        std::fs::rename(path, &backup_path)
-            .map_err(|e| format!("Failed to backup original file: {}", e))?;
+        if let Err(e) = std::fs::rename(path, &backup_path) {
+            std::fs::remove_file(&temp_path).ok();
+            return Err(format!("Failed to backup original file: {}", e).into());
+        }
 
         // リネーム実行
         match std::fs::rename(&temp_path, path) {
@@ -66,8 +141,14 @@ fn atomic_save_pdf(doc: PdfDocumentReference, save_path: &str) -> Result<(), Box
         }
     } else {
         // 新規ファイルの場合は単純にリネーム
-        std::fs::rename(&temp_path, path)
-            .map_err(|e| format!("Failed to rename temp file: {}", e))?;
+        if let Err(e) = std::fs::rename(&temp_path, path) {
+            std::fs::remove_file(&temp_path).ok();
+            return Err(format!("Failed to rename temp file: {}", e).into());
+        }
+    }
+
+    if let Err(e) = sync_dir(path.parent().unwrap_or(Path::new(""))) {
+        eprintln!("[MojiQ] フォルダを同期できません: {}: {}", path.display(), e);
     }
 
     Ok(())
@@ -317,42 +398,221 @@ fn parse_color(color: &str) -> (u8, u8, u8) {
 
 /// 合成済みページの情報 (画像本体は呼び出し側で drop を制御するため Option で包む)。
 struct ComposedPage {
-    width_mm: f32,
-    height_mm: f32,
+    geometry: PageGeometry,
     image: Option<DynamicImage>,
 }
 
-/// 背景画像 + 描画オーバーレイを合成して ComposedPage を生成する。
-/// 通常保存・圧縮保存の両パスで共有する。
-fn compose_page(
+/// 合成前のページ (背景とオーバーレイを別々に保持する)。
+struct PageLayers {
+    geometry: PageGeometry,
+    background: Option<DynamicImage>,
+    overlay: Option<DynamicImage>,
+}
+
+/// 背景画像と描画オーバーレイをデコードする。合成はしない。
+fn load_page_layers(
     page_data: &crate::commands::PageDrawingsV2,
     bg_raw: Option<&str>,
-) -> ComposedPage {
-    let bg_loaded = bg_raw.and_then(|bg| {
+    sizing: &PageSizing,
+) -> PageLayers {
+    let background = decode_background(bg_raw);
+    let overlay = decode_overlay(page_data);
+    let geometry = page_geometry(sizing, page_data, background.as_ref());
+
+    PageLayers {
+        geometry,
+        background: background.map(|bg| bg.image),
+        overlay,
+    }
+}
+
+/// デコード済みの背景画像と、元のファイルに記録されていた解像度。
+struct BackgroundImage {
+    image: DynamicImage,
+    /// (水平 dpi, 垂直 dpi)。メタデータがなければ None
+    dpi: Option<(f32, f32)>,
+}
+
+fn decode_background(bg_raw: Option<&str>) -> Option<BackgroundImage> {
+    bg_raw.and_then(|bg| {
         if bg.is_empty() {
             return None;
         }
-        decode_data_url(bg).and_then(|bytes| ::image::load_from_memory(&bytes).ok())
-    });
+        let bytes = decode_data_url(bg)?;
+        let image = ::image::load_from_memory(&bytes).ok()?;
+        Some(BackgroundImage {
+            image,
+            dpi: read_image_dpi(&bytes),
+        })
+    })
+}
 
-    let overlay_loaded = if !page_data.drawing_overlay.is_empty() {
+fn decode_overlay(page_data: &crate::commands::PageDrawingsV2) -> Option<DynamicImage> {
+    if !page_data.drawing_overlay.is_empty() {
         decode_data_url(&page_data.drawing_overlay)
             .and_then(|bytes| ::image::load_from_memory(&bytes).ok())
     } else {
         None
-    };
+    }
+}
+
+/// ページの物理サイズの決め方 (保存リクエストの `trim_size` / `ignore_image_dpi` から決まる)。
+struct PageSizing {
+    /// 強制する仕上がりサイズ (短辺 mm, 長辺 mm)
+    trim_mm: Option<(f32, f32)>,
+    /// 画像の解像度メタデータを使うか
+    use_image_dpi: bool,
+}
 
-    let (width_mm, height_mm) = if let Some(ref img) = bg_loaded {
-        let (w, h) = img.dimensions();
-        (w as f32 * 25.4 / 72.0, h as f32 * 25.4 / 72.0)
+impl PageSizing {
+    fn from_request(request: &SaveRequestV2) -> Result<Self, Box<dyn std::error::Error>> {
+        let trim_mm = match request.trim_size.as_deref().map(str::trim) {
+            Some(spec) if !spec.is_empty() => Some(
+                parse_trim_size(spec).ok_or_else(|| format!("Invalid trim size: {}", spec))?,
+            ),
+            _ => None,
+        };
+        Ok(PageSizing {
+            trim_mm,
+            use_image_dpi: !request.ignore_image_dpi.unwrap_or(false),
+        })
+    }
+
+    /// 画素数と解像度からページサイズ (mm) を求める。
+    /// 解像度が不明なら従来どおり 1px = 1/72 インチとみなす。
+    /// 仕上がりサイズを指定したときは、画像を縦横比を保ったまま仕上がりサイズに収めて中央に置く。
+    fn geometry(&self, width_px: u32, height_px: u32, dpi: Option<(f32, f32)>) -> PageGeometry {
+        if let Some((short_mm, long_mm)) = self.trim_mm {
+            // 見開きなど横長の画像は仕上がりサイズも横向きにする
+            let (width_mm, height_mm) = if width_px > height_px {
+                (long_mm, short_mm)
+            } else {
+                (short_mm, long_mm)
+            };
+            let scale = (width_mm / width_px.max(1) as f32).min(height_mm / height_px.max(1) as f32);
+            return PageGeometry {
+                width_mm,
+                height_mm,
+                content_width_mm: width_px as f32 * scale,
+                content_height_mm: height_px as f32 * scale,
+                image_dpi: 25.4 / scale,
+            };
+        }
+
+        let (dpi_x, dpi_y, image_dpi) = match dpi {
+            Some((dpi_x, dpi_y)) if self.use_image_dpi => (dpi_x, dpi_y, dpi_x.min(dpi_y)),
+            _ => (DEFAULT_IMAGE_DPI, DEFAULT_IMAGE_DPI, infer_image_dpi(width_px, height_px)),
+        };
+        let (width_mm, height_mm) = (
+            width_px as f32 * 25.4 / dpi_x,
+            height_px as f32 * 25.4 / dpi_y,
+        );
+        PageGeometry {
+            width_mm,
+            height_mm,
+            content_width_mm: width_mm,
+            content_height_mm: height_mm,
+            image_dpi,
+        }
+    }
+}
+
+/// 解像度のわからない画像を `DPI_INFERENCE_TRIM_MM` に収めたときの解像度 (dpi)。
+/// 1200 dpi のスキャンにメタデータがなくても、圧縮時に目標解像度まで縮小できるようにする。
+fn infer_image_dpi(width_px: u32, height_px: u32) -> f32 {
+    let (short_mm, long_mm) = DPI_INFERENCE_TRIM_MM;
+    let (width_mm, height_mm) = if width_px > height_px {
+        (long_mm, short_mm)
     } else {
-        (
-            page_data.width as f32 * 25.4 / 72.0,
-            page_data.height as f32 * 25.4 / 72.0,
-        )
+        (short_mm, long_mm)
     };
+    (width_px as f32 * 25.4 / width_mm).max(height_px as f32 * 25.4 / height_mm)
+}
+
+/// ページの大きさと、その中央に置く画像の大きさ (mm)。
+#[derive(Debug, Clone, Copy)]
+struct PageGeometry {
+    width_mm: f32,
+    height_mm: f32,
+    /// 画像 (背景・描画オーバーレイ) を置く範囲の大きさ。仕上がりサイズを指定しなければページと同じ
+    content_width_mm: f32,
+    content_height_mm: f32,
+    /// 画像の解像度 (dpi、縦横で違えば低いほう)。圧縮時に縮小するかの判断に使う
+    image_dpi: f32,
+}
+
+impl PageGeometry {
+    /// ページの大きさ (pt)。
+    fn page_pt(&self) -> (f32, f32) {
+        (Mm(self.width_mm).into_pt().0, Mm(self.height_mm).into_pt().0)
+    }
+
+    /// 画像を置く範囲 (pt、ページの左下原点)。
+    fn content_rect(&self) -> ImageRect {
+        let (page_width, page_height) = self.page_pt();
+        let (width, height) = (
+            Mm(self.content_width_mm).into_pt().0,
+            Mm(self.content_height_mm).into_pt().0,
+        );
+        ImageRect {
+            x: (page_width - width) / 2.0,
+            y: (page_height - height) / 2.0,
+            width,
+            height,
+        }
+    }
+}
+
+/// 仕上がりサイズ・用紙サイズの指定を (短辺 mm, 長辺 mm) に変換する。
+/// 規格名 (A3 / A4 / A5 / A6 / B4 / B5 / B6、B 列は JIS) か "幅x高さ" (mm) を受け付ける。
+pub(crate) fn parse_trim_size(spec: &str) -> Option<(f32, f32)> {
+    let named = match spec.to_ascii_uppercase().as_str() {
+        "A3" => Some((297.0, 420.0)),
+        "A4" => Some((210.0, 297.0)),
+        "A5" => Some((148.0, 210.0)),
+        "A6" => Some((105.0, 148.0)),
+        "B4" => Some((257.0, 364.0)),
+        "B5" => Some((182.0, 257.0)),
+        "B6" => Some((128.0, 182.0)),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+
+    let (w, h) = spec.split_once(['x', 'X', '×'])?;
+    let w: f32 = w.trim().trim_end_matches("mm").trim().parse().ok()?;
+    let h: f32 = h.trim().trim_end_matches("mm").trim().parse().ok()?;
+    if !(w > 0.0 && h > 0.0 && w.is_finite() && h.is_finite()) {
+        return None;
+    }
+    Some((w.min(h), w.max(h)))
+}
+
+/// ページサイズ (mm)。背景画像があればその寸法と解像度、なければ page_data の寸法を使う。
+fn page_geometry(
+    sizing: &PageSizing,
+    page_data: &crate::commands::PageDrawingsV2,
+    background: Option<&BackgroundImage>,
+) -> PageGeometry {
+    if let Some(bg) = background {
+        let (w, h) = bg.image.dimensions();
+        sizing.geometry(w, h, bg.dpi)
+    } else {
+        sizing.geometry(page_data.width, page_data.height, None)
+    }
+}
+
+/// 背景画像 + 描画オーバーレイを合成して ComposedPage を生成する。
+/// 通常保存・圧縮保存の両パスで共有する。
+fn compose_page(
+    page_data: &crate::commands::PageDrawingsV2,
+    bg_raw: Option<&str>,
+    sizing: &PageSizing,
+) -> ComposedPage {
+    let layers = load_page_layers(page_data, bg_raw, sizing);
 
-    let image = match (bg_loaded, overlay_loaded) {
+    let image = match (layers.background, layers.overlay) {
         (Some(bg), Some(overlay)) => Some(composite_images(&bg, &overlay)),
         (Some(bg), None) => Some(bg),
         (None, Some(overlay)) => {
@@ -368,8 +628,7 @@ fn compose_page(
     };
 
     ComposedPage {
-        width_mm,
-        height_mm,
+        geometry: layers.geometry,
         image,
     }
 }
@@ -384,31 +643,47 @@ fn encode_to_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, Box<dyn st
     Ok(buf)
 }
 
+/// `DynamicImage` をグレースケール (1 成分) の JPEG バイト列にエンコードする。
+fn encode_to_jpeg_gray(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let gray = img.to_luma8();
+    let (w, h) = gray.dimensions();
+    let mut buf = Vec::with_capacity((w as usize) * (h as usize) / 2);
+    let mut encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+    encoder.encode(gray.as_raw(), w, h, ::image::ExtendedColorType::L8)?;
+    Ok(buf)
+}
+
 /// 圧縮モード用の 1 ページ分のエンコード済みデータ。
 struct EncodedPage {
-    width_mm: f32,
-    height_mm: f32,
+    geometry: PageGeometry,
     width_px: u32,
     height_px: u32,
     /// 空の場合は画像なし (白ページ)
     jpeg_bytes: Vec<u8>,
+    /// JPEG がグレースケール (1 成分) か
+    grayscale: bool,
 }
 
-/// 圧縮モード用: 指定品質で全ページを逐次合成→JPEG エンコードし、合成画像は即座に drop する。
+/// 圧縮モード用: 指定設定で全ページを逐次合成→縮小→JPEG エンコードし、合成画像は即座に drop する。
 /// これにより、ページ数が多くてもメモリ使用量は「1 ページ分の raw 画像 + 全ページ分の JPEG」に収まる。
 fn encode_all_pages_at_quality(
     request: &SaveRequestV2,
-    quality: u8,
+    setting: CompressSetting,
+    sizing: &PageSizing,
 ) -> Vec<EncodedPage> {
+    let quality = setting.quality;
     let page_count = request.pages.len();
     let mut result: Vec<EncodedPage> = Vec::with_capacity(page_count);
 
     for idx in 0..page_count {
         let page_data = &request.pages[idx];
         let bg_raw = request.background_images.get(idx).map(|s| s.as_str());
-        let composed = compose_page(page_data, bg_raw);
+        let composed = compose_page(page_data, bg_raw, sizing);
+        let image = composed.image.map(|img| {
+            downsample_to_dpi(img, &composed.geometry, setting.dpi)
+        });
 
-        let (width_px, height_px, jpeg_bytes) = if let Some(ref img) = composed.image {
+        let (width_px, height_px, jpeg_bytes) = if let Some(ref img) = image {
             let (w, h) = img.dimensions();
             let bytes = encode_to_jpeg(img, quality).unwrap_or_else(|e| {
                 eprintln!("[MojiQ] JPEG encode failed (quality {}): {}", quality, e);
@@ -420,11 +695,11 @@ fn encode_all_pages_at_quality(
         };
 
         result.push(EncodedPage {
-            width_mm: composed.width_mm,
-            height_mm: composed.height_mm,
+            geometry: composed.geometry,
             width_px,
             height_px,
             jpeg_bytes,
+            grayscale: false,
         });
         // composed.image はここでスコープ外となり drop される → メモリ圧迫を回避
     }
@@ -432,45 +707,197 @@ fn encode_all_pages_at_quality(
     result
 }
 
-/// 圧縮モード用: 目標サイズに収まる最大品質を探索する。
-/// 各品質段階で `encode_all_pages_at_quality` を呼び直すため、
-/// ピクセル画像を全ページ保持する必要がなくメモリ効率が良い
-/// (代わりに CPU コストは最大 `COMPRESS_QUALITY_STEPS.len()` 倍)。
-fn search_jpeg_quality(request: &SaveRequestV2, target_bytes: u64) -> (u8, Vec<EncodedPage>) {
-    let effective_target = target_bytes.saturating_sub(COMPRESS_OVERHEAD_MARGIN_BYTES);
+/// レイヤー分離の圧縮モード用: 背景のみを指定設定で縮小・JPEG 化する (オーバーレイは合成しない)。
+/// `classes` で 2 値と判定済みのページは CCITT G4 で別途エンコードするためスキップし、
+/// グレースケールのページは 1 成分の JPEG にする。
+fn encode_all_backgrounds_at_quality(
+    request: &SaveRequestV2,
+    setting: CompressSetting,
+    classes: &[PageClass],
+    sizing: &PageSizing,
+) -> Vec<EncodedPage> {
+    let quality = setting.quality;
+    let mut result: Vec<EncodedPage> = Vec::with_capacity(request.pages.len());
+
+    for (idx, page_data) in request.pages.iter().enumerate() {
+        let class = classes.get(idx).copied().unwrap_or(PageClass::Color);
+        let bg_raw = request.background_images.get(idx).map(|s| s.as_str());
+        let background = decode_background(bg_raw);
+        let geometry = page_geometry(sizing, page_data, background.as_ref());
+        let grayscale = class == PageClass::Grayscale;
+
+        let (width_px, height_px, jpeg_bytes) = match background {
+            Some(BackgroundImage { image, .. }) if class != PageClass::Bilevel => {
+                let img = &downsample_to_dpi(image, &geometry, setting.dpi);
+                let (w, h) = img.dimensions();
+                let encoded = if grayscale {
+                    encode_to_jpeg_gray(img, quality)
+                } else {
+                    encode_to_jpeg(img, quality)
+                };
+                let bytes = encoded.unwrap_or_else(|e| {
+                    eprintln!("[MojiQ] JPEG encode failed (quality {}): {}", quality, e);
+                    Vec::new()
+                });
+                (w, h, bytes)
+            }
+            _ => (0u32, 0u32, Vec::new()),
+        };
+
+        result.push(EncodedPage {
+            geometry,
+            width_px,
+            height_px,
+            jpeg_bytes,
+            grayscale,
+        });
+    }
+
+    result
+}
+
+/// 圧縮モードの 1 回分のエンコード設定。
+#[derive(Debug, Clone, Copy)]
+struct CompressSetting {
+    /// 縮小先の解像度 (dpi)。None なら縮小しない
+    dpi: Option<f32>,
+    quality: u8,
+}
+
+/// 圧縮保存で試す解像度の段階 (先頭は目標解像度)。品質を下げる前に、まず目標解像度まで縮小する。
+/// 目標解像度以下の画像は `downsample_to_dpi` がそのまま返すので、縮小なしで試すのと同じになる。
+/// `compress_target_dpi` が 0 以下なら縮小せず、品質だけを下げる。
+fn compress_dpi_levels(request: &SaveRequestV2) -> Vec<Option<f32>> {
+    let target_dpi = request
+        .compress_target_dpi
+        .unwrap_or(DEFAULT_COMPRESS_TARGET_DPI);
+    if target_dpi.is_nan() || target_dpi <= 0.0 {
+        return vec![None];
+    }
 
-    let mut last_quality = *COMPRESS_QUALITY_STEPS.last().unwrap();
+    let mut levels = vec![Some(target_dpi)];
+    levels.extend(
+        COMPRESS_DPI_STEPS
+            .iter()
+            .filter(|&&dpi| dpi < target_dpi)
+            .map(|&dpi| Some(dpi)),
+    );
+    levels
+}
+
+/// 解像度 (dpi) 段階と品質段階を組み合わせた試行順。
+/// 各解像度では品質を `COMPRESS_MIN_QUALITY_BEFORE_DOWNSAMPLE` までしか下げず、
+/// 最後の解像度でだけ最低品質まで下げる (高解像度・低品質より低解像度・中品質のほうが見栄えが良い)。
+fn compress_settings(dpi_levels: &[Option<f32>]) -> Vec<CompressSetting> {
+    let mut settings = Vec::new();
+    for (i, &dpi) in dpi_levels.iter().enumerate() {
+        let is_last = i + 1 == dpi_levels.len();
+        settings.extend(
+            COMPRESS_QUALITY_STEPS
+                .iter()
+                .filter(|&&quality| is_last || quality >= COMPRESS_MIN_QUALITY_BEFORE_DOWNSAMPLE)
+                .map(|&quality| CompressSetting { dpi, quality }),
+        );
+    }
+    settings
+}
+
+/// 圧縮モード用: 目標サイズに収まる設定 (解像度 × 品質) を `compress_settings` の順に探索する。
+/// 各設定で `encode` (通常は `encode_all_pages_at_quality`) を呼び直すため、
+/// ピクセル画像を全ページ保持する必要がなくメモリ効率が良い (代わりに CPU コストは試行回数倍)。
+/// 縮小しても画素数が変わらない解像度段階 (元画像がすでに低解像度) は、同じ結果になるので飛ばす。
+/// `reserved_bytes` は品質に依存しない埋め込みデータ (オーバーレイ等) の合計で、目標から差し引く。
+fn search_jpeg_quality<F>(
+    target_bytes: u64,
+    reserved_bytes: u64,
+    dpi_levels: &[Option<f32>],
+    encode: F,
+) -> (CompressSetting, Vec<EncodedPage>)
+where
+    F: Fn(CompressSetting) -> Vec<EncodedPage>,
+{
+    let effective_target = target_bytes
+        .saturating_sub(COMPRESS_OVERHEAD_MARGIN_BYTES)
+        .saturating_sub(reserved_bytes);
+
+    let settings = compress_settings(dpi_levels);
+    let final_dpi = settings.last().unwrap().dpi;
+    let mut last_setting = *settings.last().unwrap();
     let mut last_encoded: Vec<EncodedPage> = Vec::new();
+    let mut last_pixels: Option<u64> = None;
+    let mut skipped_dpi: Option<Option<f32>> = None;
 
-    for &quality in COMPRESS_QUALITY_STEPS {
-        let encoded = encode_all_pages_at_quality(request, quality);
+    for (i, &setting) in settings.iter().enumerate() {
+        if skipped_dpi == Some(setting.dpi) {
+            continue;
+        }
+        let encoded = encode(setting);
         let total: u64 = encoded.iter().map(|p| p.jpeg_bytes.len() as u64).sum();
 
         if total <= effective_target {
-            return (quality, encoded);
+            return (setting, encoded);
         }
 
+        // 解像度段階の最初の試行で画素数が前段階と同じなら、この段階の残りは飛ばす
+        let pixels: u64 = encoded
+            .iter()
+            .map(|p| p.width_px as u64 * p.height_px as u64)
+            .sum();
+        let first_of_level = i == 0 || settings[i - 1].dpi != setting.dpi;
+        if first_of_level && setting.dpi != final_dpi && last_pixels == Some(pixels) {
+            skipped_dpi = Some(setting.dpi);
+        }
+        last_pixels = Some(pixels);
+
         last_encoded = encoded;
-        last_quality = quality;
+        last_setting = setting;
     }
 
     eprintln!(
-        "[MojiQ] 圧縮保存: 目標サイズに収められず、最低品質 {} を使用",
-        last_quality
+        "[MojiQ] 圧縮保存: 目標サイズに収められず、最低設定 (dpi={:?} quality={}) を使用",
+        last_setting.dpi, last_setting.quality
     );
-    (last_quality, last_encoded)
+    (last_setting, last_encoded)
+}
+
+/// 解像度が `target_dpi` を超える画像を縮小する。
+/// スクリーントーンの網点は縮小後のナイキスト周波数付近にあるため、Lanczos のように
+/// 高域を残すフィルタではエイリアシングでモアレになる。ガウシアンで高域を滑らかに落としてから間引く。
+fn downsample_to_dpi(
+    img: DynamicImage,
+    geometry: &PageGeometry,
+    target_dpi: Option<f32>,
+) -> DynamicImage {
+    let Some(target_dpi) = target_dpi else {
+        return img;
+    };
+    let (w, h) = img.dimensions();
+    let scale = target_dpi / geometry.image_dpi;
+    let target_w = (w as f32 * scale).round().max(1.0) as u32;
+    let target_h = (h as f32 * scale).round().max(1.0) as u32;
+    if target_w >= w || target_h >= h {
+        return img;
+    }
+
+    img.resize_exact(target_w, target_h, ::image::imageops::FilterType::Gaussian)
 }
 
 /// 圧縮モード版: 各ページを JPEG 化して DCTDecode filter で直接埋め込む
-fn create_pdf_with_overlays_compressed(
-    save_path: &str,
+fn write_pdf_with_overlays_compressed<W: Write>(
     request: &SaveRequestV2,
+    writer: &mut PdfWriter<W>,
+    layout: &mut PageLayout,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let target = request
         .compress_target_bytes
         .unwrap_or(DEFAULT_COMPRESS_TARGET_BYTES);
+    let sizing = PageSizing::from_request(request)?;
 
-    let (chosen_quality, encoded_pages) = search_jpeg_quality(request, target);
+    let dpi_levels = compress_dpi_levels(request);
+
+    let (chosen, encoded_pages) = search_jpeg_quality(target, 0, &dpi_levels, |setting| {
+        encode_all_pages_at_quality(request, setting, &sizing)
+    });
     let page_count = encoded_pages.len();
     if page_count == 0 {
         return Err("No pages to save".into());
@@ -478,170 +905,594 @@ fn create_pdf_with_overlays_compressed(
 
     let total_bytes: u64 = encoded_pages.iter().map(|p| p.jpeg_bytes.len() as u64).sum();
     eprintln!(
-        "[MojiQ] 圧縮保存: quality={} pages={} total_jpeg={}MB target={}MB",
-        chosen_quality,
+        "[MojiQ] 圧縮保存: dpi={:?} quality={} pages={} total_jpeg={}MB target={}MB",
+        chosen.dpi,
+        chosen.quality,
         page_count,
         total_bytes / (1024 * 1024),
         target / (1024 * 1024)
     );
 
-    let first = &encoded_pages[0];
-    let (doc_init, page1, layer1) = PdfDocument::new(
-        "MojiQ Pro Document",
-        Mm(first.width_mm),
-        Mm(first.height_mm),
-        "Layer 1",
-    );
-
-    let doc = if let Some(subject) = request.mojiq_subject.as_ref() {
-        doc_init.with_subject(subject.clone())
-    } else {
-        doc_init
-    };
-
-    let mut current_layer = doc.get_page(page1).get_layer(layer1);
-
-    for (idx, page) in encoded_pages.into_iter().enumerate() {
-        if idx > 0 {
-            let (new_page, new_layer) = doc.add_page(
-                Mm(page.width_mm),
-                Mm(page.height_mm),
-                format!("Page {}", idx + 1),
-            );
-            current_layer = doc.get_page(new_page).get_layer(new_layer);
-        }
 
+    for page in encoded_pages {
+        let mut images = Vec::new();
         if !page.jpeg_bytes.is_empty() && page.width_px > 0 && page.height_px > 0 {
-            let image = Image::from(ImageXObject {
-                width: Px(page.width_px as usize),
-                height: Px(page.height_px as usize),
-                color_space: ColorSpace::Rgb,
-                bits_per_component: ColorBits::Bit8,
-                interpolate: true,
-                image_data: page.jpeg_bytes,
-                image_filter: Some(ImageFilter::DCT),
-                clipping_bbox: None,
-                smask: None,
-            });
-
-            image.add_to_layer(
-                current_layer.clone(),
-                ImageTransform {
-                    translate_x: Some(Mm(0.0)),
-                    translate_y: Some(Mm(0.0)),
-                    scale_x: None,
-                    scale_y: None,
-                    dpi: Some(72.0),
-                    ..Default::default()
-                },
-            );
+            images.push(PdfImage::jpeg_rgb(page.width_px, page.height_px, page.jpeg_bytes));
         }
+        layout.add_page(
+            writer,
+            page.geometry.page_pt(),
+            page.geometry.content_rect(),
+            images,
+        )?;
     }
 
-    atomic_save_pdf(doc, save_path)?;
     Ok(())
 }
 
 /// 通常モード版: 1 ページずつ合成 → PDF 追加 → drop の単一パス。
-/// 大量ページでもメモリは常に 1 ページ分だけ保持する。
-fn create_pdf_with_overlays_normal(
-    save_path: &str,
+/// 大量ページでもメモリは常に 1 ページ分の raw 画像 + 圧縮済みストリームだけ保持する。
+/// 画像は PNG 予測子付きの FlateDecode で可逆圧縮する。
+fn write_pdf_with_overlays_normal<W: Write>(
     request: &SaveRequestV2,
+    writer: &mut PdfWriter<W>,
+    layout: &mut PageLayout,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let page_count = request.pages.len();
     if page_count == 0 {
         return Err("No pages to save".into());
     }
 
-    // 最初のページを先に合成して寸法を取得 (PdfDocument::new に必要)
-    let first_page_data = &request.pages[0];
-    let first_bg = request.background_images.first().map(|s| s.as_str());
-    let first_composed = compose_page(first_page_data, first_bg);
+    let level = request.flate_level.unwrap_or(DEFAULT_FLATE_LEVEL);
+    let sizing = PageSizing::from_request(request)?;
 
-    let (doc_init, page1, layer1) = PdfDocument::new(
-        "MojiQ Pro Document",
-        Mm(first_composed.width_mm),
-        Mm(first_composed.height_mm),
-        "Layer 1",
-    );
+    for (idx, page_data) in request.pages.iter().enumerate() {
+        let bg_raw = request.background_images.get(idx).map(|s| s.as_str());
+        let composed = compose_page(page_data, bg_raw, &sizing);
 
-    let doc = if let Some(subject) = request.mojiq_subject.as_ref() {
-        doc_init.with_subject(subject.clone())
-    } else {
-        doc_init
-    };
+        let mut images = Vec::new();
+        if let Some(img) = composed.image {
+            let image = PdfImage::flate_rgb(&img.to_rgb8(), level)
+                .map_err(|e| format!("Failed to compress page {}: {}", idx + 1, e))?;
+            images.push(image);
+        }
+        layout.add_page(
+            writer,
+            composed.geometry.page_pt(),
+            composed.geometry.content_rect(),
+            images,
+        )?;
+        // ここで合成画像は drop される → メモリ圧迫を回避
+    }
 
-    let mut current_layer = doc.get_page(page1).get_layer(layer1);
+    Ok(())
+}
+
+/// 背景画像の種別 (モノクロ判定の結果)。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PageClass {
+    /// 白黒 2 値の線画 (スクリーントーンを含む)
+    Bilevel,
+    /// 無彩色だが中間調を含む
+    Grayscale,
+    Color,
+}
 
-    // 最初のページを PDF に追加
-    if let Some(img) = first_composed.image {
-        add_image_to_layer_raw(&current_layer, &img);
+/// 背景画像を 2 値 / グレースケール / カラーに分類する。
+/// 彩度 (RGB の max - min) が `MONO_CHROMA_TOLERANCE` を超える画素が一定割合を超えればカラー、
+/// 無彩色のうち中間調の画素がごくわずかなら 2 値とみなす。
+fn classify_page(img: &DynamicImage) -> PageClass {
+    let (w, h) = img.dimensions();
+    let total = w as u64 * h as u64;
+    if total == 0 {
+        return PageClass::Color;
     }
-    // first_composed はここで drop される
 
-    // 2 ページ目以降を順次処理 (合成 → 追加 → drop)
-    for idx in 1..page_count {
-        let page_data = &request.pages[idx];
+    let count_chromatic = |rgb: &::image::RgbImage| -> u64 {
+        rgb.pixels()
+            .filter(|p| {
+                let [r, g, b] = p.0;
+                r.max(g).max(b) - r.min(g).min(b) > MONO_CHROMA_TOLERANCE
+            })
+            .count() as u64
+    };
+    let chromatic = match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_) => 0,
+        DynamicImage::ImageRgb8(rgb) => count_chromatic(rgb),
+        other => count_chromatic(&other.to_rgb8()),
+    };
+    if chromatic as f64 > total as f64 * MONO_COLOR_PIXEL_RATIO {
+        return PageClass::Color;
+    }
+
+    let midtones = img
+        .to_luma8()
+        .pixels()
+        .filter(|p| MONO_MIDTONE_RANGE.contains(&p.0[0]))
+        .count() as u64;
+    if midtones as f64 <= total as f64 * MONO_BILEVEL_MIDTONE_RATIO {
+        PageClass::Bilevel
+    } else {
+        PageClass::Grayscale
+    }
+}
+
+/// 圧縮モード用: 全ページの背景を分類し、2 値ページだけ CCITT G4 でエンコードしておく。
+fn classify_backgrounds(request: &SaveRequestV2) -> (Vec<PageClass>, Vec<Option<PdfImage>>) {
+    let mut classes = Vec::with_capacity(request.pages.len());
+    let mut bilevel_images = Vec::with_capacity(request.pages.len());
+
+    for idx in 0..request.pages.len() {
         let bg_raw = request.background_images.get(idx).map(|s| s.as_str());
-        let composed = compose_page(page_data, bg_raw);
+        let (class, bilevel) = match decode_background(bg_raw) {
+            Some(BackgroundImage { image: img, .. }) => {
+                let class = classify_page(&img);
+                let bilevel = if class == PageClass::Bilevel {
+                    PdfImage::ccitt_g4(&img.to_luma8(), MONO_BILEVEL_THRESHOLD)
+                } else {
+                    None
+                };
+                // CCITT 化できなかった 2 値ページはグレースケールとして JPEG 化する
+                match bilevel {
+                    Some(image) => (class, Some(image)),
+                    None if class == PageClass::Bilevel => (PageClass::Grayscale, None),
+                    None => (class, None),
+                }
+            }
+            None => (PageClass::Color, None),
+        };
+        classes.push(class);
+        bilevel_images.push(bilevel);
+    }
+
+    let bilevel_count = classes.iter().filter(|&&c| c == PageClass::Bilevel).count();
+    let gray_count = classes.iter().filter(|&&c| c == PageClass::Grayscale).count();
+    eprintln!(
+        "[MojiQ] モノクロ判定: 2値={} グレー={} カラー={}",
+        bilevel_count,
+        gray_count,
+        classes.len() - bilevel_count - gray_count
+    );
+
+    (classes, bilevel_images)
+}
+
+/// 通常モード用: 分類に応じて背景を可逆圧縮する
+/// (2 値 → 1bit CCITT G4、グレースケール → DeviceGray、カラー → DeviceRGB)。
+fn encode_background_lossless(
+    img: &DynamicImage,
+    class: PageClass,
+    level: u32,
+) -> std::io::Result<PdfImage> {
+    match class {
+        PageClass::Bilevel => {
+            let gray = img.to_luma8();
+            match PdfImage::ccitt_g4(&gray, MONO_BILEVEL_THRESHOLD) {
+                Some(image) => Ok(image),
+                None => PdfImage::flate_gray(&gray, level),
+            }
+        }
+        PageClass::Grayscale => PdfImage::flate_gray(&img.to_luma8(), level),
+        PageClass::Color => PdfImage::flate_rgb(&img.to_rgb8(), level),
+    }
+}
+
+/// オーバーレイを可逆圧縮 (FlateDecode) + SMask の画像にする。完全に透明なら None。
+fn encode_overlay(overlay: &DynamicImage, level: u32) -> Option<PdfImage> {
+    let rgba = overlay.to_rgba8();
+    if rgba.pixels().all(|p| p[3] == 0) {
+        return None;
+    }
+    match PdfImage::flate_rgba(&rgba, level) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            eprintln!("[MojiQ] Overlay encode failed: {}", e);
+            None
+        }
+    }
+}
 
-        let (new_page, new_layer) = doc.add_page(
-            Mm(composed.width_mm),
-            Mm(composed.height_mm),
-            format!("Page {}", idx + 1),
+/// レイヤー分離モード: 背景はオーバーレイと合成もリサイズもせずに埋め込み、
+/// オーバーレイは SMask 付きの可逆圧縮画像として上に重ねる。
+/// 圧縮モードでも JPEG 化するのは背景だけなので、赤字の線にブロックノイズが乗らない。
+fn write_pdf_with_separate_overlay<W: Write>(
+    request: &SaveRequestV2,
+    writer: &mut PdfWriter<W>,
+    layout: &mut PageLayout,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let page_count = request.pages.len();
+    if page_count == 0 {
+        return Err("No pages to save".into());
+    }
+
+    let level = request.flate_level.unwrap_or(DEFAULT_FLATE_LEVEL);
+    let detect_monochrome = request.detect_monochrome.unwrap_or(false);
+    let sizing = PageSizing::from_request(request)?;
+
+    if request.compress_mode.unwrap_or(false) {
+        let target = request
+            .compress_target_bytes
+            .unwrap_or(DEFAULT_COMPRESS_TARGET_BYTES);
+
+        // オーバーレイは品質探索に関係しないので先に 1 回だけエンコードする
+        let overlays: Vec<Option<PdfImage>> = request
+            .pages
+            .iter()
+            .map(|page_data| decode_overlay(page_data).and_then(|o| encode_overlay(&o, level)))
+            .collect();
+        let overlay_bytes: u64 = overlays.iter().flatten().map(|o| o.encoded_len()).sum();
+
+        // 2 値ページも品質に依存しない (CCITT G4 は可逆) ので、判定と同時にエンコードしておく
+        let (classes, mut bilevel_images) = if detect_monochrome {
+            classify_backgrounds(request)
+        } else {
+            (vec![PageClass::Color; page_count], (0..page_count).map(|_| None).collect())
+        };
+        let bilevel_bytes: u64 = bilevel_images.iter().flatten().map(|b| b.encoded_len()).sum();
+
+        let dpi_levels = compress_dpi_levels(request);
+        let (chosen, backgrounds) =
+            search_jpeg_quality(target, overlay_bytes + bilevel_bytes, &dpi_levels, |setting| {
+                encode_all_backgrounds_at_quality(request, setting, &classes, &sizing)
+            });
+
+        let background_bytes: u64 = backgrounds.iter().map(|p| p.jpeg_bytes.len() as u64).sum();
+        eprintln!(
+            "[MojiQ] 圧縮保存 (レイヤー分離): dpi={:?} quality={} pages={} background={}MB bilevel={}MB overlay={}MB target={}MB",
+            chosen.dpi,
+            chosen.quality,
+            page_count,
+            background_bytes / (1024 * 1024),
+            bilevel_bytes / (1024 * 1024),
+            overlay_bytes / (1024 * 1024),
+            target / (1024 * 1024)
         );
-        current_layer = doc.get_page(new_page).get_layer(new_layer);
 
-        if let Some(img) = composed.image {
-            add_image_to_layer_raw(&current_layer, &img);
+        for (idx, (background, overlay)) in backgrounds.into_iter().zip(overlays).enumerate() {
+            let mut images = Vec::new();
+            if let Some(bilevel) = bilevel_images[idx].take() {
+                images.push(bilevel);
+            } else if !background.jpeg_bytes.is_empty() && background.width_px > 0 && background.height_px > 0 {
+                images.push(if background.grayscale {
+                    PdfImage::jpeg_gray(background.width_px, background.height_px, background.jpeg_bytes)
+                } else {
+                    PdfImage::jpeg_rgb(background.width_px, background.height_px, background.jpeg_bytes)
+                });
+            }
+            images.extend(overlay);
+            layout.add_page(
+                writer,
+                background.geometry.page_pt(),
+                background.geometry.content_rect(),
+                images,
+            )?;
+        }
+    } else {
+        // 通常モード: 1 ページずつデコード → PDF 追加 → drop
+        for (idx, page_data) in request.pages.iter().enumerate() {
+            let bg_raw = request.background_images.get(idx).map(|s| s.as_str());
+            let layers = load_page_layers(page_data, bg_raw, &sizing);
+
+            let mut images = Vec::new();
+            if let Some(background) = layers.background {
+                let class = if detect_monochrome {
+                    classify_page(&background)
+                } else {
+                    PageClass::Color
+                };
+                let image = encode_background_lossless(&background, class, level)
+                    .map_err(|e| format!("Failed to compress page {}: {}", idx + 1, e))?;
+                images.push(image);
+            }
+            if let Some(ref overlay) = layers.overlay {
+                images.extend(encode_overlay(overlay, level));
+            }
+            layout.add_page(
+                writer,
+                layers.geometry.page_pt(),
+                layers.geometry.content_rect(),
+                images,
+            )?;
         }
-        // ここで composed も drop
     }
 
-    atomic_save_pdf(doc, save_path)?;
     Ok(())
 }
 
-/// 通常モード: raw RGB (FlateDecode) で画像をレイヤーに追加するヘルパー。
-fn add_image_to_layer_raw(layer: &PdfLayerReference, img: &DynamicImage) {
-    let (img_width, img_height) = img.dimensions();
-    let rgb_img = img.to_rgb8();
-
-    let image = Image::from(ImageXObject {
-        width: Px(img_width as usize),
-        height: Px(img_height as usize),
-        color_space: ColorSpace::Rgb,
-        bits_per_component: ColorBits::Bit8,
-        interpolate: true,
-        image_data: rgb_img.into_raw(),
-        image_filter: None,
-        clipping_bbox: None,
-        smask: None,
-    });
+/// 保存モードに応じて PDF を `out` へ逐次書き出す (ディスパッチャ)。書き終えた出力先を返す。
+fn write_pdf_with_overlays<W: Write>(
+    request: &SaveRequestV2,
+    out: W,
+) -> Result<W, Box<dyn std::error::Error>> {
+    let mut layout = page_layout(request)?;
+    let (viewer_preferences, pdf_page_layout) = viewer_settings(request)?;
+    let mut writer = PdfWriter::new(out)?;
+    if let Some(subject) = request.mojiq_subject.as_ref() {
+        writer.set_subject(subject);
+    }
+    writer.set_viewer_settings(viewer_preferences, pdf_page_layout.as_deref());
+
+    // モノクロ判定時はカラーのオーバーレイを 1bit / グレーの背景に合成できないため、レイヤー分離で保存する
+    if request.separate_overlay.unwrap_or(false) || request.detect_monochrome.unwrap_or(false) {
+        write_pdf_with_separate_overlay(request, &mut writer, &mut layout)?;
+    } else if request.compress_mode.unwrap_or(false) {
+        write_pdf_with_overlays_compressed(request, &mut writer, &mut layout)?;
+    } else {
+        write_pdf_with_overlays_normal(request, &mut writer, &mut layout)?;
+    }
+
+    layout.finish(&mut writer)?;
+    writer.finish()
+}
 
-    image.add_to_layer(
-        layer.clone(),
-        ImageTransform {
-            translate_x: Some(Mm(0.0)),
-            translate_y: Some(Mm(0.0)),
-            scale_x: None,
-            scale_y: None,
-            dpi: Some(72.0),
-            ..Default::default()
+/// リクエストの見開き設定から出力ページの並べ方を決める。
+fn page_layout(request: &SaveRequestV2) -> Result<PageLayout, Box<dyn std::error::Error>> {
+    if !request.spread_mode.unwrap_or(false) {
+        return Ok(PageLayout::single());
+    }
+
+    Ok(PageLayout::spread(
+        request.pages.len(),
+        SpreadOptions {
+            right_to_left: is_right_binding(request)?,
+            gutter_pt: Mm(request.spread_gutter_mm.unwrap_or(0.0).max(0.0)).into_pt().0,
+            single_cover: request.spread_single_cover.unwrap_or(true),
+            single_back: request.spread_single_back.unwrap_or(false),
         },
-    );
+    ))
+}
+
+/// PDF の `/PageLayout` に書ける値。
+const PDF_PAGE_LAYOUTS: &[&str] = &[
+    "SinglePage",
+    "OneColumn",
+    "TwoColumnLeft",
+    "TwoColumnRight",
+    "TwoPageLeft",
+    "TwoPageRight",
+];
+
+/// 出力 PDF の `/ViewerPreferences` と `/PageLayout` を決める。
+/// 優先順位は リクエストの明示指定 > 元 PDF の設定 > 綴じ方向からの既定値。
+/// 元 PDF の `/ViewerPreferences` のうち読み方向以外の項目 (HideToolbar など) はそのまま残す。
+fn viewer_settings(
+    request: &SaveRequestV2,
+) -> Result<(::lopdf::Dictionary, Option<String>), Box<dyn std::error::Error>> {
+    let (mut preferences, source_layout) = request
+        .source_pdf_path
+        .as_deref()
+        .map(read_source_viewer_settings)
+        .unwrap_or_default();
+    let right_binding = is_right_binding(request)?;
+
+    match request.reading_direction.as_deref() {
+        Some(direction @ ("R2L" | "L2R")) => {
+            preferences.set("Direction", ::lopdf::Object::Name(direction.as_bytes().to_vec()));
+        }
+        Some(other) => return Err(format!("Invalid reading direction: {}", other).into()),
+        None if !preferences.has(b"Direction") => {
+            let direction = if right_binding { "R2L" } else { "L2R" };
+            preferences.set("Direction", ::lopdf::Object::Name(direction.as_bytes().to_vec()));
+        }
+        None => {}
+    }
+
+    let layout = match request.page_layout.as_deref() {
+        Some(layout) if PDF_PAGE_LAYOUTS.contains(&layout) => layout.to_string(),
+        Some(other) => return Err(format!("Invalid page layout: {}", other).into()),
+        None => match source_layout {
+            Some(layout) => layout,
+            // 見開き出力は 1 枚がすでに見開きなので 1 ページずつ表示する
+            None if request.spread_mode.unwrap_or(false) => "SinglePage".to_string(),
+            None if right_binding => "TwoPageRight".to_string(),
+            None => "TwoPageLeft".to_string(),
+        },
+    };
+
+    Ok((preferences, Some(layout)))
+}
+
+/// 元 PDF のカタログから `/ViewerPreferences` と `/PageLayout` を読む。
+/// 元 PDF 全体は読み込まず、相互参照とカタログ周辺だけを読む。
+/// 読めない PDF (存在しない・壊れている) の場合は何も引き継がない。
+fn read_source_viewer_settings(path: &str) -> (::lopdf::Dictionary, Option<String>) {
+    let read = || -> Result<_, String> {
+        let mut reader = crate::pdf_catalog::PdfCatalogReader::open(path)?;
+        let catalog = reader.catalog()?;
+        let preferences = match catalog.get(b"ViewerPreferences") {
+            Ok(obj) => match reader.resolve(obj)? {
+                ::lopdf::Object::Dictionary(dict) => dict,
+                _ => ::lopdf::Dictionary::new(),
+            },
+            Err(_) => ::lopdf::Dictionary::new(),
+        };
+        let layout = match catalog.get(b"PageLayout") {
+            Ok(obj) => reader.resolve(obj)?.as_name_str().ok().map(str::to_string),
+            Err(_) => None,
+        };
+        Ok((inline_references(&mut reader, preferences), layout))
+    };
+
+    match read() {
+        Ok((preferences, layout)) => {
+            let layout = layout.filter(|name| PDF_PAGE_LAYOUTS.contains(&name.as_str()));
+            (preferences, layout)
+        }
+        Err(e) => {
+            eprintln!("[MojiQ] 元 PDF の閲覧設定を読めませんでした ({}): {}", path, e);
+            Default::default()
+        }
+    }
 }
 
-/// 背景画像と描画オーバーレイを合成してPDFを作成 (ディスパッチャ)
+/// 元 PDF の間接参照は出力 PDF では別のオブジェクトを指してしまうため、値を読んで埋め込む。
+/// 読めない値や、中にさらに間接参照を含む値の項目は引き継がない。
+fn inline_references(
+    reader: &mut crate::pdf_catalog::PdfCatalogReader,
+    dict: ::lopdf::Dictionary,
+) -> ::lopdf::Dictionary {
+    fn has_reference(obj: &::lopdf::Object) -> bool {
+        match obj {
+            ::lopdf::Object::Reference(_) | ::lopdf::Object::Stream(_) => true,
+            ::lopdf::Object::Array(items) => items.iter().any(has_reference),
+            ::lopdf::Object::Dictionary(dict) => dict.iter().any(|(_, value)| has_reference(value)),
+            _ => false,
+        }
+    }
+
+    let mut inlined = ::lopdf::Dictionary::new();
+    for (key, value) in dict.into_iter() {
+        let value = match value {
+            ::lopdf::Object::Reference(_) => match reader.resolve(&value) {
+                Ok(value) => value,
+                Err(_) => continue,
+            },
+            ::lopdf::Object::Array(items) => {
+                match items.iter().map(|item| reader.resolve(item)).collect::<Result<Vec<_>, _>>() {
+                    Ok(items) => ::lopdf::Object::Array(items),
+                    Err(_) => continue,
+                }
+            }
+            value => value,
+        };
+        if has_reference(&value) {
+            eprintln!(
+                "[MojiQ] 元 PDF の閲覧設定 /{} は引き継ぎません (間接参照を含む)",
+                String::from_utf8_lossy(&key)
+            );
+            continue;
+        }
+        inlined.set(key, value);
+    }
+    inlined
+}
+
+/// 綴じ方向が右綴じ (右から左に読む) か。None は右綴じ (日本の漫画の既定)。
+fn is_right_binding(request: &SaveRequestV2) -> Result<bool, Box<dyn std::error::Error>> {
+    match request.binding_direction.as_deref() {
+        None | Some("right") => Ok(true),
+        Some("left") => Ok(false),
+        Some(other) => Err(format!("Invalid binding direction: {}", other).into()),
+    }
+}
+
+/// 背景画像と描画オーバーレイを合成してPDFを作成
 pub fn create_pdf_with_overlays(
     save_path: &str,
     request: &SaveRequestV2,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if request.compress_mode.unwrap_or(false) {
-        create_pdf_with_overlays_compressed(save_path, request)
-    } else {
-        create_pdf_with_overlays_normal(save_path, request)
+    let keep_backups = request.backup_count.unwrap_or(DEFAULT_BACKUP_KEEP);
+    atomic_write_file(save_path, keep_backups, |w| write_pdf_with_overlays(request, w).map(|_| ()))
+}
+
+/// 書き込まれたバイト数だけを数える出力先 (サイズ見積もり用)。
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 見積もりに使うページ番号 (先頭・末尾を含めて均等に `ESTIMATE_SAMPLE_PAGES` ページ)。
+fn sample_page_indices(page_count: usize) -> Vec<usize> {
+    if page_count <= ESTIMATE_SAMPLE_PAGES {
+        return (0..page_count).collect();
     }
+    let mut indices: Vec<usize> = (0..ESTIMATE_SAMPLE_PAGES)
+        .map(|i| i * (page_count - 1) / (ESTIMATE_SAMPLE_PAGES - 1))
+        .collect();
+    indices.dedup();
+    indices
+}
+
+/// 指定ページだけを抜き出した見積もり用のリクエスト。
+/// 圧縮の目標サイズはページ数の比率で按分する (PDF 構造のマージンは 1 回分だけ残す)。
+fn sample_request(
+    request: &SaveRequestV2,
+    indices: &[usize],
+    compress_mode: bool,
+) -> SaveRequestV2 {
+    let page_count = request.pages.len() as u64;
+    let target = request
+        .compress_target_bytes
+        .unwrap_or(DEFAULT_COMPRESS_TARGET_BYTES);
+    let sampled_target = COMPRESS_OVERHEAD_MARGIN_BYTES
+        + target.saturating_sub(COMPRESS_OVERHEAD_MARGIN_BYTES) * indices.len() as u64 / page_count;
+
+    SaveRequestV2 {
+        pages: indices.iter().map(|&i| request.pages[i].clone()).collect(),
+        background_images: indices
+            .iter()
+            .map(|&i| request.background_images.get(i).cloned().unwrap_or_default())
+            .collect(),
+        // Subject は文書全体で 1 つなので、外挿せずに最後に加算する
+        mojiq_subject: None,
+        compress_mode: Some(compress_mode),
+        compress_target_bytes: Some(sampled_target),
+        compress_target_dpi: request.compress_target_dpi,
+        separate_overlay: request.separate_overlay,
+        flate_level: request.flate_level,
+        detect_monochrome: request.detect_monochrome,
+        ignore_image_dpi: request.ignore_image_dpi,
+        trim_size: request.trim_size.clone(),
+        spread_mode: request.spread_mode,
+        binding_direction: request.binding_direction.clone(),
+        spread_gutter_mm: request.spread_gutter_mm,
+        spread_single_cover: request.spread_single_cover,
+        spread_single_back: request.spread_single_back,
+        reading_direction: request.reading_direction.clone(),
+        page_layout: request.page_layout.clone(),
+        // 閲覧設定はサイズにほぼ影響しないので、元 PDF の読み込みは省く
+        source_pdf_path: None,
+        backup_count: None,
+    }
+}
+
+/// 数ページを実際にエンコードし、その出力サイズをページ数で外挿して PDF 全体のサイズを見積もる。
+/// 通常保存と圧縮保存の両方を見積もる (その他のオプションはリクエストの指定に従う)。
+/// 元 PDF のページ内容を残す保存モードはないため、見積もるのはこの 2 つだけ。
+pub fn estimate_pdf_size(
+    request: &SaveRequestV2,
+) -> Result<crate::commands::PdfSizeEstimate, Box<dyn std::error::Error>> {
+    let page_count = request.pages.len();
+    if page_count == 0 {
+        return Err("No pages to save".into());
+    }
+
+    let indices = sample_page_indices(page_count);
+    let subject_bytes = request.mojiq_subject.as_ref().map_or(0, |s| s.len() as u64);
+
+    let estimate = |compress_mode: bool| -> Result<u64, Box<dyn std::error::Error>> {
+        let sample = sample_request(request, &indices, compress_mode);
+        let counter = write_pdf_with_overlays(&sample, ByteCounter(0))?;
+        Ok(counter.0 * page_count as u64 / indices.len() as u64 + subject_bytes)
+    };
+    let normal_bytes = estimate(false)?;
+    let compressed_bytes = estimate(true)?;
+
+    eprintln!(
+        "[MojiQ] 保存サイズ見積もり: pages={} sampled={} normal={}MB compressed={}MB",
+        page_count,
+        indices.len(),
+        normal_bytes / (1024 * 1024),
+        compressed_bytes / (1024 * 1024)
+    );
+
+    Ok(crate::commands::PdfSizeEstimate {
+        page_count,
+        sampled_pages: indices.len(),
+        normal_bytes,
+        compressed_bytes,
+    })
 }
 
 /// 2つの画像を合成（オーバーレイのアルファチャンネルを使用）
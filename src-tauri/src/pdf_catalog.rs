@@ -0,0 +1,297 @@
+// PDF 全体を読み込まずに、必要なオブジェクト (カタログなど) だけを読む。
+// ファイル末尾の startxref から相互参照 (xref 表 / xref ストリーム、/Prev をたどる) を読み、
+// オブジェクトは位置を求めてその周辺だけを読み込んで lopdf に解析させる。
+// 数百 MB の元 PDF から閲覧設定 (/ViewerPreferences など) を引き継ぐときに使う。
+
+use ::lopdf::xref::XrefEntry;
+use ::lopdf::{Dictionary, Document, Object, ObjectId, ObjectStream, Reader, Stream};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// startxref を探すファイル末尾の長さ
+const TAIL_BYTES: u64 = 1024;
+/// オブジェクトを読むときに最初に読み込む長さ。解析できなければ倍にして読み直す
+const INITIAL_WINDOW_BYTES: usize = 64 * 1024;
+/// 1 つのオブジェクト (xref ストリーム・オブジェクトストリームを含む) に読み込む長さの上限
+const MAX_WINDOW_BYTES: usize = 16 * 1024 * 1024;
+/// たどる相互参照の数の上限 (/Prev の循環対策)
+const MAX_XREF_SECTIONS: usize = 64;
+
+/// オブジェクトの場所。
+#[derive(Clone, Copy)]
+enum Location {
+    Offset { offset: u64, generation: u16 },
+    /// オブジェクトストリームの中
+    Compressed { container: u32 },
+}
+
+/// 必要なオブジェクトだけを読む PDF の読み手。
+pub struct PdfCatalogReader {
+    file: File,
+    len: u64,
+    locations: HashMap<u32, Location>,
+    trailer: Dictionary,
+}
+
+impl PdfCatalogReader {
+    /// ファイルを開き、相互参照とトレーラーだけを読む。
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open PDF: {}", e))?;
+        let len = file.metadata().map_err(|e| format!("Failed to read PDF: {}", e))?.len();
+        let mut reader = PdfCatalogReader {
+            file,
+            len,
+            locations: HashMap::new(),
+            trailer: Dictionary::new(),
+        };
+
+        let mut pending = vec![reader.xref_start()?];
+        let mut seen = HashSet::new();
+        let mut first = true;
+        while let Some(offset) = pending.pop() {
+            if !seen.insert(offset) || seen.len() > MAX_XREF_SECTIONS {
+                continue;
+            }
+            let trailer = reader.read_xref_section(offset)?;
+            // 新しい相互参照から順に読むので、先に読んだ場所を優先する
+            for key in [b"Prev".as_slice(), b"XRefStm".as_slice()] {
+                if let Ok(prev) = trailer.get(key).and_then(Object::as_i64) {
+                    pending.push(prev.max(0) as u64);
+                }
+            }
+            if first {
+                reader.trailer = trailer;
+                first = false;
+            }
+        }
+        Ok(reader)
+    }
+
+    /// カタログ (トレーラーの /Root)。
+    pub fn catalog(&mut self) -> Result<Dictionary, String> {
+        let root = self.trailer.get(b"Root").map_err(|_| "PDF has no /Root".to_string())?.clone();
+        match self.resolve(&root)? {
+            Object::Dictionary(dict) => Ok(dict),
+            _ => Err("PDF /Root is not a dictionary".to_string()),
+        }
+    }
+
+    /// 間接参照ならその先のオブジェクトを読む。それ以外はそのまま返す。
+    pub fn resolve(&mut self, object: &Object) -> Result<Object, String> {
+        match object {
+            Object::Reference(id) => self.object(*id),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// オブジェクトを 1 つ読む。
+    pub fn object(&mut self, id: ObjectId) -> Result<Object, String> {
+        match self.locations.get(&id.0).copied() {
+            Some(Location::Offset { offset, generation }) if generation == id.1 => self.read_object_at(offset, Some(id)),
+            Some(Location::Compressed { container }) => {
+                let Object::Stream(mut stream) = self.object((container, 0))? else {
+                    return Err(format!("Object stream {} is not a stream", container));
+                };
+                let mut objects = ObjectStream::new(&mut stream)
+                    .map_err(|e| format!("Failed to read object stream {}: {}", container, e))?
+                    .objects;
+                objects.remove(&id).ok_or_else(|| format!("Object {} {} not found", id.0, id.1))
+            }
+            _ => Err(format!("Object {} {} not found", id.0, id.1)),
+        }
+    }
+
+    /// ファイル末尾の startxref が指す位置。
+    fn xref_start(&mut self) -> Result<u64, String> {
+        let tail = self.read_at(self.len.saturating_sub(TAIL_BYTES), TAIL_BYTES as usize)?;
+        let position = tail
+            .windows(b"startxref".len())
+            .rposition(|w| w == b"startxref")
+            .ok_or("PDF has no startxref")?;
+        let digits: String = tail[position + b"startxref".len()..]
+            .iter()
+            .map(|&b| b as char)
+            .skip_while(|c| c.is_ascii_whitespace())
+            .take_while(char::is_ascii_digit)
+            .collect();
+        digits.parse().map_err(|_| "PDF has an invalid startxref".to_string())
+    }
+
+    /// `offset` の相互参照 (xref 表または xref ストリーム) を読み、その場所を登録してトレーラーを返す。
+    fn read_xref_section(&mut self, offset: u64) -> Result<Dictionary, String> {
+        let head = self.read_at(offset, 16)?;
+        if head.trim_ascii_start().starts_with(b"xref") {
+            self.read_xref_table(offset)
+        } else {
+            self.read_xref_stream(offset)
+        }
+    }
+
+    /// 従来の xref 表。エントリの行末は空白の種類がまちまちなので、空白区切りで読む。
+    fn read_xref_table(&mut self, offset: u64) -> Result<Dictionary, String> {
+        let mut size = INITIAL_WINDOW_BYTES;
+        loop {
+            let window = self.read_at(offset, size)?;
+            if let Some(trailer) = self.parse_xref_table(&window) {
+                return Ok(trailer);
+            }
+            if window.len() < size || size >= MAX_WINDOW_BYTES {
+                return Err(format!("Failed to read xref table at {}", offset));
+            }
+            size *= 2;
+        }
+    }
+
+    fn parse_xref_table(&mut self, window: &[u8]) -> Option<Dictionary> {
+        let trailer_at = window.windows(b"trailer".len()).position(|w| w == b"trailer")?;
+        let trailer = parse_direct_dictionary(&window[trailer_at + b"trailer".len()..])?;
+
+        let table = std::str::from_utf8(&window[..trailer_at]).ok()?;
+        let mut tokens = table.split_ascii_whitespace().skip(1);
+        let mut entries = Vec::new();
+        while let (Some(start), Some(count)) = (tokens.next(), tokens.next()) {
+            let (start, count) = (start.parse::<u32>().ok()?, count.parse::<u32>().ok()?);
+            for i in 0..count {
+                let (offset, generation, kind) = (tokens.next()?, tokens.next()?, tokens.next()?);
+                if kind == "n" {
+                    let location = Location::Offset {
+                        offset: offset.parse().ok()?,
+                        generation: generation.parse().ok()?,
+                    };
+                    entries.push((start + i, location));
+                }
+            }
+        }
+        for (id, location) in entries {
+            self.locations.entry(id).or_insert(location);
+        }
+        Some(trailer)
+    }
+
+    /// xref ストリーム (PDF 1.5 以降)。ストリームの辞書がトレーラーを兼ねる。
+    fn read_xref_stream(&mut self, offset: u64) -> Result<Dictionary, String> {
+        let Object::Stream(stream) = self.read_object_at(offset, None)? else {
+            return Err(format!("No xref stream at {}", offset));
+        };
+        let content = if stream.dict.has(b"Filter") {
+            stream
+                .decompressed_content()
+                .map_err(|e| format!("Failed to decode xref stream: {}", e))?
+        } else {
+            stream.content.clone()
+        };
+        let widths = stream
+            .dict
+            .get(b"W")
+            .and_then(Object::as_array)
+            .map_err(|_| "xref stream has no /W".to_string())?
+            .iter()
+            .map(|w| w.as_i64().map(|w| w.clamp(0, 8) as usize))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "xref stream has an invalid /W".to_string())?;
+        let [w_kind, w_field, w_index] = widths[..] else {
+            return Err("xref stream has an invalid /W".to_string());
+        };
+        let size = stream.dict.get(b"Size").and_then(Object::as_i64).unwrap_or(0);
+        let index = match stream.dict.get(b"Index").and_then(Object::as_array) {
+            Ok(index) => index.iter().filter_map(|v| v.as_i64().ok()).collect(),
+            Err(_) => vec![0, size],
+        };
+
+        let row = w_kind + w_field + w_index;
+        let mut rows = content.chunks_exact(row.max(1));
+        for range in index.chunks_exact(2) {
+            for id in range[0].max(0)..range[0].max(0) + range[1].max(0) {
+                let Some(entry) = rows.next() else {
+                    break;
+                };
+                // 種類の幅が 0 なら種類 1 (ファイル内の位置)
+                let kind = if w_kind == 0 { 1 } else { be_number(&entry[..w_kind]) };
+                let field = be_number(&entry[w_kind..w_kind + w_field]);
+                let generation = be_number(&entry[w_kind + w_field..]);
+                let location = match kind {
+                    1 => Location::Offset { offset: field, generation: generation as u16 },
+                    2 => Location::Compressed { container: field as u32 },
+                    _ => continue,
+                };
+                self.locations.entry(id as u32).or_insert(location);
+            }
+        }
+        Ok(stream.dict)
+    }
+
+    /// `offset` にある間接オブジェクトを読む。読み込む範囲を広げながら解析できるまで試す。
+    fn read_object_at(&mut self, offset: u64, expected: Option<ObjectId>) -> Result<Object, String> {
+        let mut size = INITIAL_WINDOW_BYTES;
+        loop {
+            let window = self.read_at(offset, size)?;
+            let complete = window.len() < size || size >= MAX_WINDOW_BYTES;
+            match self.parse_object(&window, expected) {
+                Some(object) => return Ok(object),
+                None if complete => return Err(format!("Failed to read PDF object at {}", offset)),
+                None => size *= 2,
+            }
+        }
+    }
+
+    fn parse_object(&mut self, window: &[u8], expected: Option<ObjectId>) -> Option<Object> {
+        let start = window.len() - window.trim_ascii_start().len();
+        let id = match expected {
+            Some(id) => id,
+            None => parse_object_id(&window[start..])?,
+        };
+        // 読み込んだ範囲の先頭にだけオブジェクトがある文書として lopdf に解析させる
+        let mut document = Document::new();
+        document.reference_table.insert(id.0, XrefEntry::Normal { offset: start as u32, generation: id.1 });
+        let reader = Reader { buffer: window, document };
+        let object = reader.get_object(id, &mut HashSet::new()).ok()?;
+
+        match object {
+            // /Length が間接参照のストリームは中身が読まれないので、長さを求めて切り出す
+            Object::Stream(Stream { dict, start_position: Some(position), .. }) => {
+                let length = self.resolve(dict.get(b"Length").ok()?).ok()?.as_i64().ok()?;
+                let content = window.get(position..position.checked_add(length.try_into().ok()?)?)?;
+                Some(Object::Stream(Stream::new(dict, content.to_vec())))
+            }
+            object => Some(object),
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, String> {
+        let size = size.min(self.len.saturating_sub(offset) as usize);
+        let mut buffer = vec![0; size];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut buffer))
+            .map_err(|e| format!("Failed to read PDF: {}", e))?;
+        Ok(buffer)
+    }
+}
+
+/// "12 0 obj" の番号と世代。
+fn parse_object_id(bytes: &[u8]) -> Option<ObjectId> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(32)]);
+    let mut tokens = head.split_ascii_whitespace();
+    let id = tokens.next()?.parse().ok()?;
+    let generation = tokens.next()?.parse().ok()?;
+    (tokens.next()?.starts_with("obj")).then_some((id, generation))
+}
+
+/// 間接オブジェクトではない辞書 (トレーラー) を解析する。
+fn parse_direct_dictionary(bytes: &[u8]) -> Option<Dictionary> {
+    let mut buffer = b"1 0 obj\n".to_vec();
+    buffer.extend_from_slice(bytes);
+    let mut document = Document::new();
+    document.reference_table.insert(1, XrefEntry::Normal { offset: 0, generation: 0 });
+    let reader = Reader { buffer: &buffer, document };
+    match reader.get_object((1, 0), &mut HashSet::new()).ok()? {
+        Object::Dictionary(dict) => Some(dict),
+        _ => None,
+    }
+}
+
+/// xref ストリームのフィールド (ビッグエンディアン)。
+fn be_number(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| (n << 8) | b as u64)
+}
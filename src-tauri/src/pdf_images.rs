@@ -0,0 +1,451 @@
+// スキャン画像の PDF (1 ページに 1 枚の画像) から各ページの画像を取り出す。
+// GUI では pdf.js でページを描画して背景にするが、ヘッドレス書き出しでは PDF を描画できないため、
+// ページに貼られている画像そのものを背景として使う。対応するのは画像だけのページで、
+// 画像の上に文字 (写植) や線・塗りを描いているページは、画像だけを使うと内容が欠けるためエラーにする。
+// OCR 済みのスキャン PDF の透明テキスト (描画モード 3 / 7) は見えないので画像だけのページとして扱う。
+
+use ::image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use ::lopdf::content::Content;
+use ::lopdf::{Dictionary, Document, Object, ObjectId};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+/// 描画しない文字の描画モード (3 = 不可視、7 = クリップのみ)。
+const INVISIBLE_TEXT_MODES: [i64; 2] = [3, 7];
+
+/// フォーム XObject をたどる入れ子の上限。
+const MAX_FORM_DEPTH: usize = 8;
+
+/// ページから取り出した背景画像。
+pub struct PageImage {
+    /// 保存リクエストの background_images にそのまま渡せる data URL
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+    /// ページの MediaBox の大きさ (pt)
+    pub page_size_pt: (f32, f32),
+}
+
+/// すべてのページの背景画像を取り出す。
+pub fn extract_page_images(path: &str) -> Result<Vec<PageImage>, Box<dyn std::error::Error>> {
+    let doc = Document::load(path)?;
+    doc.get_pages()
+        .into_iter()
+        .map(|(page_number, page_id)| {
+            extract_page_image(&doc, page_id).map_err(|e| format!("{} ページ目: {}", page_number, e).into())
+        })
+        .collect()
+}
+
+/// 1 ページ (0 始まり) の背景画像を取り出す。
+pub fn extract_page_image_at(path: &str, page_index: usize) -> Result<PageImage, Box<dyn std::error::Error>> {
+    let doc = Document::load(path)?;
+    let pages = doc.get_pages();
+    let (page_number, page_id) = pages
+        .into_iter()
+        .nth(page_index)
+        .ok_or_else(|| format!("{} ページ目がありません", page_index + 1))?;
+    extract_page_image(&doc, page_id).map_err(|e| format!("{} ページ目: {}", page_number, e).into())
+}
+
+impl PageImage {
+    /// data URL の画像をデコードする (ページ画像の比較などで画素が必要なとき)。
+    pub fn decode(&self) -> Result<DynamicImage, String> {
+        decode_data_url(&self.data_url)
+    }
+}
+
+/// data URL (または base64 だけ) の画像をデコードする。
+pub fn decode_data_url(data_url: &str) -> Result<DynamicImage, String> {
+    let encoded = data_url.split_once(',').map(|(_, data)| data).unwrap_or(data_url);
+    let bytes = BASE64.decode(encoded).map_err(|e| format!("画像データが不正です: {}", e))?;
+    ::image::load_from_memory(&bytes).map_err(|e| format!("画像を読み込めません: {}", e))
+}
+
+fn extract_page_image(doc: &Document, page_id: ObjectId) -> Result<PageImage, String> {
+    let page_size_pt = media_box(doc, page_id)?;
+
+    let (resources, resource_ids) = doc.get_page_resources(page_id).map_err(|e| e.to_string())?;
+    let mut xobjects = BTreeMap::new();
+    for resources in resources.into_iter().chain(resource_ids.iter().filter_map(|id| doc.get_dictionary(*id).ok())) {
+        for (name, id) in xobject_ids(doc, resources) {
+            // 近い階層 (ページ自身) の Resources を優先する
+            xobjects.entry(name).or_insert(id);
+        }
+    }
+
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    if let Some(kind) = find_vector_content(doc, &content, &xobjects, 0)? {
+        return Err(format!("画像の上に{}が描かれているページです。画像だけを使うと{}が欠けるため対応していません", kind, kind));
+    }
+
+    // ページに貼られた画像のうち最も大きいもの (同じ大きさなら SMask のない背景側) を使う
+    let mut candidates = Vec::new();
+    for id in xobjects.values() {
+        let Ok(stream) = doc.get_object(*id).and_then(Object::as_stream) else {
+            continue;
+        };
+        if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image".as_slice()) {
+            continue;
+        }
+        let width = stream.dict.get(b"Width").and_then(Object::as_i64).unwrap_or(0);
+        let height = stream.dict.get(b"Height").and_then(Object::as_i64).unwrap_or(0);
+        let has_mask = stream.dict.has(b"SMask");
+        candidates.push((width * height, !has_mask, stream));
+    }
+    let (_, _, stream) = candidates
+        .into_iter()
+        .max_by_key(|(area, opaque, _)| (*area, *opaque))
+        .ok_or("画像が貼られていないページです (文字や図形の PDF はアプリから書き出してください)")?;
+
+    decode_image_stream(doc, &stream.dict, &stream.content).map(|(data_url, width, height)| PageImage {
+        data_url,
+        width,
+        height,
+        page_size_pt,
+    })
+}
+
+/// Resources の XObject 辞書の (名前, オブジェクト番号)。
+fn xobject_ids(doc: &Document, resources: &Dictionary) -> Vec<(Vec<u8>, ObjectId)> {
+    let Ok(xobjects) = resources.get(b"XObject").and_then(|o| doc.dereference(o)).and_then(|(_, o)| o.as_dict()) else {
+        return Vec::new();
+    };
+    xobjects
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), value.as_reference().ok()?)))
+        .collect()
+}
+
+/// コンテンツストリームが画像以外に見える内容 (文字、線や塗り) を描いていれば、その種類を返す。
+/// 呼び出されたフォーム XObject の中も調べる。
+fn find_vector_content(
+    doc: &Document,
+    content: &[u8],
+    xobjects: &BTreeMap<Vec<u8>, ObjectId>,
+    depth: usize,
+) -> Result<Option<&'static str>, String> {
+    let content = Content::decode(content).map_err(|e| format!("ページの内容を読み取れません: {}", e))?;
+    // 文字の描画モードはグラフィックス状態の一部なので q / Q で保存・復元される
+    let mut text_mode = 0;
+    let mut saved_text_modes = Vec::new();
+
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "q" => saved_text_modes.push(text_mode),
+            "Q" => text_mode = saved_text_modes.pop().unwrap_or(0),
+            "Tr" => text_mode = operation.operands.first().and_then(|o| o.as_i64().ok()).unwrap_or(0),
+            "Tj" | "TJ" | "'" | "\"" if !INVISIBLE_TEXT_MODES.contains(&text_mode) => return Ok(Some("文字")),
+            // 線・塗り・シェーディング (クリップだけの "W n" は何も描かない)
+            "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "sh" => return Ok(Some("線や塗り")),
+            "Do" => {
+                let Some(id) = operation.operands.first().and_then(|o| o.as_name().ok()).and_then(|n| xobjects.get(n))
+                else {
+                    continue;
+                };
+                let Ok(stream) = doc.get_object(*id).and_then(Object::as_stream) else {
+                    continue;
+                };
+                if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form".as_slice()) {
+                    continue;
+                }
+                if depth >= MAX_FORM_DEPTH {
+                    return Err("フォームの入れ子が深すぎます".to_string());
+                }
+                // Resources のないフォームは呼び出し元の Resources を使う
+                let form_xobjects = match stream.dict.get(b"Resources").and_then(|o| doc.dereference(o)).and_then(|(_, o)| o.as_dict()) {
+                    Ok(resources) => xobject_ids(doc, resources).into_iter().collect(),
+                    Err(_) => xobjects.clone(),
+                };
+                let form_content = if stream.dict.has(b"Filter") {
+                    stream.decompressed_content().map_err(|e| format!("フォームの内容を展開できません: {}", e))?
+                } else {
+                    stream.content.clone()
+                };
+                if let Some(kind) = find_vector_content(doc, &form_content, &form_xobjects, depth + 1)? {
+                    return Ok(Some(kind));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// MediaBox (親の Pages から継承されることもある) の幅と高さ。
+fn media_box(doc: &Document, page_id: ObjectId) -> Result<(f32, f32), String> {
+    let mut node = doc.get_dictionary(page_id).map_err(|e| e.to_string())?;
+    loop {
+        if let Ok(object) = node.get(b"MediaBox") {
+            let values: Vec<f32> = doc
+                .dereference(object)
+                .and_then(|(_, o)| o.as_array())
+                .map_err(|e| e.to_string())?
+                .iter()
+                .filter_map(|v| v.as_float().ok())
+                .collect();
+            if let [x0, y0, x1, y1] = values.as_slice() {
+                return Ok(((x1 - x0).abs(), (y1 - y0).abs()));
+            }
+            return Err("MediaBox が不正です".to_string());
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).map_err(|_| "MediaBox がありません")?;
+        node = doc.get_dictionary(parent).map_err(|e| e.to_string())?;
+    }
+}
+
+fn name_list(doc: &Document, object: Option<&Object>) -> Vec<Vec<u8>> {
+    match object.map(|o| doc.dereference(o).map(|(_, o)| o)) {
+        Some(Ok(Object::Name(name))) => vec![name.clone()],
+        Some(Ok(Object::Array(items))) => items.iter().filter_map(|o| o.as_name().ok().map(<[u8]>::to_vec)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// 色空間の成分数 (DeviceGray = 1, DeviceRGB = 3, DeviceCMYK = 4)。
+fn color_components(doc: &Document, color_space: Option<&Object>) -> Result<u8, String> {
+    let resolved = match color_space {
+        Some(object) => doc.dereference(object).map(|(_, o)| o).map_err(|e| e.to_string())?,
+        None => return Ok(1),
+    };
+    let name = match resolved {
+        Object::Name(name) => name.as_slice(),
+        Object::Array(items) => items.first().and_then(|o| o.as_name().ok()).unwrap_or_default(),
+        _ => b"",
+    };
+    match name {
+        b"DeviceGray" | b"CalGray" | b"G" => Ok(1),
+        b"DeviceRGB" | b"CalRGB" | b"RGB" => Ok(3),
+        b"DeviceCMYK" | b"CMYK" => Ok(4),
+        b"ICCBased" => {
+            let stream = resolved
+                .as_array()
+                .ok()
+                .and_then(|items| items.get(1))
+                .and_then(|o| doc.dereference(o).ok())
+                .and_then(|(_, o)| o.as_stream().ok())
+                .ok_or("ICCBased の色空間が不正です")?;
+            match stream.dict.get(b"N").and_then(Object::as_i64) {
+                Ok(n @ (1 | 3 | 4)) => Ok(n as u8),
+                _ => Err("ICCBased の成分数が不正です".to_string()),
+            }
+        }
+        other => Err(format!("対応していない色空間です: {}", String::from_utf8_lossy(other))),
+    }
+}
+
+/// 画像 XObject をデコードし、(data URL, 幅, 高さ) を返す。JPEG はそのまま埋め込む。
+fn decode_image_stream(doc: &Document, dict: &Dictionary, content: &[u8]) -> Result<(String, u32, u32), String> {
+    let width = dict.get(b"Width").and_then(Object::as_i64).map_err(|_| "画像の幅がありません")? as u32;
+    let height = dict.get(b"Height").and_then(Object::as_i64).map_err(|_| "画像の高さがありません")? as u32;
+    let filters = name_list(doc, dict.get(b"Filter").ok());
+    let params = dict
+        .get(b"DecodeParms")
+        .ok()
+        .and_then(|o| match doc.dereference(o).map(|(_, o)| o) {
+            Ok(Object::Dictionary(d)) => Some(d.clone()),
+            Ok(Object::Array(items)) => items.last().and_then(|o| o.as_dict().ok()).cloned(),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let inverted = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|d| d.first())
+        .is_some_and(|v| v.as_float().ok() == Some(1.0));
+
+    let image = match filters.iter().map(Vec::as_slice).collect::<Vec<_>>().as_slice() {
+        // JPEG はデコードせずにそのまま渡す (背景の読み込み時に pdf.rs がデコードする)
+        [b"DCTDecode"] if !inverted => {
+            return Ok((format!("data:image/jpeg;base64,{}", BASE64.encode(content)), width, height));
+        }
+        [b"DCTDecode"] => invert(
+            ::image::load_from_memory_with_format(content, ImageFormat::Jpeg)
+                .map_err(|e| format!("JPEG を読み込めません: {}", e))?,
+        ),
+        [b"CCITTFaxDecode"] => decode_ccitt(content, width, height, &params, inverted)?,
+        [] | [b"FlateDecode"] => {
+            let raw = if filters.is_empty() {
+                content.to_vec()
+            } else {
+                let mut raw = Vec::new();
+                flate2::read::ZlibDecoder::new(content)
+                    .read_to_end(&mut raw)
+                    .map_err(|e| format!("画像の展開に失敗しました: {}", e))?;
+                raw
+            };
+            let components = color_components(doc, dict.get(b"ColorSpace").ok())?;
+            let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8) as u8;
+            let raw = remove_png_predictor(raw, &params, width, components, bits)?;
+            decode_raw(&raw, width, height, components, bits, inverted)?
+        }
+        other => {
+            let names: Vec<String> = other.iter().map(|n| String::from_utf8_lossy(n).into_owned()).collect();
+            return Err(format!("対応していない画像形式です: {}", names.join(", ")));
+        }
+    };
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("画像の変換に失敗しました: {}", e))?;
+    Ok((format!("data:image/png;base64,{}", BASE64.encode(&png)), width, height))
+}
+
+/// 展開済みの画素データを画像にする (8bit のグレー / RGB / CMYK と 1bit のグレー)。
+fn decode_raw(raw: &[u8], width: u32, height: u32, components: u8, bits: u8, inverted: bool) -> Result<DynamicImage, String> {
+    let (w, h) = (width as usize, height as usize);
+    let short = || "画像データが足りません".to_string();
+
+    let image = match (components, bits) {
+        (1, 1) => {
+            let row_bytes = w.div_ceil(8);
+            let raw = raw.get(..row_bytes * h).ok_or_else(short)?;
+            GrayImage::from_fn(width, height, |x, y| {
+                let byte = raw[y as usize * row_bytes + x as usize / 8];
+                let bit = (byte >> (7 - x % 8)) & 1;
+                ::image::Luma([if bit == 1 { 255 } else { 0 }])
+            })
+            .into()
+        }
+        (1, 8) => GrayImage::from_raw(width, height, raw.get(..w * h).ok_or_else(short)?.to_vec())
+            .ok_or_else(short)?
+            .into(),
+        (3, 8) => RgbImage::from_raw(width, height, raw.get(..w * h * 3).ok_or_else(short)?.to_vec())
+            .ok_or_else(short)?
+            .into(),
+        (4, 8) => {
+            let cmyk = raw.get(..w * h * 4).ok_or_else(short)?;
+            let rgb = cmyk
+                .chunks_exact(4)
+                .flat_map(|p| {
+                    let k = 255 - p[3] as u16;
+                    [0, 1, 2].map(|i| ((255 - p[i] as u16) * k / 255) as u8)
+                })
+                .collect();
+            RgbImage::from_raw(width, height, rgb).ok_or_else(short)?.into()
+        }
+        _ => return Err(format!("対応していない画素形式です ({} 成分, {} bit)", components, bits)),
+    };
+
+    Ok(if inverted { invert(image) } else { image })
+}
+
+fn invert(mut image: DynamicImage) -> DynamicImage {
+    image.invert();
+    image
+}
+
+/// CCITT G4 (K < 0) の 2 値画像をデコードする。
+fn decode_ccitt(content: &[u8], width: u32, height: u32, params: &Dictionary, inverted: bool) -> Result<DynamicImage, String> {
+    let k = params.get(b"K").and_then(Object::as_i64).unwrap_or(0);
+    if k >= 0 {
+        return Err("CCITT G3 の画像には対応していません".to_string());
+    }
+    let columns = params.get(b"Columns").and_then(Object::as_i64).unwrap_or(1728) as u16;
+    let black_is_1 = params.get(b"BlackIs1").and_then(Object::as_bool).unwrap_or(false);
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    fax::decoder::decode_g4(content.iter().copied(), columns, Some(height as u16), |transitions| {
+        pixels.extend(
+            fax::decoder::pels(transitions, columns)
+                .take(width as usize)
+                .map(|color| if color == fax::Color::Black { 0u8 } else { 255u8 }),
+        );
+    })
+    .ok_or("CCITT 画像のデコードに失敗しました")?;
+    pixels.resize(width as usize * height as usize, 255);
+
+    let image: DynamicImage = GrayImage::from_raw(width, height, pixels)
+        .ok_or("CCITT 画像のデコードに失敗しました")?
+        .into();
+    // BlackIs1 と Decode [1 0] はどちらも白黒を反転させる
+    Ok(if black_is_1 != inverted { invert(image) } else { image })
+}
+
+/// FlateDecode の PNG 予測子 (Predictor 10-15) を外す。
+fn remove_png_predictor(raw: Vec<u8>, params: &Dictionary, width: u32, components: u8, bits: u8) -> Result<Vec<u8>, String> {
+    let predictor = params.get(b"Predictor").and_then(Object::as_i64).unwrap_or(1);
+    if predictor < 10 {
+        return if predictor <= 1 {
+            Ok(raw)
+        } else {
+            Err("TIFF 予測子の画像には対応していません".to_string())
+        };
+    }
+    let colors = params.get(b"Colors").and_then(Object::as_i64).unwrap_or(components as i64) as usize;
+    let bits_per_component = params.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(bits as i64) as usize;
+    let columns = params.get(b"Columns").and_then(Object::as_i64).unwrap_or(width as i64) as usize;
+    let bpp = (colors * bits_per_component).div_ceil(8).max(1);
+    let row_len = (columns * colors * bits_per_component).div_ceil(8);
+
+    let mut out = Vec::with_capacity(raw.len());
+    let mut previous = vec![0u8; row_len];
+    for chunk in raw.chunks(row_len + 1) {
+        let (filter, data) = chunk.split_first().ok_or("画像データが不正です")?;
+        let mut row = data.to_vec();
+        row.resize(row_len, 0);
+        for i in 0..row_len {
+            let left = if i >= bpp { row[i - bpp] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+            row[i] = row[i].wrapping_add(match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("不正な PNG 予測子です: {}", filter)),
+            });
+        }
+        out.extend_from_slice(&row);
+        previous = row;
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector_content(content: &[u8]) -> Option<&'static str> {
+        find_vector_content(&Document::new(), content, &BTreeMap::new(), 0).unwrap()
+    }
+
+    #[test]
+    fn detects_content_beyond_the_image() {
+        const CASES: &[(&[u8], Option<&str>)] = &[
+            (b"q 400 0 0 600 0 0 cm /Im0 Do Q", None),
+            // クリップだけのパスは何も描かない
+            (b"q 0 0 400 600 re W n 400 0 0 600 0 0 cm /Im0 Do Q", None),
+            (b"/Im0 Do BT /F1 12 Tf 10 10 Td (Hi) Tj ET", Some("文字")),
+            (b"/Im0 Do BT /F1 12 Tf [(A) -20 (B)] TJ ET", Some("文字")),
+            // OCR の透明テキスト
+            (b"/Im0 Do BT 3 Tr /F1 12 Tf (ocr) Tj ET", None),
+            (b"/Im0 Do BT 7 Tr /F1 12 Tf (ocr) Tj ET", None),
+            // 描画モードは Q で元に戻る
+            (b"q 3 Tr Q BT /F1 12 Tf (x) Tj ET", Some("文字")),
+            (b"3 Tr q 0 Tr Q BT /F1 12 Tf (x) Tj ET", None),
+            (b"/Im0 Do 10 10 m 100 100 l S", Some("線や塗り")),
+            (b"/Im0 Do 0 0 10 10 re f*", Some("線や塗り")),
+            (b"/Im0 Do /Sh0 sh", Some("線や塗り")),
+        ];
+        for (content, expected) in CASES {
+            assert_eq!(vector_content(content), *expected, "{}", String::from_utf8_lossy(content));
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use std::io::Write;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use lopdf::{dictionary, Dictionary, Object, ObjectId, StringFormat};
+
+/// PDF を出力先へ逐次書き出す低レベルライタ。
+/// printpdf の `ImageXObject` は DCT 以外のフィルタを扱えず、SMask の出力にも不具合があるため、
+/// SaveRequestV2 の保存はすべてこちらを使う。
+/// 各ページの画像・コンテンツ・ページオブジェクトは `add_page` の時点で書き出してしまい、
+/// 手元にはオブジェクトのオフセットだけを残す。ページツリーと xref は `finish` で最後に書く。
+/// これによりメモリ使用量はページ数に比例せず、1 ページ分の画像で頭打ちになる。
+pub struct PdfWriter<W: Write> {
+    out: W,
+    /// これまでに書き出したバイト数 (= 次のオブジェクトのオフセット)
+    position: u64,
+    /// オブジェクト番号 - 1 をインデックスとするオフセット。未書き出しは None
+    offsets: Vec<Option<u64>>,
+    pages_id: ObjectId,
+    page_ids: Vec<ObjectId>,
+    subject: Option<String>,
+    /// カタログの `/ViewerPreferences`
+    viewer_preferences: Option<Dictionary>,
+    /// カタログの `/PageLayout` (`SinglePage` / `TwoPageRight` など)
+    page_layout: Option<String>,
+}
+
+/// ページ上の画像の配置 (pt, 左下原点)。
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// PDF に埋め込むエンコード済みの画像 XObject。
+pub struct PdfImage {
+    pub width: u32,
+    pub height: u32,
+    /// `DeviceRGB` / `DeviceGray`
+    pub color_space: &'static str,
+    pub bits_per_component: u8,
+    /// `DCTDecode` / `FlateDecode` / `CCITTFaxDecode`。None なら非圧縮の raw データ
+    pub filter: Option<&'static str>,
+    /// フィルタのパラメータ (FlateDecode の PNG 予測子など)
+    pub decode_parms: Option<Dictionary>,
+    pub data: Vec<u8>,
+    /// 透明度を表すグレースケール画像 (PDF 1.4 の soft mask)
+    pub smask: Option<Box<PdfImage>>,
+}
+
+impl PdfImage {
+    /// JPEG バイト列をそのまま DCTDecode で埋め込む RGB 画像。
+    pub fn jpeg_rgb(width: u32, height: u32, jpeg_bytes: Vec<u8>) -> Self {
+        PdfImage {
+            width,
+            height,
+            color_space: "DeviceRGB",
+            bits_per_component: 8,
+            filter: Some("DCTDecode"),
+            decode_parms: None,
+            data: jpeg_bytes,
+            smask: None,
+        }
+    }
+
+    /// グレースケール JPEG バイト列をそのまま DCTDecode で埋め込む。
+    pub fn jpeg_gray(width: u32, height: u32, jpeg_bytes: Vec<u8>) -> Self {
+        PdfImage {
+            color_space: "DeviceGray",
+            ..Self::jpeg_rgb(width, height, jpeg_bytes)
+        }
+    }
+
+    /// 8bit グレースケール画像を PNG 予測子付きの FlateDecode で可逆圧縮する。
+    pub fn flate_gray(gray: &::image::GrayImage, level: u32) -> std::io::Result<Self> {
+        let (width, height) = gray.dimensions();
+        Self::flate(width, height, "DeviceGray", 1, gray.as_raw(), level)
+    }
+
+    /// グレースケール画像を `threshold` で 2 値化し、1bit の CCITT G4 (CCITTFaxDecode) で圧縮する。
+    /// CCITT の行幅は u16 に収まる必要があるため、それを超える場合は None を返す。
+    pub fn ccitt_g4(gray: &::image::GrayImage, threshold: u8) -> Option<Self> {
+        let (width, height) = gray.dimensions();
+        let line_width = u16::try_from(width).ok()?;
+
+        let mut encoder = fax::encoder::Encoder::new(fax::VecWriter::new());
+        for row in gray.rows() {
+            let pels = row.map(|p| if p.0[0] < threshold { fax::Color::Black } else { fax::Color::White });
+            // VecWriter の Error は Infallible
+            let _ = encoder.encode_line(pels, line_width);
+        }
+        let data = match encoder.finish() {
+            Ok(writer) => writer.finish(),
+            Err(never) => match never {},
+        };
+
+        Some(PdfImage {
+            width,
+            height,
+            color_space: "DeviceGray",
+            bits_per_component: 1,
+            filter: Some("CCITTFaxDecode"),
+            decode_parms: Some(dictionary! {
+                "K" => -1,
+                "Columns" => width as i64,
+                "Rows" => height as i64,
+            }),
+            data,
+            smask: None,
+        })
+    }
+
+    /// 8bit RGB 画像を PNG 予測子付きの FlateDecode で可逆圧縮する。
+    pub fn flate_rgb(rgb: &::image::RgbImage, level: u32) -> std::io::Result<Self> {
+        let (width, height) = rgb.dimensions();
+        Self::flate(width, height, "DeviceRGB", 3, rgb.as_raw(), level)
+    }
+
+    /// RGBA 画像を可逆圧縮 (FlateDecode) の RGB 本体 + 8bit アルファの SMask に分解する。
+    pub fn flate_rgba(rgba: &::image::RgbaImage, level: u32) -> std::io::Result<Self> {
+        let (width, height) = rgba.dimensions();
+        let pixel_count = (width as usize) * (height as usize);
+        let mut rgb = Vec::with_capacity(pixel_count * 3);
+        let mut alpha = Vec::with_capacity(pixel_count);
+        for pixel in rgba.pixels() {
+            rgb.extend_from_slice(&pixel.0[..3]);
+            alpha.push(pixel.0[3]);
+        }
+
+        let smask = Self::flate(width, height, "DeviceGray", 1, &alpha, level)?;
+        let mut image = Self::flate(width, height, "DeviceRGB", 3, &rgb, level)?;
+        image.smask = Some(Box::new(smask));
+        Ok(image)
+    }
+
+    /// 8bit/component の画素列を PNG 予測子 (`/Predictor 15`) + FlateDecode で圧縮する。
+    fn flate(
+        width: u32,
+        height: u32,
+        color_space: &'static str,
+        colors: usize,
+        data: &[u8],
+        level: u32,
+    ) -> std::io::Result<Self> {
+        let row_bytes = width as usize * colors;
+        let compressed = flate_compress_png_predicted(data, row_bytes, colors, level)?;
+        Ok(PdfImage {
+            width,
+            height,
+            color_space,
+            bits_per_component: 8,
+            filter: Some("FlateDecode"),
+            decode_parms: Some(dictionary! {
+                "Predictor" => 15,
+                "Colors" => colors as i64,
+                "BitsPerComponent" => 8,
+                "Columns" => width as i64,
+            }),
+            data: compressed,
+            smask: None,
+        })
+    }
+
+    /// SMask を含めたストリーム本体のバイト数。
+    pub fn encoded_len(&self) -> u64 {
+        self.data.len() as u64 + self.smask.as_ref().map_or(0, |m| m.encoded_len())
+    }
+}
+
+/// PNG 予測子を行ごとに適用しながら zlib (FlateDecode) で圧縮する。
+/// 各行で None / Sub / Up / Average / Paeth のうち絶対値和が最小のものを選ぶ
+/// (libpng と同じ簡易ヒューリスティック)。フィルタ済みの行は 1 行分ずつしか保持しない。
+fn flate_compress_png_predicted(
+    data: &[u8],
+    row_bytes: usize,
+    bpp: usize,
+    level: u32,
+) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(
+        Vec::with_capacity(data.len() / 4),
+        Compression::new(level.min(9)),
+    );
+    if row_bytes == 0 {
+        return encoder.finish();
+    }
+
+    let zero_row = vec![0u8; row_bytes];
+    let mut candidates = vec![vec![0u8; row_bytes]; 5];
+    let mut prev: &[u8] = &zero_row;
+
+    for row in data.chunks_exact(row_bytes) {
+        for i in 0..row_bytes {
+            let x = row[i];
+            let a = if i >= bpp { row[i - bpp] } else { 0 };
+            let b = prev[i];
+            let c = if i >= bpp { prev[i - bpp] } else { 0 };
+            candidates[0][i] = x;
+            candidates[1][i] = x.wrapping_sub(a);
+            candidates[2][i] = x.wrapping_sub(b);
+            candidates[3][i] = x.wrapping_sub(((a as u16 + b as u16) / 2) as u8);
+            candidates[4][i] = x.wrapping_sub(paeth_predictor(a, b, c));
+        }
+
+        let best = (0..candidates.len())
+            .min_by_key(|&f| {
+                candidates[f]
+                    .iter()
+                    .map(|&v| (v as i8).unsigned_abs() as u64)
+                    .sum::<u64>()
+            })
+            .unwrap_or(0);
+        encoder.write_all(&[best as u8])?;
+        encoder.write_all(&candidates[best])?;
+        prev = row;
+    }
+
+    encoder.finish()
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+impl<W: Write> PdfWriter<W> {
+    /// ヘッダを書き出してライタを作る。ページツリーのオブジェクト番号だけ先に確保しておく。
+    pub fn new(out: W) -> std::io::Result<Self> {
+        let mut writer = PdfWriter {
+            out,
+            position: 0,
+            offsets: Vec::new(),
+            pages_id: (1, 0),
+            page_ids: Vec::new(),
+            subject: None,
+            viewer_preferences: None,
+            page_layout: None,
+        };
+        writer.pages_id = writer.new_object_id();
+        // 2 行目はバイナリを含むファイルであることを示すコメント (PDF 仕様の推奨)
+        writer.write_bytes(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(writer)
+    }
+
+    /// PDF `/Subject` に書き込む文字列を設定する。
+    pub fn set_subject(&mut self, subject: &str) {
+        self.subject = Some(subject.to_string());
+    }
+
+    /// ビューアでの開き方 (カタログの `/ViewerPreferences` と `/PageLayout`) を設定する。
+    pub fn set_viewer_settings(&mut self, viewer_preferences: Dictionary, page_layout: Option<&str>) {
+        self.viewer_preferences = Some(viewer_preferences).filter(|prefs| !prefs.is_empty());
+        self.page_layout = page_layout.map(str::to_string);
+    }
+
+    /// 画像ごとに配置先を指定してページを追加する。先頭から順に重ねる。
+    /// 画像データはここで出力先へ書き出され、呼び出し後には保持されない。
+    pub fn add_page_with_layout(
+        &mut self,
+        width_pt: f32,
+        height_pt: f32,
+        images: Vec<(PdfImage, ImageRect)>,
+    ) -> std::io::Result<()> {
+        let mut xobjects = Dictionary::new();
+        let mut content = String::new();
+        for (i, (image, rect)) in images.into_iter().enumerate() {
+            let name = format!("Im{}", i);
+            let image_id = self.write_image(image)?;
+            xobjects.set(name.as_bytes(), image_id);
+            content.push_str(&format!(
+                "q {:.4} 0 0 {:.4} {:.4} {:.4} cm /{} Do Q\n",
+                rect.width, rect.height, rect.x, rect.y, name
+            ));
+        }
+
+        let content_id = self.write_stream(Dictionary::new(), content.as_bytes())?;
+        let page_id = self.new_object_id();
+        self.write_object(
+            page_id,
+            &Object::Dictionary(dictionary! {
+                "Type" => "Page",
+                "Parent" => self.pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width_pt.into(), height_pt.into()],
+                "Resources" => dictionary! { "XObject" => xobjects },
+                "Contents" => content_id,
+            }),
+        )?;
+        self.page_ids.push(page_id);
+        Ok(())
+    }
+
+    /// ページツリー・カタログ・文書情報と xref を書き出して出力先を返す。
+    pub fn finish(mut self) -> Result<W, Box<dyn std::error::Error>> {
+        let kids: Vec<Object> = self.page_ids.iter().map(|&id| id.into()).collect();
+        let count = kids.len() as i64;
+        self.write_object(
+            self.pages_id,
+            &Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+            }),
+        )?;
+
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => self.pages_id,
+        };
+        if let Some(viewer_preferences) = self.viewer_preferences.take() {
+            catalog.set("ViewerPreferences", viewer_preferences);
+        }
+        if let Some(page_layout) = self.page_layout.take() {
+            catalog.set("PageLayout", Object::Name(page_layout.into_bytes()));
+        }
+        let catalog_id = self.new_object_id();
+        self.write_object(catalog_id, &Object::Dictionary(catalog))?;
+
+        let mut info = dictionary! {
+            "Title" => Object::string_literal("MojiQ Pro Document"),
+            "Producer" => Object::string_literal("MojiQ Pro"),
+        };
+        if let Some(subject) = self.subject.take() {
+            info.set("Subject", Object::string_literal(subject));
+        }
+        let info_id = self.new_object_id();
+        self.write_object(info_id, &Object::Dictionary(info))?;
+
+        // 相互参照表: 各エントリはちょうど 20 バイト
+        let xref_start = self.position;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f\r\n", self.offsets.len() + 1);
+        for (i, offset) in self.offsets.iter().enumerate() {
+            let offset = offset.ok_or_else(|| format!("PDF object {} was never written", i + 1))?;
+            xref.push_str(&format!("{:010} 00000 n\r\n", offset));
+        }
+        self.write_bytes(xref.as_bytes())?;
+
+        let mut trailer = Vec::new();
+        trailer.extend_from_slice(b"trailer\n");
+        write_object(
+            &mut trailer,
+            &Object::Dictionary(dictionary! {
+                "Size" => (self.offsets.len() + 1) as i64,
+                "Root" => catalog_id,
+                "Info" => info_id,
+            }),
+        )?;
+        trailer.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", xref_start).as_bytes());
+        self.write_bytes(&trailer)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn new_object_id(&mut self) -> ObjectId {
+        self.offsets.push(None);
+        (self.offsets.len() as u32, 0)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// 間接オブジェクト `id 0 obj ... endobj` を書き出し、オフセットを記録する。
+    fn write_object(&mut self, id: ObjectId, object: &Object) -> std::io::Result<()> {
+        self.offsets[id.0 as usize - 1] = Some(self.position);
+        let mut buf = format!("{} {} obj\n", id.0, id.1).into_bytes();
+        write_object(&mut buf, object)?;
+        buf.extend_from_slice(b"\nendobj\n");
+        self.write_bytes(&buf)
+    }
+
+    /// ストリームを書き出す。本体は (画像データで大きいため) バッファにコピーせず直接書く。
+    fn write_stream(&mut self, mut dict: Dictionary, data: &[u8]) -> std::io::Result<ObjectId> {
+        let id = self.new_object_id();
+        self.offsets[id.0 as usize - 1] = Some(self.position);
+        dict.set("Length", data.len() as i64);
+
+        let mut head = format!("{} {} obj\n", id.0, id.1).into_bytes();
+        write_object(&mut head, &Object::Dictionary(dict))?;
+        head.extend_from_slice(b"\nstream\n");
+        self.write_bytes(&head)?;
+        self.write_bytes(data)?;
+        self.write_bytes(b"\nendstream\nendobj\n")?;
+        Ok(id)
+    }
+
+    /// 画像 XObject (と SMask) を書き出し、その ObjectId を返す。
+    fn write_image(&mut self, image: PdfImage) -> std::io::Result<ObjectId> {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => image.width as i64,
+            "Height" => image.height as i64,
+            "ColorSpace" => image.color_space,
+            "BitsPerComponent" => image.bits_per_component as i64,
+            "Interpolate" => true,
+        };
+        if let Some(filter) = image.filter {
+            dict.set("Filter", filter);
+        }
+        if let Some(decode_parms) = image.decode_parms {
+            dict.set("DecodeParms", decode_parms);
+        }
+        if let Some(smask) = image.smask {
+            let smask_id = self.write_image(*smask)?;
+            dict.set("SMask", smask_id);
+        }
+        self.write_stream(dict, &image.data)
+    }
+}
+
+/// 直接オブジェクトを PDF の構文で書き出す (ストリームは間接オブジェクトとしてのみ書くので扱わない)。
+fn write_object(out: &mut Vec<u8>, object: &Object) -> std::io::Result<()> {
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => out.extend_from_slice(if *value { b"true" } else { b"false" }),
+        Object::Integer(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => write_real(out, *value),
+        Object::Name(name) => write_name(out, name),
+        Object::String(bytes, StringFormat::Literal) => {
+            out.push(b'(');
+            for &b in bytes {
+                match b {
+                    b'(' | b')' | b'\\' => out.extend_from_slice(&[b'\\', b]),
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    _ => out.push(b),
+                }
+            }
+            out.push(b')');
+        }
+        Object::String(bytes, StringFormat::Hexadecimal) => {
+            out.push(b'<');
+            for b in bytes {
+                out.extend_from_slice(format!("{:02X}", b).as_bytes());
+            }
+            out.push(b'>');
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b' ');
+                }
+                write_object(out, item)?;
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => {
+            out.extend_from_slice(b"<<");
+            for (key, value) in dict.iter() {
+                write_name(out, key);
+                out.push(b' ');
+                write_object(out, value)?;
+            }
+            out.extend_from_slice(b">>");
+        }
+        Object::Reference((id, generation)) => {
+            out.extend_from_slice(format!("{} {} R", id, generation).as_bytes());
+        }
+        Object::Stream(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "streams must be written as indirect objects",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 実数は指数表記を使えないので固定小数で書き、末尾の 0 を落とす。
+fn write_real(out: &mut Vec<u8>, value: f32) {
+    let value = if value.is_finite() { value } else { 0.0 };
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    out.extend_from_slice(if text == "-0" { "0" } else { text }.as_bytes());
+}
+
+/// 名前オブジェクト。区切り文字や印字不能な文字は `#xx` でエスケープする。
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &b in name {
+        let is_delimiter = b"()<>[]{}/%#".contains(&b);
+        if (0x21..=0x7E).contains(&b) && !is_delimiter {
+            out.push(b);
+        } else {
+            out.extend_from_slice(format!("#{:02X}", b).as_bytes());
+        }
+    }
+}
@@ -0,0 +1,396 @@
+// CUPS (Linux / macOS) での印刷: `lpstat` でプリンターを列挙し、`lp` でジョブを投入する。
+// コマンドの出力はロケールで変わるため、常に LC_ALL=C で実行して英語の出力を解析する。
+// コマンドのパスは環境変数 MOJIQ_LP / MOJIQ_LPSTAT で差し替えられる (CUPS-PDF のキューでの確認や試験用)。
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrinterInfo {
+    pub name: String,
+    pub is_default: bool,
+    /// ジョブを受け付ける状態か (lpstat で disabled でない)
+    pub is_enabled: bool,
+}
+
+/// 印刷オプション。すべて省略可能で、省略時はプリンター側の既定値になる。
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PrintOptions {
+    /// 出力先プリンター名。None ならシステムの既定プリンター
+    #[serde(default)]
+    pub printer: Option<String>,
+    #[serde(default)]
+    pub copies: Option<u32>,
+    /// "one-sided" / "long-edge" (長辺綴じ) / "short-edge" (短辺綴じ)
+    #[serde(default)]
+    pub duplex: Option<String>,
+    /// プリンターに渡すページ範囲 ("1-3,5" 形式、1 始まり)。面付け後の用紙単位で数える。
+    /// 原稿のページを選ぶときは `PageSelection` を使う。
+    #[serde(default)]
+    pub page_ranges: Option<String>,
+}
+
+/// 印刷する原稿ページの選び方。
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PageSelection {
+    /// "all" / "ranges" (page_ranges で指定) / "spread" (spread_index の見開きのみ) /
+    /// "commented" (描画・コメントのあるページのみ)。None なら all。
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// "1-3,5" 形式 (1 始まり)
+    #[serde(default)]
+    pub page_ranges: Option<String>,
+    /// 見開きのインデックス (spreadViewStore と同じく 0 = 表紙単独、以降 [2, 3], [4, 5], ...)
+    #[serde(default)]
+    pub spread_index: Option<usize>,
+}
+
+impl PageSelection {
+    /// 印刷するページ (0 始まり、昇順・重複なし)。`has_comments` はページに描画があるかを返す。
+    pub fn select<F>(&self, page_count: usize, has_comments: F) -> Result<Vec<usize>, String>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let pages: Vec<usize> = match self.mode.as_deref() {
+            None | Some("all") => (0..page_count).collect(),
+            Some("ranges") => {
+                let ranges = self.page_ranges.as_deref().unwrap_or("");
+                parse_page_ranges(ranges, page_count)?
+            }
+            Some("spread") => {
+                let spread = self.spread_index.ok_or("spread_index is required")?;
+                // 1 始まりのページ番号で、見開き 0 は 1 ページ目、見開き k は 2k と 2k+1 ページ目
+                let numbers = if spread == 0 { vec![1] } else { vec![2 * spread, 2 * spread + 1] };
+                numbers
+                    .into_iter()
+                    .filter(|&n| n <= page_count)
+                    .map(|n| n - 1)
+                    .collect()
+            }
+            Some("commented") => (0..page_count).filter(|&i| has_comments(i)).collect(),
+            Some(other) => return Err(format!("Invalid page selection: {}", other)),
+        };
+
+        if pages.is_empty() {
+            return Err("印刷するページがありません".to_string());
+        }
+        Ok(pages)
+    }
+}
+
+/// "1-3,5" 形式のページ範囲を 0 始まりのページ番号に展開する (昇順・重複なし)。
+fn parse_page_ranges(ranges: &str, page_count: usize) -> Result<Vec<usize>, String> {
+    let invalid = || format!("Invalid page ranges: {}", ranges);
+    let mut pages = std::collections::BTreeSet::new();
+
+    for part in ranges.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => (part, part),
+        };
+        let first: usize = first.parse().map_err(|_| invalid())?;
+        let last: usize = last.parse().map_err(|_| invalid())?;
+        if first == 0 || first > last {
+            return Err(invalid());
+        }
+        // 範囲外は文書の最終ページで打ち切る
+        pages.extend((first..=last.min(page_count)).map(|n| n - 1));
+    }
+
+    Ok(pages.into_iter().collect())
+}
+
+impl PrintOptions {
+    /// CUPS の `sides` オプション値。
+    fn cups_sides(&self) -> Result<Option<&'static str>, String> {
+        match self.duplex.as_deref() {
+            None => Ok(None),
+            Some("one-sided") => Ok(Some("one-sided")),
+            Some("long-edge") => Ok(Some("two-sided-long-edge")),
+            Some("short-edge") => Ok(Some("two-sided-short-edge")),
+            Some(other) => Err(format!("Invalid duplex mode: {}", other)),
+        }
+    }
+
+    /// ページ範囲の書式を確認する (数字・カンマ・ハイフンのみ)。
+    fn validated_page_ranges(&self) -> Result<Option<&str>, String> {
+        match self.page_ranges.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(ranges) => {
+                let valid = ranges.split(',').all(|part| {
+                    let mut bounds = part.trim().splitn(2, '-');
+                    let is_page = |s: Option<&str>| s.is_some_and(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()));
+                    let first = bounds.next();
+                    match bounds.next() {
+                        Some(last) => is_page(first) && is_page(Some(last)),
+                        None => is_page(first),
+                    }
+                });
+                if valid {
+                    Ok(Some(ranges))
+                } else {
+                    Err(format!("Invalid page ranges: {}", ranges))
+                }
+            }
+        }
+    }
+}
+
+/// CUPS のコマンドのパス。環境変数 `env` があればそれを使う。
+#[cfg(not(target_os = "windows"))]
+fn cups_command(name: &str, env: &str) -> String {
+    std::env::var(env).ok().filter(|path| !path.is_empty()).unwrap_or_else(|| name.to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn lpstat(args: &[&str]) -> Result<String, String> {
+    let output = std::process::Command::new(cups_command("lpstat", "MOJIQ_LPSTAT"))
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| format!("lpstat の実行に失敗しました: {}", e))?;
+    // プリンターが 1 台もないと lpstat は失敗終了するが、その場合は空の一覧として扱う
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// CUPS に登録されているプリンターの一覧。
+#[cfg(not(target_os = "windows"))]
+pub fn list_printers() -> Result<Vec<PrinterInfo>, String> {
+    let default = parse_default_printer(&lpstat(&["-d"])?);
+    Ok(parse_printers(&lpstat(&["-p"])?, default.as_deref()))
+}
+
+/// `lpstat -d` の出力 "system default destination: NAME" から既定のプリンター名を取り出す。
+/// 既定がなければ "no system default destination" が出力される。
+#[cfg(not(target_os = "windows"))]
+fn parse_default_printer(stdout: &str) -> Option<String> {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("system default destination:"))
+        .map(|name| name.trim().to_string())
+}
+
+/// `lpstat -p` の出力 "printer NAME is idle.  enabled since ..." / "printer NAME disabled since ..."
+/// からプリンターの一覧を作る。
+#[cfg(not(target_os = "windows"))]
+fn parse_printers(stdout: &str, default: Option<&str>) -> Vec<PrinterInfo> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("printer ")?;
+            let name = rest.split_whitespace().next()?.to_string();
+            Some(PrinterInfo {
+                is_default: default == Some(name.as_str()),
+                is_enabled: !rest.contains(" disabled"),
+                name,
+            })
+        })
+        .collect()
+}
+
+/// `lp` に渡す引数。
+#[cfg(not(target_os = "windows"))]
+fn lp_args(file_path: &str, title: &str, options: &PrintOptions) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    if let Some(printer) = options.printer.as_deref().filter(|p| !p.is_empty()) {
+        args.push("-d".to_string());
+        args.push(printer.to_string());
+    }
+    if let Some(copies) = options.copies {
+        if copies == 0 {
+            return Err("Copies must be at least 1".to_string());
+        }
+        args.push("-n".to_string());
+        args.push(copies.to_string());
+    }
+    if let Some(sides) = options.cups_sides()? {
+        args.push("-o".to_string());
+        args.push(format!("sides={}", sides));
+    }
+    if let Some(ranges) = options.validated_page_ranges()? {
+        args.push("-o".to_string());
+        args.push(format!("page-ranges={}", ranges.replace(' ', "")));
+    }
+    args.push("-t".to_string());
+    args.push(title.to_string());
+    // ファイル名が "-" で始まってもオプションと解釈されないようにする
+    args.push("--".to_string());
+    args.push(file_path.to_string());
+    Ok(args)
+}
+
+/// `lp` の出力 "request id is NAME-123 (1 file(s))" からジョブ ID を取り出す。
+#[cfg(not(target_os = "windows"))]
+fn parse_lp_job_id(stdout: &str) -> Option<String> {
+    stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("request id is "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+}
+
+/// PDF を CUPS の印刷キューに投入し、ジョブ ID を返す。
+#[cfg(not(target_os = "windows"))]
+pub fn submit_print_job(file_path: &str, title: &str, options: &PrintOptions) -> Result<String, String> {
+    let output = std::process::Command::new(cups_command("lp", "MOJIQ_LP"))
+        .args(lp_args(file_path, title, options)?)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| format!("lp の実行に失敗しました: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "印刷ジョブの投入に失敗しました: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_lp_job_id(&stdout).ok_or_else(|| format!("lp の出力からジョブ ID を読み取れません: {}", stdout.trim()))
+}
+
+/// SumatraPDF の `-print-settings` 値 (Windows 用)。対応しない項目は無視される。
+#[cfg(target_os = "windows")]
+pub fn sumatra_print_settings(options: &PrintOptions) -> Result<Option<String>, String> {
+    let mut settings = Vec::new();
+    if let Some(ranges) = options.validated_page_ranges()? {
+        settings.push(ranges.replace(' ', ""));
+    }
+    if let Some(copies) = options.copies.filter(|&c| c > 1) {
+        settings.push(format!("{}x", copies));
+    }
+    match options.cups_sides()? {
+        Some("two-sided-long-edge") => settings.push("duplexlong".to_string()),
+        Some("two-sided-short-edge") => settings.push("duplexshort".to_string()),
+        Some(_) => settings.push("simplex".to_string()),
+        None => {}
+    }
+    Ok(Some(settings.join(",")).filter(|s| !s.is_empty()))
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    fn options(printer: Option<&str>, copies: Option<u32>, duplex: Option<&str>, ranges: Option<&str>) -> PrintOptions {
+        PrintOptions {
+            printer: printer.map(str::to_string),
+            copies,
+            duplex: duplex.map(str::to_string),
+            page_ranges: ranges.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn lp_args_defaults() {
+        let args = lp_args("/tmp/a.pdf", "MojiQ Pro", &PrintOptions::default()).unwrap();
+        assert_eq!(args, ["-t", "MojiQ Pro", "--", "/tmp/a.pdf"]);
+    }
+
+    #[test]
+    fn lp_args_all_options() {
+        let options = options(Some("CUPS-PDF"), Some(3), Some("long-edge"), Some("1-3, 5"));
+        let args = lp_args("-a.pdf", "MojiQ Pro", &options).unwrap();
+        assert_eq!(
+            args,
+            [
+                "-d", "CUPS-PDF", "-n", "3", "-o", "sides=two-sided-long-edge", "-o", "page-ranges=1-3,5", "-t",
+                "MojiQ Pro", "--", "-a.pdf",
+            ]
+        );
+    }
+
+    #[test]
+    fn lp_args_duplex() {
+        let cases = [
+            ("one-sided", "sides=one-sided"),
+            ("long-edge", "sides=two-sided-long-edge"),
+            ("short-edge", "sides=two-sided-short-edge"),
+        ];
+        for (duplex, expected) in cases {
+            let args = lp_args("a.pdf", "t", &options(None, None, Some(duplex), None)).unwrap();
+            assert_eq!(args[..2], ["-o", expected], "{}", duplex);
+        }
+        assert!(lp_args("a.pdf", "t", &options(None, None, Some("both"), None)).is_err());
+    }
+
+    #[test]
+    fn lp_args_copies_and_printer() {
+        assert_eq!(lp_args("a.pdf", "t", &options(None, Some(1), None, None)).unwrap()[..2], ["-n", "1"]);
+        assert!(lp_args("a.pdf", "t", &options(None, Some(0), None, None)).is_err());
+        // 空のプリンター名は既定のプリンター
+        assert_eq!(lp_args("a.pdf", "t", &options(Some(""), None, None, None)).unwrap()[0], "-t");
+    }
+
+    #[test]
+    fn lp_args_page_ranges() {
+        for (ranges, expected) in [("5", "page-ranges=5"), (" 1-2, 4 ", "page-ranges=1-2,4")] {
+            let args = lp_args("a.pdf", "t", &options(None, None, None, Some(ranges))).unwrap();
+            assert_eq!(args[..2], ["-o", expected], "{:?}", ranges);
+        }
+        assert_eq!(lp_args("a.pdf", "t", &options(None, None, None, Some("  "))).unwrap()[0], "-t");
+        for ranges in ["1-", "-3", "a", "1-2-3", "1,,2", "1;rm"] {
+            assert!(lp_args("a.pdf", "t", &options(None, None, None, Some(ranges))).is_err(), "{:?}", ranges);
+        }
+    }
+
+    #[test]
+    fn parses_lp_job_id() {
+        assert_eq!(parse_lp_job_id("request id is PDF-42 (1 file(s))\n").as_deref(), Some("PDF-42"));
+        assert_eq!(parse_lp_job_id("  request id is Office_Printer-7 (1 file(s))").as_deref(), Some("Office_Printer-7"));
+        assert_eq!(parse_lp_job_id(""), None);
+        assert_eq!(parse_lp_job_id("lp: Error - no default destination available."), None);
+    }
+
+    #[test]
+    fn parses_lpstat_output() {
+        assert_eq!(parse_default_printer("system default destination: PDF\n").as_deref(), Some("PDF"));
+        assert_eq!(parse_default_printer("no system default destination\n"), None);
+
+        let stdout = "printer PDF is idle.  enabled since Sat 17 Oct 2026 10:00:00 AM JST\n\
+                      printer Office disabled since Sat 17 Oct 2026 09:00:00 AM JST -\n\
+                      \treason unknown\n";
+        let printers = parse_printers(stdout, Some("PDF"));
+        let summary: Vec<_> = printers.iter().map(|p| (p.name.as_str(), p.is_default, p.is_enabled)).collect();
+        assert_eq!(summary, [("PDF", true, true), ("Office", false, false)]);
+        assert!(parse_printers("", None).is_empty());
+    }
+
+    /// CUPS-PDF などのキューに実際にジョブを投入する。
+    /// `MOJIQ_TEST_PRINTER=PDF cargo test -- --ignored submit_to_cups_queue` で実行する。
+    #[test]
+    #[ignore]
+    fn submit_to_cups_queue() {
+        let printer = std::env::var("MOJIQ_TEST_PRINTER").unwrap_or_else(|_| "PDF".to_string());
+        assert!(
+            list_printers().unwrap().iter().any(|p| p.name == printer),
+            "printer {} is not registered",
+            printer
+        );
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        doc.objects.insert(
+            pages_id,
+            lopdf::Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = std::env::temp_dir().join(format!("mojiq-print-test-{}.pdf", std::process::id()));
+        doc.save(&path).unwrap();
+
+        let options = options(Some(&printer), Some(1), None, None);
+        let result = submit_print_job(&path.to_string_lossy(), "MojiQ print test", &options);
+        std::fs::remove_file(&path).ok();
+        assert!(result.unwrap().starts_with(&format!("{}-", printer)));
+    }
+}
@@ -0,0 +1,511 @@
+// 1 ファイルのプロジェクト (.mojiq): 元原稿 (コピーか参照)、描画データ・コメントデータ、校正チェックの状態、
+// メモ、設定を ZIP にまとめる。コピーして持ち回っても `_描画.json` などが元原稿と離れない。
+//
+//   manifest.json   ProjectManifest (形式・バージョン・元原稿の一覧)
+//   sources/...     埋め込んだ元原稿 (無圧縮)
+//   drawing.json    描画データ (`_描画.json` と同じ MojiQExportData)
+//   comment.json    コメントデータ (`_コメント.json` と同じ)
+//   checked.json    校正チェックの確認済み状態 (CheckedState)
+//   memo.txt        メモ
+//   settings.json   プロジェクトの設定 (書き出し設定など。中身はフロントエンドが決める)
+
+use crate::drawing_data::MojiQExportData;
+use crate::zip_archive::{ZipArchive, ZipWriter};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+
+pub const PROJECT_FORMAT: &str = "mojiq-project";
+/// このバージョンより新しいプロジェクトは開かない
+pub const PROJECT_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SOURCES_DIR: &str = "sources/";
+const DRAWING_ENTRY: &str = "drawing.json";
+const COMMENT_ENTRY: &str = "comment.json";
+const CHECKED_ENTRY: &str = "checked.json";
+const MEMO_ENTRY: &str = "memo.txt";
+const SETTINGS_ENTRY: &str = "settings.json";
+
+/// 埋め込んだ元原稿を展開するフォルダ (アプリのキャッシュフォルダの中)。
+const EXTRACT_DIR_NAME: &str = "projects";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectManifest {
+    /// 常に "mojiq-project"
+    pub format: String,
+    pub version: u32,
+    /// 保存したアプリのバージョン
+    pub app_version: String,
+    /// 作成・更新日時 (ISO 8601、UTC)
+    pub created_at: String,
+    pub modified_at: String,
+    /// ページ順の元原稿 (PDF なら 1 つ、画像ならページごと)
+    pub sources: Vec<ProjectSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSource {
+    /// ファイル名
+    pub name: String,
+    /// 埋め込んだ場合の ZIP 内のパス。None なら参照
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// 追加したときの絶対パス
+    pub original_path: String,
+    /// プロジェクトファイルのフォルダからの相対パス (参照のみ)。フォルダごと移動しても見つけられるようにする
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_path: Option<String>,
+    pub size: u64,
+    /// 更新日時 (UNIX エポックからのミリ秒)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+/// プロジェクトに入れる元原稿。`embed` なら ZIP にコピーし、そうでなければパスだけを記録する。
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectSourceInput {
+    pub path: String,
+    #[serde(default)]
+    pub embed: bool,
+}
+
+/// 作成・保存するプロジェクトの中身。保存では None の項目はプロジェクトにある内容を残す。
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectContent {
+    /// 元原稿。保存で None なら今の元原稿 (埋め込みも) をそのまま残す
+    #[serde(default)]
+    pub sources: Option<Vec<ProjectSourceInput>>,
+    /// 描画データ (MojiQExportData の JSON)
+    #[serde(default)]
+    pub drawings: Option<String>,
+    /// コメントデータ (MojiQExportData の JSON)
+    #[serde(default)]
+    pub comments: Option<String>,
+    #[serde(default)]
+    pub checked_state: Option<serde_json::Value>,
+    #[serde(default)]
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+}
+
+/// 開いたプロジェクト。
+#[derive(Debug, Serialize)]
+pub struct OpenedProject {
+    pub manifest: ProjectManifest,
+    /// 読み込む元原稿のパス (埋め込みは展開先、参照は見つかった場所)。manifest.sources の順
+    pub source_paths: Vec<String>,
+    /// 見つからなかった参照の元原稿 (manifest.sources の original_path)
+    pub missing_sources: Vec<String>,
+    pub drawings: Option<String>,
+    pub comments: Option<String>,
+    pub checked_state: Option<serde_json::Value>,
+    pub memo: Option<String>,
+    pub settings: Option<serde_json::Value>,
+}
+
+/// 検証結果。`errors` があれば開けない (壊れている)、`warnings` は開けるが欠けているもの。
+#[derive(Debug, Serialize)]
+pub struct ProjectValidation {
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub manifest: Option<ProjectManifest>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// UNIX ミリ秒を "YYYY-MM-DDTHH:MM:SSZ" にする (描画データの exportedAt と同じ ISO 8601)。
+fn iso_timestamp(millis: u64) -> String {
+    let (year, month, day, hour, minute, second) = crate::backup::utc_datetime(millis);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
+
+fn project_dir(project_path: &Path) -> &Path {
+    project_path.parent().unwrap_or(Path::new(""))
+}
+
+/// `base` から `path` への相対パス ("/" 区切り)。ドライブが違うなど相対にできなければ None。
+fn relative_path(base: &Path, path: &Path) -> Option<String> {
+    let base: Vec<Component> = base.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = base.iter().zip(&path).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), base.len() - common)
+        .chain(path[common..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()))
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// ZIP 内のパスが sources/ の下を指しているか (展開先のフォルダの外に書かないため)。
+fn is_safe_source_entry(entry: &str) -> bool {
+    entry.strip_prefix(SOURCES_DIR).is_some_and(|rest| {
+        !rest.is_empty()
+            && Path::new(rest).components().all(|c| matches!(c, Component::Normal(_)))
+            && !rest.contains('\\')
+    })
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| format!("プロジェクトを開けません: {}: {}", path.display(), e))?;
+    ZipArchive::new(BufReader::new(file)).map_err(|e| format!("プロジェクトを読み込めません: {}: {}", path.display(), e))
+}
+
+fn read_text(archive: &mut ZipArchive<BufReader<File>>, name: &str) -> Result<Option<String>, String> {
+    let Some(bytes) = archive.read(name).map_err(|e| format!("{} を読み込めません: {}", name, e))? else {
+        return Ok(None);
+    };
+    String::from_utf8(bytes).map(Some).map_err(|_| format!("{} が UTF-8 ではありません", name))
+}
+
+fn read_json(archive: &mut ZipArchive<BufReader<File>>, name: &str) -> Result<Option<serde_json::Value>, String> {
+    read_text(archive, name)?
+        .map(|text| serde_json::from_str(&text).map_err(|e| format!("{} を読み込めません: {}", name, e)))
+        .transpose()
+}
+
+fn read_manifest(archive: &mut ZipArchive<BufReader<File>>) -> Result<ProjectManifest, String> {
+    let text = read_text(archive, MANIFEST_ENTRY)?.ok_or("MojiQ のプロジェクトではありません (manifest.json がありません)")?;
+    let manifest: ProjectManifest =
+        serde_json::from_str(&text).map_err(|e| format!("manifest.json を読み込めません: {}", e))?;
+    if manifest.format != PROJECT_FORMAT {
+        return Err(format!("MojiQ のプロジェクトではありません (形式: {})", manifest.format));
+    }
+    if manifest.version > PROJECT_VERSION {
+        return Err(format!(
+            "新しいバージョンの MojiQ で作られたプロジェクトです (形式のバージョン {})。アプリを更新してください",
+            manifest.version
+        ));
+    }
+    Ok(manifest)
+}
+
+/// 参照の元原稿を探す。プロジェクトからの相対パスを優先し (フォルダごと移動した場合)、なければ元の場所。
+fn resolve_linked_source(project_path: &Path, source: &ProjectSource) -> Option<PathBuf> {
+    source
+        .relative_path
+        .as_ref()
+        .map(|relative| project_dir(project_path).join(relative))
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(&source.original_path)))
+        .find(|path| path.is_file())
+}
+
+/// 描画データ・コメントデータが MojiQExportData として読めるか確かめる。
+fn check_drawing_json(name: &str, json: &str) -> Result<(), String> {
+    MojiQExportData::from_json(json).map(|_| ()).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// 埋め込む元原稿の (元のパス, ZIP 内の名前)。
+type EmbeddedSource = (PathBuf, String);
+
+/// 元原稿の一覧を作る。埋め込むものは ZIP 内の名前を決めて返す。
+fn collect_sources(
+    project_path: &Path,
+    inputs: &[ProjectSourceInput],
+) -> Result<(Vec<ProjectSource>, Vec<EmbeddedSource>), String> {
+    let mut sources = Vec::with_capacity(inputs.len());
+    let mut embedded = Vec::new();
+    for input in inputs {
+        let path = PathBuf::from(&input.path);
+        let metadata =
+            std::fs::metadata(&path).map_err(|e| format!("元原稿が見つかりません: {}: {}", path.display(), e))?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("元原稿のパスが不正です: {}", path.display()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+        let absolute = std::path::absolute(&path).unwrap_or_else(|_| path.clone());
+
+        let entry = input.embed.then(|| {
+            // 別のフォルダの同じ名前の画像がぶつからないよう、2 つ目以降は番号のフォルダに入れる
+            let mut entry = format!("{}{}", SOURCES_DIR, name);
+            let mut index = 1;
+            while embedded.iter().any(|(_, e)| *e == entry) {
+                index += 1;
+                entry = format!("{}{}/{}", SOURCES_DIR, index, name);
+            }
+            embedded.push((path.clone(), entry.clone()));
+            entry
+        });
+        let relative_path = match entry {
+            Some(_) => None,
+            None => std::path::absolute(project_path)
+                .ok()
+                .and_then(|project| relative_path(project_dir(&project), &absolute)),
+        };
+
+        sources.push(ProjectSource {
+            name,
+            entry,
+            original_path: absolute.to_string_lossy().into_owned(),
+            relative_path,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    Ok((sources, embedded))
+}
+
+/// プロジェクトを書き出す。`previous` は上書き保存のときの元のプロジェクト (残す内容の読み出し元)。
+fn write_project(
+    path: &Path,
+    content: ProjectContent,
+    previous: Option<(ProjectManifest, ZipArchive<BufReader<File>>)>,
+) -> Result<ProjectManifest, String> {
+    for (name, json) in [(DRAWING_ENTRY, &content.drawings), (COMMENT_ENTRY, &content.comments)] {
+        if let Some(json) = json {
+            check_drawing_json(name, json)?;
+        }
+    }
+
+    let now = now_millis();
+    let (previous_manifest, mut previous_archive) = previous.unzip();
+    let (sources, embedded) = match &content.sources {
+        Some(inputs) => collect_sources(path, inputs)?,
+        None => {
+            let manifest = previous_manifest.as_ref().ok_or("元原稿を指定してください")?;
+            (manifest.sources.clone(), Vec::new())
+        }
+    };
+    if sources.is_empty() {
+        return Err("元原稿を指定してください".to_string());
+    }
+
+    // 指定のない項目は元のプロジェクトから (展開せずに) 引き継ぐ
+    let mut kept_entries = Vec::new();
+    if let Some(archive) = previous_archive.as_ref() {
+        let keep = |name: &str| match name {
+            DRAWING_ENTRY => content.drawings.is_none(),
+            COMMENT_ENTRY => content.comments.is_none(),
+            CHECKED_ENTRY => content.checked_state.is_none(),
+            MEMO_ENTRY => content.memo.is_none(),
+            SETTINGS_ENTRY => content.settings.is_none(),
+            _ => content.sources.is_none() && sources.iter().any(|s| s.entry.as_deref() == Some(name)),
+        };
+        kept_entries = archive.entries().iter().filter(|e| keep(&e.name)).cloned().collect();
+    }
+
+    let manifest = ProjectManifest {
+        format: PROJECT_FORMAT.to_string(),
+        version: PROJECT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: previous_manifest.map(|m| m.created_at).unwrap_or_else(|| iso_timestamp(now)),
+        modified_at: iso_timestamp(now),
+        sources,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let json_entry = |value: &Option<serde_json::Value>| -> Result<Option<Vec<u8>>, String> {
+        value.as_ref().map(|v| serde_json::to_vec_pretty(v).map_err(|e| e.to_string())).transpose()
+    };
+    let data_entries: Vec<(&str, Option<Vec<u8>>)> = vec![
+        (DRAWING_ENTRY, content.drawings.map(String::into_bytes)),
+        (COMMENT_ENTRY, content.comments.map(String::into_bytes)),
+        (CHECKED_ENTRY, json_entry(&content.checked_state)?),
+        (MEMO_ENTRY, content.memo.map(String::into_bytes)),
+        (SETTINGS_ENTRY, json_entry(&content.settings)?),
+    ];
+
+    let path_str = path.to_string_lossy();
+    crate::pdf::atomic_write_file(&path_str, crate::backup::DEFAULT_BACKUP_KEEP, |writer| {
+        let mut zip = ZipWriter::new(writer);
+        zip.add_deflated(MANIFEST_ENTRY, &manifest_json)?;
+        for (name, data) in &data_entries {
+            if let Some(data) = data {
+                zip.add_deflated(name, data)?;
+            }
+        }
+        // 元のファイルを開いたまま置き換えないよう、引き継ぎ終わったら閉じる
+        if let Some(mut archive) = previous_archive.take() {
+            for entry in &kept_entries {
+                zip.add_raw(&mut archive, entry)?;
+            }
+        }
+        for (source, entry) in &embedded {
+            zip.add_stored_file(entry, source)
+                .map_err(|e| format!("{} を埋め込めません: {}", source.display(), e))?;
+        }
+        zip.finish()?;
+        Ok(())
+    })
+    .map_err(|e| format!("プロジェクトを保存できません: {}", e))?;
+
+    Ok(manifest)
+}
+
+/// 新しいプロジェクトを作る (既にあれば置き換える)。
+pub fn create(path: &Path, content: ProjectContent) -> Result<ProjectManifest, String> {
+    if content.sources.as_ref().is_none_or(|s| s.is_empty()) {
+        return Err("元原稿を指定してください".to_string());
+    }
+    write_project(path, content, None)
+}
+
+/// 既存のプロジェクトを更新する。`content` で None の項目は今の内容を残す。
+pub fn save(path: &Path, content: ProjectContent) -> Result<ProjectManifest, String> {
+    let mut archive = open_archive(path)?;
+    let manifest = read_manifest(&mut archive)?;
+    write_project(path, content, Some((manifest, archive)))
+}
+
+/// 埋め込んだ元原稿の展開先。プロジェクトごとに別のフォルダにする。
+pub fn extract_dir(app: &tauri::AppHandle, project_path: &Path) -> Result<PathBuf, String> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    project_path.hash(&mut hasher);
+    let stem = project_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    app.path()
+        .app_cache_dir()
+        .map(|dir| dir.join(EXTRACT_DIR_NAME).join(format!("{}-{:016x}", stem, hasher.finish())))
+        .map_err(|e| format!("キャッシュフォルダを取得できません: {}", e))
+}
+
+/// プロジェクトを開く。埋め込みの元原稿は `extract_to` に展開する。
+pub fn open(path: &Path, extract_to: &Path) -> Result<OpenedProject, String> {
+    let mut archive = open_archive(path)?;
+    let manifest = read_manifest(&mut archive)?;
+
+    // 前回開いたときの展開が残っていれば消してから展開し直す
+    if extract_to.exists() {
+        std::fs::remove_dir_all(extract_to)
+            .map_err(|e| format!("展開先を削除できません: {}: {}", extract_to.display(), e))?;
+    }
+
+    let mut source_paths = Vec::with_capacity(manifest.sources.len());
+    let mut missing_sources = Vec::new();
+    for source in &manifest.sources {
+        match &source.entry {
+            Some(entry) => {
+                if !is_safe_source_entry(entry) {
+                    return Err(format!("プロジェクトの元原稿のパスが不正です: {}", entry));
+                }
+                let zip_entry = archive
+                    .entry(entry)
+                    .cloned()
+                    .ok_or_else(|| format!("埋め込まれた元原稿がありません: {}", entry))?;
+                let dest = extract_to.join(&entry[SOURCES_DIR.len()..]);
+                if let Some(parent) = dest.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| format!("展開先を作れません: {}", e))?;
+                }
+                let file = File::create(&dest).map_err(|e| format!("{} を展開できません: {}", source.name, e))?;
+                archive
+                    .extract_to(&zip_entry, std::io::BufWriter::new(file))
+                    .map_err(|e| format!("{} を展開できません: {}", source.name, e))?;
+                source_paths.push(dest.to_string_lossy().into_owned());
+            }
+            None => match resolve_linked_source(path, source) {
+                Some(found) => source_paths.push(found.to_string_lossy().into_owned()),
+                None => missing_sources.push(source.original_path.clone()),
+            },
+        }
+    }
+
+    Ok(OpenedProject {
+        source_paths,
+        missing_sources,
+        drawings: read_text(&mut archive, DRAWING_ENTRY)?,
+        comments: read_text(&mut archive, COMMENT_ENTRY)?,
+        checked_state: read_json(&mut archive, CHECKED_ENTRY)?,
+        memo: read_text(&mut archive, MEMO_ENTRY)?,
+        settings: read_json(&mut archive, SETTINGS_ENTRY)?,
+        manifest,
+    })
+}
+
+/// プロジェクトを検証する: ZIP の各エントリの CRC、manifest、元原稿の有無、各データの形式。
+pub fn validate(path: &Path) -> ProjectValidation {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let result = |errors: Vec<String>, warnings: Vec<String>, manifest: Option<ProjectManifest>| ProjectValidation {
+        valid: errors.is_empty(),
+        errors,
+        warnings,
+        manifest,
+    };
+
+    let mut archive = match open_archive(path) {
+        Ok(archive) => archive,
+        Err(e) => return result(vec![e], warnings, None),
+    };
+    let manifest = match read_manifest(&mut archive) {
+        Ok(manifest) => manifest,
+        Err(e) => return result(vec![e], warnings, None),
+    };
+
+    for entry in archive.entries().to_vec() {
+        if let Err(e) = archive.extract_to(&entry, std::io::sink()) {
+            errors.push(e.to_string());
+        }
+    }
+
+    for source in &manifest.sources {
+        match &source.entry {
+            Some(entry) if !is_safe_source_entry(entry) => {
+                errors.push(format!("元原稿のパスが不正です: {}", entry));
+            }
+            Some(entry) => match archive.entry(entry) {
+                Some(zip_entry) if zip_entry.size != source.size => {
+                    errors.push(format!("埋め込まれた元原稿のサイズが違います: {}", source.name));
+                }
+                Some(_) => {}
+                None => errors.push(format!("埋め込まれた元原稿がありません: {}", source.name)),
+            },
+            None => match resolve_linked_source(path, source) {
+                Some(found) => {
+                    let size = std::fs::metadata(&found).map(|m| m.len()).unwrap_or(0);
+                    if size != source.size {
+                        warnings.push(format!("元原稿がプロジェクト作成後に変更されています: {}", found.display()));
+                    }
+                }
+                None => warnings.push(format!("参照している元原稿が見つかりません: {}", source.original_path)),
+            },
+        }
+    }
+    if manifest.sources.is_empty() {
+        errors.push("元原稿がありません".to_string());
+    }
+
+    for name in [DRAWING_ENTRY, COMMENT_ENTRY] {
+        match read_text(&mut archive, name) {
+            Ok(Some(json)) => {
+                if let Err(e) = check_drawing_json(name, &json) {
+                    errors.push(e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    for name in [CHECKED_ENTRY, SETTINGS_ENTRY] {
+        if let Err(e) = read_json(&mut archive, name) {
+            errors.push(e);
+        }
+    }
+    if let Err(e) = read_text(&mut archive, MEMO_ENTRY) {
+        errors.push(e);
+    }
+
+    let known = [MANIFEST_ENTRY, DRAWING_ENTRY, COMMENT_ENTRY, CHECKED_ENTRY, MEMO_ENTRY, SETTINGS_ENTRY];
+    for entry in archive.entries() {
+        let referenced = manifest.sources.iter().any(|s| s.entry.as_deref() == Some(entry.name.as_str()));
+        if !known.contains(&entry.name.as_str()) && !referenced {
+            warnings.push(format!("不明なファイルが含まれています: {}", entry.name));
+        }
+    }
+
+    result(errors, warnings, Some(manifest))
+}
@@ -0,0 +1,153 @@
+// 多重起動の防止: 起動時にローカルソケット (Windows は名前付きパイプ、Linux は抽象名前空間、macOS はソケットファイル) に
+// 接続を試み、既に起動しているアプリがあればコマンドライン引数のファイルを渡して終了する。
+// 最初のインスタンスはソケットで待ち受け、後から起動されたインスタンスのファイルを受け取る。
+// 応答しない相手で起動や待ち受けが止まらないよう、送受信はスレッドを分けて待つ時間を区切る
+// (Windows の名前付きパイプは読み書きのタイムアウトに対応していないため)。
+
+use interprocess::local_socket::{prelude::*, GenericFilePath, GenericNamespaced, Listener, ListenerOptions, Name, Stream};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// ファイルを受け取ったことを送り返す応答。後から起動した側はこれを読んでから終了する。
+const ACK: &str = "ok\n";
+
+/// 後から起動した側が応答を待つ時間。過ぎたら既存のインスタンスは応答しないとみなして通常どおり起動する。
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 最初のインスタンスがメッセージの受信を待つ時間 (タイムアウトに対応している OS のみ)。
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 1 回の接続で受け取るメッセージの上限 (ファイルパスの JSON 配列)。
+const MAX_MESSAGE_BYTES: u64 = 1024 * 1024;
+
+pub enum Instance {
+    /// 最初のインスタンス。待ち受けに失敗した場合は None (多重起動防止なしで続行する)
+    Primary(Option<Listener>),
+    /// 既に起動しているインスタンスにファイルを渡した
+    Forwarded,
+}
+
+/// ユーザーごとのソケット名。別ユーザーのアプリには接続しない。
+fn socket_file_name() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let user: String = user.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    format!("mojiq-pro-{}.sock", user)
+}
+
+/// 名前空間型の名前が使えない環境 (macOS) ではテンポラリフォルダのソケットファイルを使う。
+fn socket_file_path() -> std::path::PathBuf {
+    std::env::temp_dir().join(socket_file_name())
+}
+
+fn socket_name() -> std::io::Result<Name<'static>> {
+    if GenericNamespaced::is_supported() {
+        socket_file_name().to_ns_name::<GenericNamespaced>()
+    } else {
+        socket_file_path().to_fs_name::<GenericFilePath>()
+    }
+}
+
+/// 既存のインスタンスにファイルを渡す。`ACK_TIMEOUT` 以内に応答が返れば true。
+/// 接続できなければ (起動中のインスタンスがなければ) エラー。
+fn forward(name: Name<'static>, files: &[String]) -> std::io::Result<bool> {
+    let stream = Stream::connect(name)?;
+    let message = serde_json::to_string(files).map_err(std::io::Error::other)?;
+
+    // 応答しないインスタンスに書き込み・読み込みで止められないよう、別スレッドで送受信する。
+    // 時間切れの場合、スレッドはブロックしたまま残るがプロセスの終了とともに消える
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let exchange = || -> std::io::Result<bool> {
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(format!("{}\n", message).as_bytes())?;
+            let mut reply = String::new();
+            stream.take(ACK.len() as u64).read_line(&mut reply)?;
+            Ok(reply == ACK)
+        };
+        let _ = tx.send(exchange());
+    });
+
+    match rx.recv_timeout(ACK_TIMEOUT) {
+        Ok(result) => result,
+        Err(_) => Ok(false),
+    }
+}
+
+/// 既存のインスタンスがあればファイルを渡し、なければ待ち受けを開始する。
+/// `files` は絶対パスにしておくこと (起動したインスタンスと作業フォルダが違うため)。
+pub fn acquire(files: &[String]) -> Instance {
+    let name = match socket_name() {
+        Ok(name) => name,
+        Err(e) => {
+            eprintln!("[MojiQ] 多重起動チェックのソケット名を作れません: {}", e);
+            return Instance::Primary(None);
+        }
+    };
+
+    match forward(name.clone(), files) {
+        Ok(true) => return Instance::Forwarded,
+        Ok(false) => eprintln!("[MojiQ] 起動中のインスタンスから応答がありません。新しく起動します"),
+        // 接続できない = 起動中のインスタンスがない
+        Err(_) => {}
+    }
+
+    let create = || ListenerOptions::new().name(name.clone()).create_sync();
+    let listener = match create() {
+        // 異常終了したインスタンスのソケットファイルが残っている (ファイル型の名前のみ)。接続できなかったので削除してやり直す
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && !GenericNamespaced::is_supported() => {
+            let _ = std::fs::remove_file(socket_file_path());
+            create()
+        }
+        result => result,
+    };
+
+    match listener {
+        Ok(listener) => Instance::Primary(Some(listener)),
+        Err(e) => {
+            eprintln!("[MojiQ] 多重起動チェックの待ち受けを開始できません: {}", e);
+            Instance::Primary(None)
+        }
+    }
+}
+
+/// 後から起動したインスタンスからの接続を待ち受け、渡されたファイル (空のこともある) を `on_files` に渡す。
+/// 呼び出し元のスレッドをブロックするので専用スレッドで呼ぶ。
+/// 接続ごとにスレッドを分けるので、何も送ってこない接続があっても次の接続を受け付けられる。
+pub fn serve<F>(listener: Listener, on_files: F)
+where
+    F: Fn(Vec<String>) + Send + Sync + 'static,
+{
+    let on_files = Arc::new(on_files);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("[MojiQ] 多重起動チェックの接続エラー: {}", e);
+                continue;
+            }
+        };
+        let on_files = Arc::clone(&on_files);
+        // Windows では未対応でエラーになるが、その場合もスレッドが 1 つ残るだけで待ち受けは続く
+        let _ = stream.set_recv_timeout(Some(RECEIVE_TIMEOUT));
+        std::thread::spawn(move || {
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            if let Err(e) = (&mut stream).take(MAX_MESSAGE_BYTES).read_line(&mut line) {
+                eprintln!("[MojiQ] 多重起動チェックの受信エラー: {}", e);
+                return;
+            }
+            // 上限で切れたメッセージや途中で切断されたメッセージは改行で終わらない
+            if !line.ends_with('\n') {
+                eprintln!("[MojiQ] 多重起動チェックのメッセージが不完全です ({} bytes)", line.len());
+                return;
+            }
+            let files: Vec<String> = serde_json::from_str(line.trim()).unwrap_or_default();
+            let _ = stream.get_mut().write_all(ACK.as_bytes());
+            on_files(files);
+        });
+    }
+}
@@ -0,0 +1,151 @@
+// 見開き出力: 2 ページを横に並べて 1 枚の PDF ページにする割り付け。
+// 保存モード (通常 / 圧縮 / レイヤー分離) はページごとの画像を作るだけで、
+// どう並べて書き出すかは `PageLayout` に任せる。
+
+use std::collections::VecDeque;
+use std::io::Write;
+use crate::pdf_writer::{ImageRect, PdfImage, PdfWriter};
+
+/// 見開き出力の設定。
+pub struct SpreadOptions {
+    /// 右綴じ (右から左に読む)。true なら若い番号のページを右側に置く
+    pub right_to_left: bool,
+    /// 左右ページの間隔 (ノド, pt)
+    pub gutter_pt: f32,
+    /// 1 ページ目 (表紙) を見開きにせず単独で出力する
+    pub single_cover: bool,
+    /// 最終ページ (裏表紙) を見開きにせず単独で出力する
+    pub single_back: bool,
+}
+
+/// ページを並べる単位 (何ページで 1 枚にするか) を先頭から順に求める。
+/// 表紙・裏表紙の単独指定を除いた残りを 2 ページずつ組にし、端数は単独にする。
+fn spread_group_sizes(page_count: usize, options: &SpreadOptions) -> VecDeque<usize> {
+    let mut sizes = VecDeque::new();
+    let mut start = 0;
+    if options.single_cover && page_count > 0 {
+        sizes.push_back(1);
+        start = 1;
+    }
+    let end = if options.single_back && page_count > start {
+        page_count - 1
+    } else {
+        page_count
+    };
+
+    let mut i = start;
+    while i < end {
+        let size = (end - i).min(2);
+        sizes.push_back(size);
+        i += size;
+    }
+    if end < page_count {
+        sizes.push_back(1);
+    }
+    sizes
+}
+
+/// 書き出し待ちのページ。
+struct PendingPage {
+    width_pt: f32,
+    height_pt: f32,
+    /// 画像を置く範囲 (ページの左下原点)
+    content: ImageRect,
+    images: Vec<PdfImage>,
+}
+
+/// ページを順に受け取り、単ページ出力ならそのまま、見開き出力なら組がそろった時点で
+/// 1 枚にまとめて `PdfWriter` へ書き出す。保留するのは組の前半 1 ページ分だけ。
+pub struct PageLayout {
+    spread: Option<SpreadOptions>,
+    group_sizes: VecDeque<usize>,
+    pending: Vec<PendingPage>,
+}
+
+impl PageLayout {
+    /// 1 ページ = 1 枚の通常の出力。
+    pub fn single() -> Self {
+        PageLayout {
+            spread: None,
+            group_sizes: VecDeque::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// `page_count` ページを見開きで出力する。
+    pub fn spread(page_count: usize, options: SpreadOptions) -> Self {
+        PageLayout {
+            group_sizes: spread_group_sizes(page_count, &options),
+            spread: Some(options),
+            pending: Vec::new(),
+        }
+    }
+
+    /// 元の 1 ページ分 (`images` はページの `content` の範囲に重ねる画像) を追加する。
+    pub fn add_page<W: Write>(
+        &mut self,
+        writer: &mut PdfWriter<W>,
+        (width_pt, height_pt): (f32, f32),
+        content: ImageRect,
+        images: Vec<PdfImage>,
+    ) -> std::io::Result<()> {
+        if self.spread.is_none() {
+            let placed = images.into_iter().map(|image| (image, content)).collect();
+            return writer.add_page_with_layout(width_pt, height_pt, placed);
+        }
+
+        self.pending.push(PendingPage {
+            width_pt,
+            height_pt,
+            content,
+            images,
+        });
+        if self.pending.len() >= self.group_sizes.front().copied().unwrap_or(1) {
+            self.group_sizes.pop_front();
+            self.flush(writer)?;
+        }
+        Ok(())
+    }
+
+    /// 保留中のページを書き出す。全ページを追加し終えたら必ず呼ぶ。
+    pub fn finish<W: Write>(&mut self, writer: &mut PdfWriter<W>) -> std::io::Result<()> {
+        self.flush(writer)
+    }
+
+    fn flush<W: Write>(&mut self, writer: &mut PdfWriter<W>) -> std::io::Result<()> {
+        let mut pages: Vec<PendingPage> = self.pending.drain(..).collect();
+        let options = match self.spread.as_ref() {
+            Some(options) if pages.len() >= 2 => options,
+            _ => {
+                for page in pages {
+                    let placed = page.images.into_iter().map(|image| (image, page.content)).collect();
+                    writer.add_page_with_layout(page.width_pt, page.height_pt, placed)?;
+                }
+                return Ok(());
+            }
+        };
+
+        // 左から順に並べる。右綴じは若い番号のページが右側
+        if options.right_to_left {
+            pages.reverse();
+        }
+        let gutter = options.gutter_pt.max(0.0);
+        let width_pt = pages.iter().map(|p| p.width_pt).sum::<f32>() + gutter * (pages.len() - 1) as f32;
+        let height_pt = pages.iter().map(|p| p.height_pt).fold(0.0, f32::max);
+
+        let mut placed = Vec::new();
+        let mut x = 0.0;
+        for page in pages {
+            // 高さが違うページは上下中央にそろえる
+            let rect = ImageRect {
+                x: x + page.content.x,
+                y: (height_pt - page.height_pt) / 2.0 + page.content.y,
+                width: page.content.width,
+                height: page.content.height,
+            };
+            placed.extend(page.images.into_iter().map(|image| (image, rect)));
+            x += page.width_pt + gutter;
+        }
+        writer.add_page_with_layout(width_pt, height_pt, placed)
+    }
+}
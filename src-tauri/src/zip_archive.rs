@@ -0,0 +1,424 @@
+// .mojiq プロジェクト用の最小限の ZIP の読み書き。
+// 無圧縮 (stored) と deflate のみに対応し、ZIP64 は扱わない (エントリ・ファイル全体とも 4GB 未満)。
+// ファイル名は UTF-8 (汎用フラグの bit 11) で書く。
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIGNATURE: u32 = 0x0605_4b50;
+const LOCAL_HEADER_LEN: u64 = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+/// ZIP のコメントの最大長。終端レコードはファイル末尾からこの範囲にある
+const MAX_COMMENT_LEN: u64 = 0xffff;
+/// 展開するときに先に確保するバッファの上限。中央ディレクトリのサイズは信用できないので、超える分は読みながら広げる
+const MAX_PREALLOCATE: u64 = 16 * 1024 * 1024;
+/// 展開に必要なバージョン (2.0: deflate)
+const VERSION_NEEDED: u16 = 20;
+/// ファイル名が UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+
+pub const METHOD_STORED: u16 = 0;
+pub const METHOD_DEFLATED: u16 = 8;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn too_large(name: &str) -> io::Error {
+    io::Error::other(format!("{} が大きすぎます (4GB 以上は保存できません)", name))
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+/// 現在時刻 (UTC) の MS-DOS 形式の (時刻, 日付)。
+fn dos_datetime_now() -> (u16, u16) {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let (year, month, day, hour, minute, second) = crate::backup::utc_datetime(millis);
+    // MS-DOS 形式は 1980 年から
+    let year = year.clamp(1980, 2107) as u16;
+    let time = ((hour as u16) << 11) | ((minute as u16) << 5) | (second as u16 / 2);
+    let date = ((year - 1980) << 9) | ((month as u16) << 5) | day as u16;
+    (time, date)
+}
+
+/// CRC-32 とバイト数を数えながら書き出す。
+struct CrcWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    written: u64,
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 中央ディレクトリに書くエントリの情報。
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub size: u64,
+    header_offset: u64,
+}
+
+/// 先頭から順にエントリを書き、`finish` で中央ディレクトリを書く。
+pub struct ZipWriter<W: Write> {
+    inner: W,
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        let (dos_time, dos_date) = dos_datetime_now();
+        ZipWriter { inner, offset: 0, entries: Vec::new(), dos_time, dos_date }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// ローカルヘッダを書いてエントリを登録する。データはこの後に `compressed_size` バイト書くこと。
+    fn start_entry(&mut self, name: &str, method: u16, crc32: u32, compressed_size: u64, size: u64) -> io::Result<()> {
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(io::Error::other(format!("ZIP に同じ名前のエントリがあります: {}", name)));
+        }
+        let limit = u64::from(u32::MAX);
+        if compressed_size >= limit || size >= limit || self.offset >= limit {
+            return Err(too_large(name));
+        }
+        let name_len = u16::try_from(name.len()).map_err(|_| invalid_data(format!("名前が長すぎます: {}", name)))?;
+
+        let mut header = Vec::with_capacity(LOCAL_HEADER_LEN as usize + name.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&self.dos_time.to_le_bytes());
+        header.extend_from_slice(&self.dos_date.to_le_bytes());
+        header.extend_from_slice(&crc32.to_le_bytes());
+        header.extend_from_slice(&(compressed_size as u32).to_le_bytes());
+        header.extend_from_slice(&(size as u32).to_le_bytes());
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+
+        self.entries.push(ZipEntry {
+            name: name.to_string(),
+            method,
+            crc32,
+            compressed_size,
+            size,
+            header_offset: self.offset,
+        });
+        self.write_all(&header)
+    }
+
+    /// メモリ上のデータを deflate で圧縮して追加する。
+    pub fn add_deflated(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        self.start_entry(name, METHOD_DEFLATED, crc32fast::hash(data), compressed.len() as u64, data.len() as u64)?;
+        self.write_all(&compressed)
+    }
+
+    /// ファイルを無圧縮で追加する (PDF や JPEG は圧縮済みなので deflate しない)。
+    /// CRC を先に計算するため 2 回読む。大きなファイルもメモリに載せない。
+    pub fn add_stored_file(&mut self, name: &str, path: &Path) -> io::Result<()> {
+        let mut counter = CrcWriter { inner: io::sink(), hasher: crc32fast::Hasher::new(), written: 0 };
+        io::copy(&mut std::fs::File::open(path)?, &mut counter)?;
+        let (crc32, size) = (counter.hasher.finalize(), counter.written);
+
+        self.start_entry(name, METHOD_STORED, crc32, size, size)?;
+        let copied = io::copy(&mut std::fs::File::open(path)?.take(size), &mut self.inner)?;
+        self.offset += copied;
+        if copied != size {
+            return Err(io::Error::other(format!("書き込み中に {} が変更されました", path.display())));
+        }
+        Ok(())
+    }
+
+    /// 別の ZIP のエントリを展開せずにそのまま追加する。
+    pub fn add_raw<R: Read + Seek>(&mut self, source: &mut ZipArchive<R>, entry: &ZipEntry) -> io::Result<()> {
+        self.start_entry(&entry.name, entry.method, entry.crc32, entry.compressed_size, entry.size)?;
+        let mut data = source.raw_reader(entry)?;
+        let copied = io::copy(&mut data, &mut self.inner)?;
+        self.offset += copied;
+        if copied != entry.compressed_size {
+            return Err(invalid_data(format!("ZIP のエントリが途中で終わっています: {}", entry.name)));
+        }
+        Ok(())
+    }
+
+    /// 中央ディレクトリと終端レコードを書き、書き込み先を返す。
+    pub fn finish(mut self) -> io::Result<W> {
+        let central_offset = self.offset;
+        let mut central = Vec::new();
+        for entry in &self.entries {
+            central.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend_from_slice(&VERSION_NEEDED.to_le_bytes()); // 作成したバージョン
+            central.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+            central.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+            central.extend_from_slice(&entry.method.to_le_bytes());
+            central.extend_from_slice(&self.dos_time.to_le_bytes());
+            central.extend_from_slice(&self.dos_date.to_le_bytes());
+            central.extend_from_slice(&entry.crc32.to_le_bytes());
+            central.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+            central.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            central.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]); // 拡張フィールド長・コメント長・ディスク番号・内部属性・外部属性
+            central.extend_from_slice(&(entry.header_offset as u32).to_le_bytes());
+            central.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = u16::try_from(self.entries.len()).map_err(|_| io::Error::other("ZIP のエントリが多すぎます"))?;
+        if central_offset + central.len() as u64 >= u64::from(u32::MAX) {
+            return Err(too_large("ZIP"));
+        }
+
+        let mut end = Vec::with_capacity(END_OF_CENTRAL_DIR_LEN);
+        end.extend_from_slice(&END_OF_CENTRAL_DIR_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0; 4]); // ディスク番号
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        end.extend_from_slice(&(central_offset as u32).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // コメント長
+
+        self.write_all(&central)?;
+        self.write_all(&end)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// 中央ディレクトリを読み、エントリを名前で取り出す。
+pub struct ZipArchive<R: Read + Seek> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let tail_len = len.min(MAX_COMMENT_LEN + END_OF_CENTRAL_DIR_LEN as u64);
+        reader.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        reader.read_exact(&mut tail)?;
+        // 終端レコードが収まらないファイル (空のファイルなど)。以降の位置はどれも終端レコード全体が tail に収まる
+        if tail.len() < END_OF_CENTRAL_DIR_LEN {
+            return Err(invalid_data("ZIP ファイルではありません"));
+        }
+
+        let end = (0..=tail.len() - END_OF_CENTRAL_DIR_LEN)
+            .rev()
+            .find(|&pos| u32_at(&tail, pos) == END_OF_CENTRAL_DIR_SIGNATURE)
+            .ok_or_else(|| invalid_data("ZIP ファイルではありません"))?;
+        let end = &tail[end..end + END_OF_CENTRAL_DIR_LEN];
+        let count = u16_at(end, 10) as usize;
+        let central_len = u32_at(end, 12);
+        let central_offset = u32_at(end, 16);
+        if count == 0xffff || central_len == u32::MAX || central_offset == u32::MAX {
+            return Err(invalid_data("ZIP64 形式には対応していません"));
+        }
+        if u64::from(central_offset) + u64::from(central_len) > len {
+            return Err(invalid_data("ZIP の中央ディレクトリが壊れています"));
+        }
+
+        reader.seek(SeekFrom::Start(u64::from(central_offset)))?;
+        let mut central = vec![0; central_len as usize];
+        reader.read_exact(&mut central)?;
+
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = 0;
+        for _ in 0..count {
+            if pos + CENTRAL_HEADER_LEN > central.len() || u32_at(&central, pos) != CENTRAL_HEADER_SIGNATURE {
+                return Err(invalid_data("ZIP の中央ディレクトリが壊れています"));
+            }
+            let name_len = u16_at(&central, pos + 28) as usize;
+            let extra_len = u16_at(&central, pos + 30) as usize;
+            let comment_len = u16_at(&central, pos + 32) as usize;
+            let name_end = pos + CENTRAL_HEADER_LEN + name_len;
+            let name = central
+                .get(pos + CENTRAL_HEADER_LEN..name_end)
+                .ok_or_else(|| invalid_data("ZIP の中央ディレクトリが壊れています"))?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(&central, pos + 10),
+                crc32: u32_at(&central, pos + 16),
+                compressed_size: u64::from(u32_at(&central, pos + 20)),
+                size: u64::from(u32_at(&central, pos + 24)),
+                header_offset: u64::from(u32_at(&central, pos + 42)),
+            });
+            pos = name_end + extra_len + comment_len;
+        }
+
+        Ok(ZipArchive { reader, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// エントリの圧縮されたままのデータ。
+    fn raw_reader(&mut self, entry: &ZipEntry) -> io::Result<io::Take<&mut R>> {
+        self.reader.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0; LOCAL_HEADER_LEN as usize];
+        self.reader.read_exact(&mut header)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_data(format!("ZIP のエントリが壊れています: {}", entry.name)));
+        }
+        let skip = u64::from(u16_at(&header, 26)) + u64::from(u16_at(&header, 28));
+        self.reader.seek(SeekFrom::Current(skip as i64))?;
+        Ok((&mut self.reader).take(entry.compressed_size))
+    }
+
+    /// エントリを展開して `out` に書き、サイズと CRC を確かめる。
+    pub fn extract_to<W: Write>(&mut self, entry: &ZipEntry, out: W) -> io::Result<u64> {
+        let raw = self.raw_reader(entry)?;
+        let mut reader: Box<dyn Read + '_> = match entry.method {
+            METHOD_STORED => Box::new(raw),
+            METHOD_DEFLATED => Box::new(DeflateDecoder::new(raw)),
+            method => return Err(invalid_data(format!("対応していない圧縮方式です ({}): {}", method, entry.name))),
+        };
+        let mut writer = CrcWriter { inner: out, hasher: crc32fast::Hasher::new(), written: 0 };
+        // 中央ディレクトリのサイズより大きく展開されるデータは壊れている
+        io::copy(&mut (&mut reader).take(entry.size + 1), &mut writer)?;
+        if writer.written != entry.size || writer.hasher.finalize() != entry.crc32 {
+            return Err(invalid_data(format!("ZIP のエントリが壊れています (CRC 不一致): {}", entry.name)));
+        }
+        Ok(writer.written)
+    }
+
+    /// 名前でエントリを探して展開する。なければ None。
+    pub fn read(&mut self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entry(name).cloned() else {
+            return Ok(None);
+        };
+        let mut data = Vec::with_capacity(entry.size.min(MAX_PREALLOCATE) as usize);
+        self.extract_to(&entry, &mut data)?;
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// テストは並列に走るので、呼び出しごとに別の一時ファイルを使う。
+    static NEXT_SAMPLE: AtomicUsize = AtomicUsize::new(0);
+
+    /// deflate と無圧縮のエントリを 1 つずつ持つ ZIP。
+    fn sample_zip() -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "mojiq-zip-test-{}-{}.bin",
+            std::process::id(),
+            NEXT_SAMPLE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, b"%PDF-1.7 stored bytes").unwrap();
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_deflated("manifest.json", "{\"名前\": \"第1話\"}".repeat(100).as_bytes()).unwrap();
+        zip.add_stored_file("pages/001.pdf", &path).unwrap();
+        std::fs::remove_file(&path).ok();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn write_then_read() {
+        let bytes = sample_zip();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let names: Vec<_> = archive.entries().iter().map(|e| (e.name.as_str(), e.method)).collect();
+        assert_eq!(names, [("manifest.json", METHOD_DEFLATED), ("pages/001.pdf", METHOD_STORED)]);
+
+        let manifest = archive.read("manifest.json").unwrap().unwrap();
+        assert_eq!(manifest, "{\"名前\": \"第1話\"}".repeat(100).as_bytes());
+        assert_eq!(archive.read("pages/001.pdf").unwrap().unwrap(), b"%PDF-1.7 stored bytes");
+        assert_eq!(archive.read("missing.json").unwrap(), None);
+    }
+
+    #[test]
+    fn copies_raw_entries() {
+        let mut source = ZipArchive::new(Cursor::new(sample_zip())).unwrap();
+        let mut zip = ZipWriter::new(Vec::new());
+        for entry in source.entries().to_vec() {
+            zip.add_raw(&mut source, &entry).unwrap();
+        }
+        let mut copy = ZipArchive::new(Cursor::new(zip.finish().unwrap())).unwrap();
+        assert_eq!(copy.read("pages/001.pdf").unwrap().unwrap(), b"%PDF-1.7 stored bytes");
+        assert!(copy.read("manifest.json").unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_crc_mismatch() {
+        let mut bytes = sample_zip();
+        let at = bytes.windows(5).position(|w| w == b"%PDF-").unwrap();
+        bytes[at] = b'!';
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let error = archive.read("pages/001.pdf").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(archive.read("manifest.json").is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = sample_zip();
+        for len in [0, 1, 3, 4, 21, 22, bytes.len() / 2, bytes.len() - 1] {
+            assert!(ZipArchive::new(Cursor::new(&bytes[..len])).is_err(), "len {}", len);
+        }
+        // 終端レコードだけの空の ZIP は読める
+        let empty = ZipWriter::new(Vec::new()).finish().unwrap();
+        assert!(ZipArchive::new(Cursor::new(empty)).unwrap().entries().is_empty());
+    }
+
+    #[test]
+    fn does_not_trust_entry_size() {
+        let mut bytes = sample_zip();
+        // 中央ディレクトリの 2 つ目のエントリ (pages/001.pdf) の展開後サイズを 4GB 近くにする
+        let central = bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == CENTRAL_HEADER_SIGNATURE.to_le_bytes())
+            .map(|(pos, _)| pos)
+            .nth(1)
+            .unwrap();
+        bytes[central + 24..central + 28].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.read("pages/001.pdf").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
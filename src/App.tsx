@@ -34,6 +34,8 @@ import { useTextLayerStore } from './stores/textLayerStore';
 import { useModeStore } from './stores/modeStore';
 import { useSidebarStore } from './stores/sidebarStore';
 import { useSettingsStore } from './stores/settingsStore';
+import { useAutosave } from './hooks/useAutosave';
+import { useLinkedFileWatcher } from './hooks/useLinkedFileWatcher';
 import { LoadedDocument, FileMetadata, ToolType } from './types';
 import { renderPdfToImages } from './utils/pdfRenderer';
 import { preloadAllBackgroundImages, backgroundImageCache } from './utils/backgroundImageCache';
@@ -58,6 +60,11 @@ function App() {
     updateDocument,
     createNewDocument,
   } = useDocumentStore();
+
+  // クラッシュ復旧用の自動保存（起動時の復旧確認を含む）
+  useAutosave();
+  // リンク方式の画像の外部変更監視
+  useLinkedFileWatcher();
   const { setLoading, setProgress } = useLoadingStore();
   const { showAlert, showConfirm } = useModalStore();
   const { zoom, resetZoom, setZoom } = useZoomStore();
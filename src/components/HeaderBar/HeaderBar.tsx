@@ -958,7 +958,7 @@ export const HeaderBar: React.FC = () => {
       }, 0);
 
       try {
-        const diskResult = await invoke<{ free_space: number; is_enough: boolean }>('check_disk_space', {
+        const diskResult = await invoke<{ free_space: number; total_space: number; is_enough: boolean }>('check_disk_space', {
           filePath: savePath,
           requiredBytes: Math.ceil(estimatedBytes),
         });
@@ -1125,6 +1125,15 @@ export const HeaderBar: React.FC = () => {
       // （元PDFに既にテキストがあるため、保存後は非表示のままにする）
       useCommentVisibilityStore.getState().hide();
 
+      // 保存できたので自動保存ジャーナルは不要（元ファイルと保存先の両方）
+      const previousPath = useDocumentStore.getState().getActiveDocument()?.filePath;
+      for (const sourcePath of new Set([previousPath, savePath])) {
+        if (!sourcePath) continue;
+        invoke('discard_autosave_session', { sourcePath }).catch((error) => {
+          console.error('Failed to discard autosave:', error);
+        });
+      }
+
       // ドキュメントを保存済みとしてマーク
       if (activeDocumentId) {
         markAsSaved(activeDocumentId, savePath);
@@ -1297,6 +1306,8 @@ export const HeaderBar: React.FC = () => {
   useEffect(() => {
     const handleOpenFileEvent = async (e: Event) => {
       const path = (e as CustomEvent).detail?.path;
+      // 自動保存からの復元時はジャーナルID が渡される
+      const recoverJournalId: string | undefined = (e as CustomEvent).detail?.recoverJournalId;
       if (!path) return;
 
       disableSpreadView();
@@ -1408,6 +1419,18 @@ export const HeaderBar: React.FC = () => {
           }
         }
 
+        // 自動保存の描画データを読み込んだページに適用
+        if (recoverJournalId) {
+          setLoading(true, '自動保存から復元中...');
+          const jsonData = await invoke<string>('load_autosave_session', { journalId: recoverJournalId });
+          const importData = parseImportJson(jsonData);
+          const loadedPages = useDrawingStore.getState().pages;
+          useDrawingStore.getState().setPages(applyImportDataToPages(scaleImportData(importData, loadedPages), loadedPages));
+          if (importData.checkedState) {
+            useProofreadingCheckStore.getState().restoreCheckedState(importData.checkedState);
+          }
+        }
+
         setProgress(100);
       } catch (error) {
         console.error('[HeaderBar] Failed to open recent file:', error);
@@ -1516,21 +1539,6 @@ export const HeaderBar: React.FC = () => {
       setProgress(10);
 
       const { pages, getPageImageAsync, pdfDocument } = useDrawingStore.getState();
-      const pageDrawings = pages.map((page) => ({
-        page_number: page.pageNumber,
-        strokes: page.layers.flatMap((layer) =>
-          layer.visible
-            ? layer.strokes.map((stroke) => ({
-                points: stroke.points.map((p) => [p.x, p.y] as [number, number]),
-                color: stroke.color,
-                width: stroke.width,
-                pressure: stroke.points.map((p) => p.pressure || 0.5),
-              }))
-            : []
-        ),
-        width: page.width,
-        height: page.height,
-      }));
 
       // 背景画像を取得
       const backgroundImages: string[] = [];
@@ -1568,15 +1576,51 @@ export const HeaderBar: React.FC = () => {
         setProgress(10 + Math.floor((i / totalPages) * 40));
       }
 
+      // 描画データを保存時と同じPNGオーバーレイとしてレンダリング (50-60%)
+      setLoading(true, '描画データをレンダリング中...');
+      await preloadDrawingFonts(pages);
+
+      const pageDrawingsV2: Array<{
+        page_number: number;
+        drawing_overlay: string;
+        width: number;
+        height: number;
+      }> = [];
+
+      for (let i = 0; i < totalPages; i++) {
+        const page = pages[i];
+
+        let overlayPng = '';
+        if (hasDrawings(page)) {
+          try {
+            overlayPng = await renderPageDrawingsToCanvas(page, { hideComments: true });
+          } catch (error) {
+            console.error(`Failed to render drawings for page ${i}:`, error);
+          }
+          await new Promise(r => setTimeout(r, 0));
+        }
+
+        pageDrawingsV2.push({
+          page_number: page.pageNumber,
+          drawing_overlay: overlayPng,
+          width: page.width,
+          height: page.height,
+        });
+
+        setProgress(50 + Math.floor(((i + 1) / totalPages) * 10));
+      }
+
       setLoading(true, '印刷ダイアログを開いています...');
       setProgress(60);
 
       await invoke('print_pdf', {
         request: {
-          original_path: null,
-          pages: pageDrawings,
+          pages: pageDrawingsV2,
           background_images: backgroundImages,
         },
+        selection: null,
+        imposition: null,
+        options: null,
       });
 
       setProgress(100);
@@ -1672,7 +1716,12 @@ export const HeaderBar: React.FC = () => {
       setProgress(20);
 
       const filePath = Array.isArray(selected) ? selected[0] : selected;
-      const jsonData = await invoke<string>('load_drawing_json', { path: filePath });
+      // 現在のページサイズを渡し、座標のスケーリングは Rust 側で行う (v1.1 以降)
+      const pageSizes: Record<string, { width: number; height: number }> = {};
+      for (const page of pages) {
+        pageSizes[String(page.pageNumber)] = { width: page.width, height: page.height };
+      }
+      const jsonData = await invoke<string>('load_drawing_json', { path: filePath, pageSizes });
       setProgress(50);
 
       const importData = parseImportJson(jsonData);
@@ -1682,8 +1731,8 @@ export const HeaderBar: React.FC = () => {
         return;
       }
 
-      // ページ数チェック
-      if (importData.pageCount !== pages.length) {
+      // ページ数チェック（pageCount のない古いデータは load_drawing_json が省略して返すので確認しない）
+      if (importData.pageCount != null && importData.pageCount !== pages.length) {
         const confirmed = await showConfirm(
           `描画データのページ数（${importData.pageCount}ページ）と現在のドキュメント（${pages.length}ページ）が一致しません。\n\n続行すると、対応するページにのみ描画が適用されます。続行しますか？`,
           {
@@ -1701,11 +1750,8 @@ export const HeaderBar: React.FC = () => {
 
       setProgress(70);
 
-      // 座標スケーリング
-      const scaledData = scaleImportData(importData, pages);
-
-      // 描画データを適用
-      const newPages = applyImportDataToPages(scaledData, pages);
+      // 描画データを適用（座標は load_drawing_json でスケーリング済み）
+      const newPages = applyImportDataToPages(importData.data, pages);
       setPages(newPages);
 
       // チェック済み状態を復元（v1.2以降のデータに含まれる場合）
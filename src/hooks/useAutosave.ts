@@ -0,0 +1,86 @@
+import { useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { useDrawingStore } from '../stores/drawingStore';
+import { useDocumentStore } from '../stores/documentStore';
+import { useModalStore } from '../stores/modalStore';
+import { useProofreadingCheckStore } from '../stores/proofreadingCheckStore';
+import { prepareExportData, exportDataToJson } from '../utils/drawingExportImport';
+import type { PageState } from '../types';
+
+// 自動保存の間隔（編集があったときだけ書き出す）
+const AUTOSAVE_INTERVAL_MS = 30 * 1000;
+
+// Rust側の RecoverableSession
+interface RecoverableSession {
+  journal_id: string;
+  source_path: string;
+  saved_at: number;
+  source_exists: boolean;
+  source_changed: boolean;
+}
+
+/**
+ * クラッシュ復旧用の自動保存
+ * - 編集中の描画データを定期的にRust側のジャーナルへ送る
+ * - 起動時に残っていたジャーナルがあれば復元するか確認する
+ */
+export function useAutosave() {
+  // 定期的な自動保存
+  useEffect(() => {
+    // 読み込み直後の状態は保存しない（編集があったときだけ書き出す）
+    let baselinePath: string | null = null;
+    let lastPages: PageState[] | null = null;
+
+    const timer = setInterval(() => {
+      const { pages } = useDrawingStore.getState();
+      const sourcePath = useDocumentStore.getState().getActiveDocument()?.filePath ?? null;
+
+      if (sourcePath !== baselinePath) {
+        baselinePath = sourcePath;
+        lastPages = pages;
+        return;
+      }
+      if (!sourcePath || pages.length === 0 || pages === lastPages) return;
+      lastPages = pages;
+
+      const checkedState = useProofreadingCheckStore.getState().getCheckedState();
+      const data = exportDataToJson(prepareExportData(pages, checkedState));
+      invoke('autosave_session', { sourcePath, data }).catch((error) => {
+        console.error('Failed to autosave:', error);
+      });
+    }, AUTOSAVE_INTERVAL_MS);
+
+    return () => clearInterval(timer);
+  }, []);
+
+  // 起動時の復旧確認
+  useEffect(() => {
+    const unlisten = listen<RecoverableSession[]>('autosave-recovery-available', async (event) => {
+      // 最新のセッションのみ確認する（残りは次回起動時に確認）
+      const session = event.payload.find((s) => s.source_exists);
+      if (!session) return;
+
+      const fileName = session.source_path.split(/[/\\]/).pop() || session.source_path;
+      const savedAt = new Date(session.saved_at).toLocaleString();
+      const changedNote = session.source_changed
+        ? '\n\n※ 元ファイルは自動保存の後に変更されています。描画の位置がずれる可能性があります。'
+        : '';
+      const confirmed = await useModalStore.getState().showConfirm(
+        `前回保存されずに終了した編集があります。\n${fileName}（${savedAt}）\n\n復元しますか？${changedNote}`,
+        { title: '自動保存からの復元', kind: 'warning', okLabel: '復元', cancelLabel: '破棄' }
+      );
+
+      if (confirmed) {
+        window.dispatchEvent(new CustomEvent('mojiq-open-file', {
+          detail: { path: session.source_path, recoverJournalId: session.journal_id },
+        }));
+      } else {
+        invoke('discard_autosave_session', { journalId: session.journal_id }).catch((error) => {
+          console.error('Failed to discard autosave:', error);
+        });
+      }
+    });
+    return () => { unlisten.then(fn => fn()); };
+  }, []);
+}
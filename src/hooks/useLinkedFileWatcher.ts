@@ -0,0 +1,69 @@
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { useDrawingStore } from '../stores/drawingStore';
+import { useModalStore } from '../stores/modalStore';
+import { imageCache } from '../utils/imageCache';
+import { backgroundImageCache } from '../utils/backgroundImageCache';
+
+// Rust側の LinkedFileChange
+interface LinkedFileChange {
+  kind: 'modified' | 'deleted' | 'renamed';
+  path: string;
+  new_path: string | null;
+  modified_at: number | null;
+  width: number | null;
+  height: number | null;
+}
+
+/**
+ * リンク方式の画像の外部変更を受け取る
+ * - 上書き: キャッシュを破棄し、リンク情報（更新日時・サイズ）を更新する
+ * - 削除・リネーム: 警告を表示する
+ */
+export function useLinkedFileWatcher() {
+  useEffect(() => {
+    const unlisten = listen<LinkedFileChange>('linked-file-changed', async (event) => {
+      const change = event.payload;
+      const { pages, setPages } = useDrawingStore.getState();
+      const pageIndex = pages.findIndex((p) => p.imageLink?.type === 'file' && p.imageLink.filePath === change.path);
+      if (pageIndex < 0) return;
+
+      const fileName = change.path.split(/[/\\]/).pop() || change.path;
+
+      if (change.kind === 'modified') {
+        imageCache.clearForDocument([change.path]);
+        backgroundImageCache.delete(pageIndex);
+        setPages(pages.map((page, i) => {
+          if (i !== pageIndex || !page.imageLink) return page;
+          return {
+            ...page,
+            imageLink: {
+              ...page.imageLink,
+              modifiedAt: change.modified_at ?? page.imageLink.modifiedAt,
+              width: change.width ?? page.imageLink.width,
+              height: change.height ?? page.imageLink.height,
+            },
+          };
+        }));
+
+        const sizeChanged = change.width !== null && change.height !== null
+          && (change.width !== pages[pageIndex].width || change.height !== pages[pageIndex].height);
+        if (sizeChanged) {
+          await useModalStore.getState().showAlert(
+            `${pageIndex + 1}ページの画像（${fileName}）が外部で更新され、サイズが変わりました。\n描画の位置がずれている可能性があります。`,
+            { title: '画像の更新', kind: 'warning' }
+          );
+        }
+      } else {
+        const detail = change.kind === 'renamed' && change.new_path
+          ? `名前が変更されました（${change.new_path.split(/[/\\]/).pop()}）`
+          : '削除されました';
+        await useModalStore.getState().showAlert(
+          `${pageIndex + 1}ページの画像（${fileName}）が${detail}。\n元の画像ファイルを確認してください。`,
+          { title: 'リンク切れ', kind: 'warning' }
+        );
+      }
+    });
+    return () => { unlisten.then(fn => fn()); };
+  }, []);
+}
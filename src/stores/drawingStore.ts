@@ -1,4 +1,5 @@
 import { create } from 'zustand';
+import { invoke } from '@tauri-apps/api/core';
 import { DrawingState, PageState, Layer, Stroke, Shape, Point, ToolType, SelectionBounds, Annotation, TextElement, PdfAnnotationText, ImageElement, PdfPageInfo, HistoryState, StampType, ImageLink, FileMetadata } from '../types';
 import { renderPdfPage } from '../utils/pdfRenderer';
 import { imageCache } from '../utils/imageCache';
@@ -25,6 +26,35 @@ const deepClone = <T>(obj: T): T => {
   }
 };
 
+/**
+ * リンク方式の画像のファイルパス（重複なし）
+ */
+const linkedFilePaths = (pages: PageState[]): Set<string> => {
+  return new Set(pages.flatMap(p => p.imageLink?.type === 'file' ? [p.imageLink.filePath] : []));
+};
+
+/**
+ * 外部での上書き・削除・リネームの監視対象を、表示中のドキュメントのリンク画像に合わせる
+ * ドキュメントを閉じる・差し替える・タブを切り替えるときに、前のドキュメントの監視を解除する
+ */
+const syncLinkedFileWatch = (previous: PageState[], next: PageState[]) => {
+  const before = linkedFilePaths(previous);
+  const after = linkedFilePaths(next);
+  const removed = [...before].filter(path => !after.has(path));
+  const added = [...after].filter(path => !before.has(path));
+
+  if (removed.length > 0) {
+    invoke('unwatch_linked_files', { paths: removed }).catch((error) => {
+      console.error('Failed to unwatch linked files:', error);
+    });
+  }
+  if (added.length > 0) {
+    invoke('watch_linked_files', { paths: added }).catch((error) => {
+      console.error('Failed to watch linked files:', error);
+    });
+  }
+};
+
 /**
  * ページ内の総オブジェクト数を取得（ストローク + 図形 + テキスト + 画像）
  */
@@ -338,6 +368,7 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
     );
     const firstLayerId = pageStates[0]?.layers[0]?.id || '';
 
+    syncLinkedFileWatch(get().pages, pageStates);
     set({
       pages: pageStates,
       currentPage: 0,
@@ -366,6 +397,8 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
     });
     const firstLayerId = pageStates[0]?.layers[0]?.id || '';
 
+    // 前のドキュメントの監視を解除し、このドキュメントのリンク画像を監視する
+    syncLinkedFileWatch(get().pages, pageStates);
     set({
       pages: pageStates,
       currentPage: 0,
@@ -481,6 +514,7 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
     });
     const firstLayerId = pageStates[0]?.layers[0]?.id || '';
 
+    syncLinkedFileWatch(get().pages, pageStates);
     set({
       pages: pageStates,
       currentPage: 0,
@@ -528,6 +562,7 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
     });
     const firstLayerId = pageStates[0]?.layers[0]?.id || '';
 
+    syncLinkedFileWatch(get().pages, pageStates);
     set({
       pages: pageStates,
       currentPage: 0,
@@ -593,6 +628,8 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
 
   clearDocument: () => {
     useGridStore.getState().resetAll();
+    // 閉じたドキュメントのリンク画像の監視を解除する
+    syncLinkedFileWatch(get().pages, []);
     set({
       pages: [],
       currentPage: 0,
@@ -616,6 +653,8 @@ export const useDrawingStore = create<DrawingStore>((set, get) => ({
 
   // ドキュメント状態を復元（マルチタブ対応用）
   restoreDocumentState: (docState) => {
+    // 切り替え先のタブのリンク画像だけを監視する (イベントは表示中のドキュメントにしか反映しないため)
+    syncLinkedFileWatch(get().pages, docState.pages);
     set({
       pages: docState.pages,
       currentPage: docState.currentPage,
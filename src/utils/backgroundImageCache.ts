@@ -47,6 +47,17 @@ class BackgroundImageCacheManager {
     return true;
   }
 
+  /**
+   * 指定ページのキャッシュを削除（メモリ解放付き）
+   */
+  delete(pageNumber: number): void {
+    const img = this.cache.get(pageNumber);
+    if (img && 'close' in img && typeof img.close === 'function') {
+      img.close();
+    }
+    this.cache.delete(pageNumber);
+  }
+
   /**
    * キャッシュをクリア（メモリ解放付き）
    */
@@ -8,6 +8,9 @@
 import { PageState, Layer, Stroke, Shape, TextElement, ImageElement, Point, Annotation } from '../types';
 import type { CheckedState } from '../stores/proofreadingCheckStore';
 
+// 3-way マージ (merge_drawing_json) で競合したオブジェクトのレイヤー ID（Rust 側の CONFLICT_LAYER_ID）
+export const MERGE_CONFLICT_LAYER_ID = 'merge-conflict';
+
 // エクスポートデータ形式（ver_2.08互換）
 export interface MojiQExportData {
   version: string;  // '1.0', '1.1', '1.2' など
@@ -359,6 +362,21 @@ function unflattenToLayers(objects: ExportedObject[], existingLayers: Layer[]):
   for (const [layerId, layerObjects] of Object.entries(groupedByLayer)) {
     let targetLayer = newLayers.find((l) => l.id === layerId);
 
+    // 3-way マージで競合したオブジェクトは専用のレイヤーに分ける
+    if (!targetLayer && layerId === MERGE_CONFLICT_LAYER_ID) {
+      targetLayer = {
+        id: layerId,
+        name: 'マージの競合',
+        visible: true,
+        opacity: 1,
+        strokes: [],
+        shapes: [],
+        texts: [],
+        images: [],
+      };
+      newLayers.push(targetLayer);
+    }
+
     // レイヤーが見つからない場合は最初のレイヤーに追加
     if (!targetLayer) {
       if (newLayers.length === 0) {